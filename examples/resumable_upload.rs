@@ -0,0 +1,159 @@
+// This attribute being conditional is an implementation detail of the nickel
+// test setup for testing examples. Usually, it should just require `#[macro_use]`.
+#[cfg_attr(not(test), macro_use)]
+extern crate nickel;
+
+use std::collections::HashMap;
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use nickel::hyper::header::{self, HeaderValue};
+use nickel::status::StatusCode;
+use nickel::{ContentRange, HttpRouter, Middleware, MiddlewareResult, Nickel, Request, Response};
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+// The ranges received so far for each upload in progress, as chunks may
+// arrive out of order, e.g. after a retry
+type ReceivedRanges = HashMap<String, Vec<(u64, u64)>>;
+
+#[derive(Clone, Default)]
+struct UploadChunk {
+    received: Arc<Mutex<ReceivedRanges>>
+}
+
+impl UploadChunk {
+    // Records `range` for upload `name`, returning the end of the part
+    // received without gaps from the first byte on, if any.
+    fn record(&self, name: &str, range: ContentRange) -> Option<u64> {
+        let mut received = self.received.lock().unwrap();
+        let ranges = received.entry(name.to_string()).or_default();
+        ranges.push((range.start, range.end));
+        ranges.sort_unstable();
+
+        let mut prefix_end: Option<u64> = None;
+        for &(start, end) in ranges.iter() {
+            let next = prefix_end.map_or(0, |e| e + 1);
+            if start > next {
+                break;
+            }
+            prefix_end = Some(prefix_end.map_or(end, |e| e.max(end)));
+        }
+        prefix_end
+    }
+
+    fn finish(&self, name: &str) {
+        self.received.lock().unwrap().remove(name);
+    }
+}
+
+#[async_trait]
+impl Middleware<()> for UploadChunk {
+    async fn invoke(&self, req: &mut Request, mut res: Response) -> MiddlewareResult {
+        let range = match req.content_range() {
+            Some(range) => range,
+            None => return res.error(StatusCode::BAD_REQUEST, "Missing or invalid Content-Range")
+        };
+        // The route only matches names made of `[,a-zA-Z0-9%_-]`, so they
+        // can't escape the upload directory.
+        let name = req.param("name").unwrap().to_string();
+        let path = upload_path(&name);
+        let body = try_with!(res, req.raw_body().await);
+        if body.len() as u64 != range.size() {
+            return res.error(StatusCode::BAD_REQUEST, "Body doesn't match Content-Range");
+        }
+
+        let written = async {
+            let mut file = OpenOptions::new().create(true).write(true).truncate(false).open(&path).await?;
+            file.seek(SeekFrom::Start(range.start)).await?;
+            file.write_all(body).await
+        };
+        if let Err(e) = written.await {
+            return res.error(StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to store chunk: {}", e));
+        }
+
+        let prefix_end = self.record(&name, range);
+        if let (Some(end), Some(total)) = (prefix_end, range.total) {
+            if end + 1 == total {
+                self.finish(&name);
+                return res.send(format!("Stored {}", path.display()));
+            }
+        }
+
+        // Tell the client how far the upload got without gaps, so it can
+        // continue from there, e.g. after a dropped connection. Without a
+        // `Range` header it has to start over.
+        res.set(StatusCode::PERMANENT_REDIRECT);
+        if let Some(end) = prefix_end {
+            res.set_header(header::RANGE, HeaderValue::from_str(&format!("bytes=0-{}", end)).unwrap());
+        }
+        res.send("")
+    }
+}
+
+fn upload_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("nickel-upload-{}", name))
+}
+
+#[tokio::main]
+async fn main() {
+    let mut server = Nickel::new();
+
+    // try it with curl, sending the file in two chunks
+    // curl -X PUT 'http://localhost:6767/uploads/greeting' -H 'Content-Range: bytes 0-5/11' --data-binary 'hello '
+    // curl -X PUT 'http://localhost:6767/uploads/greeting' -H 'Content-Range: bytes 6-10/11' --data-binary 'world'
+    let uploads = UploadChunk::default();
+    server.put("/uploads/:name", uploads.clone());
+    server.patch("/uploads/:name", uploads);
+
+    server.listen("127.0.0.1:6767").await.unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use nickel::hyper::{self, header, Body, Method};
+    use nickel::status::StatusCode;
+    use nickel::{Action, HttpRouter, Middleware, ReloadPolicy, RequestBuilder, Response, Router, TemplateCache};
+    use super::{upload_path, UploadChunk};
+
+    async fn put_chunk(router: &Router, name: &str, range: &str, chunk: &'static str) -> Response {
+        let mut req = RequestBuilder::new().method(Method::PUT)
+                                           .uri(format!("/uploads/{}", name))
+                                           .header("content-range", range)
+                                           .body(chunk)
+                                           .build();
+        let res = Response::from_internal(hyper::Response::new(Body::empty()),
+                                          Arc::new(TemplateCache::with_policy(ReloadPolicy::Never)),
+                                          Arc::new(()));
+        match router.invoke(&mut req, res).await {
+            Ok(Action::Halt(res)) => res,
+            _ => panic!("expected the upload handler to respond")
+        }
+    }
+
+    #[tokio::test]
+    async fn assembles_out_of_order_chunks() {
+        let name = format!("test-{}", std::process::id());
+        let mut router = Router::new();
+        router.put("/uploads/:name", UploadChunk::default());
+
+        // the last chunk arrives before the first
+        let res = put_chunk(&router, &name, "bytes 6-10/11", "world").await;
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert!(res.headers().get(header::RANGE).is_none());
+
+        let res = put_chunk(&router, &name, "bytes 0-2/11", "hel").await;
+        assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(res.headers()[header::RANGE], "bytes=0-2");
+
+        let res = put_chunk(&router, &name, "bytes 3-5/11", "lo ").await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let path = upload_path(&name);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello world");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
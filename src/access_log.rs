@@ -0,0 +1,115 @@
+use async_trait::async_trait;
+use serde_json::json;
+
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::{Middleware, MiddlewareResult, ResponseSummary};
+
+/// The log target access log entries are written to.
+pub const ACCESS_LOG_TARGET: &str = "nickel::access";
+
+/// Logs one JSON object per request once the response has been sent, at info
+/// level to the `nickel::access` target of the `log` facade, e.g.
+///
+/// ```text
+/// {"method":"GET","path":"/users/:id","status":200,"bytes":11,"duration_ms":0.42,"client_ip":"10.0.0.7","request_id":"f81d4fae"}
+/// ```
+///
+/// The `path` is the template of the matched route rather than the request
+/// path, to keep the number of distinct values low, and is `null` for
+/// requests no route matched. The `request_id` is taken from the
+/// `X-Request-Id` header. No other headers are logged, so credentials and
+/// cookies never end up in the logs.
+///
+/// It should be added before any other middleware, so the duration covers
+/// all of them.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::{Nickel, JsonAccessLog};
+///
+/// let mut server = Nickel::new();
+/// server.utilize(JsonAccessLog);
+/// ```
+#[derive(Clone, Copy)]
+pub struct JsonAccessLog;
+
+#[async_trait]
+impl<D: Send + 'static + Sync> Middleware<D> for JsonAccessLog {
+    async fn invoke(&self, req: &mut Request<D>, res: Response<D>)
+            -> MiddlewareResult<D> {
+        let method = req.origin.method().to_string();
+        let client_ip = req.remote_addr().map(|addr| addr.ip().to_string());
+        let request_id = req.origin.headers()
+                                   .get("x-request-id")
+                                   .and_then(|v| v.to_str().ok())
+                                   .map(str::to_string);
+
+        req.on_response_sent(move |summary: &ResponseSummary| {
+            let entry = json!({
+                "method": method,
+                "path": summary.route(),
+                "status": summary.status().as_u16(),
+                "bytes": summary.bytes_out(),
+                "duration_ms": summary.elapsed().as_secs_f64() * 1000.0,
+                "client_ip": client_ip,
+                "request_id": request_id,
+            });
+            info!(target: ACCESS_LOG_TARGET, "{}", entry);
+        });
+        res.next_middleware()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use hyper::Request as HyperRequest;
+    use serde_json::Value;
+    use super::{JsonAccessLog, ACCESS_LOG_TARGET};
+    use crate::{HttpRouter, Router};
+    use crate::middleware::MiddlewareStack;
+    use crate::test_helpers;
+
+    #[tokio::test]
+    async fn logs_json_entries() {
+        test_helpers::capture_logs();
+
+        let mut stack = MiddlewareStack::new();
+        stack.add_middleware(JsonAccessLog);
+        let mut router = Router::new();
+        router.get("/users/:id", middleware!("hello world"));
+        stack.add_middleware(router);
+
+        let origin = HyperRequest::get("/users/42?expand=1")
+                         .header("x-request-id", "access-log-test")
+                         .header("authorization", "Bearer secret");
+        let req = test_helpers::request_from(origin, "10.0.0.7:4711");
+        let response = stack.invoke(req, test_helpers::response()).await;
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        for _ in 0..100 {
+            let entry = test_helpers::logs_of(ACCESS_LOG_TARGET)
+                            .into_iter()
+                            .map(|(_, message)| message)
+                            .find(|message| message.contains("access-log-test"));
+            if let Some(entry) = entry {
+                assert!(!entry.contains("secret"));
+                let mut entry: Value = serde_json::from_str(&entry).unwrap();
+                assert!(entry["duration_ms"].as_f64().unwrap() >= 0.0);
+                entry.as_object_mut().unwrap().remove("duration_ms");
+                assert_eq!(entry, serde_json::json!({
+                    "method": "GET",
+                    "path": "/users/:id",
+                    "status": 200,
+                    "bytes": 11,
+                    "client_ip": "10.0.0.7",
+                    "request_id": "access-log-test"
+                }));
+                return
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("no access log entry was written");
+    }
+}
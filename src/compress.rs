@@ -0,0 +1,272 @@
+use std::io::Write;
+use async_trait::async_trait;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use futures::{stream, Stream, StreamExt};
+use hyper::{Body, Method};
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{self, HeaderValue};
+use mime::Mime;
+
+use crate::status::StatusCode;
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::{Halt, Middleware, MiddlewareResult};
+
+const DEFAULT_MIN_SIZE: usize = 1024;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Gzip compresses the responses of the wrapped middleware for clients
+/// accepting `gzip`, setting `Content-Encoding: gzip` and dropping the
+/// `Content-Length`. The body is compressed while it is streamed, so large
+/// or streamed bodies are not buffered. A strong `ETag` is made weak, as the
+/// compressed body is a different representation.
+///
+/// Responses that are encoded already, ranged responses and content types
+/// that are compressed by themselves, like images, video and archives, are
+/// passed through as they are. So are bodies smaller than `min_size`, for
+/// which compression doesn't pay off. Compressible responses get a
+/// `Vary: Accept-Encoding` either way, so caches keep the variants apart.
+///
+/// # Examples
+/// ```{rust}
+/// #[macro_use] extern crate nickel;
+/// use nickel::{Nickel, HttpRouter, Compress};
+///
+/// fn main() {
+///     let mut server = Nickel::new();
+///     let mut router = Nickel::router();
+///     router.get("/report", middleware!("a long, repetitive report"));
+///
+///     server.utilize(Compress::new(router).min_size(512));
+/// }
+/// ```
+pub struct Compress<M> {
+    middleware: M,
+    min_size: usize,
+}
+
+impl<M> Compress<M> {
+    /// Compresses the responses of `middleware`.
+    pub fn new(middleware: M) -> Compress<M> {
+        Compress {
+            middleware,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+
+    /// The smallest body in bytes that gets compressed.
+    ///
+    /// Defaults to 1 KiB.
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+}
+
+#[async_trait]
+impl<D: Send + 'static + Sync, M: Middleware<D>> Middleware<D> for Compress<M> {
+    async fn invoke(&self, req: &mut Request<D>, res: Response<D>)
+            -> MiddlewareResult<D> {
+        let mut res = match self.middleware.invoke(req, res).await {
+            Ok(Halt(res)) => res,
+            result => return result
+        };
+        let compressible = req.origin.method() != Method::HEAD
+                           && !matches!(res.status(), StatusCode::NO_CONTENT
+                                                      | StatusCode::NOT_MODIFIED
+                                                      | StatusCode::PARTIAL_CONTENT)
+                           && !res.headers().contains_key(header::CONTENT_ENCODING)
+                           && is_compressible(res.headers().get(header::CONTENT_TYPE));
+        if !compressible {
+            return Ok(Halt(res));
+        }
+        res.add_vary(&header::ACCEPT_ENCODING);
+        if !req.accepts_encoding("gzip") {
+            return Ok(Halt(res));
+        }
+
+        let length = res.headers()
+                        .get(header::CONTENT_LENGTH)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse::<usize>().ok());
+        if matches!(length, Some(length) if length < self.min_size) {
+            return Ok(Halt(res));
+        }
+
+        // Without a length, read far enough to know whether the body is
+        // large enough
+        let mut body = std::mem::replace(res.origin.body_mut(), Body::empty());
+        let mut chunks: Vec<Result<Bytes, BoxError>> = Vec::new();
+        let mut size = 0;
+        let mut complete = false;
+        while length.is_none() && size < self.min_size {
+            match body.data().await {
+                Some(Ok(chunk)) => {
+                    size += chunk.len();
+                    chunks.push(Ok(chunk));
+                },
+                Some(Err(e)) => {
+                    chunks.push(Err(e.into()));
+                    break;
+                },
+                None => {
+                    complete = true;
+                    break;
+                }
+            }
+        }
+        if complete && size < self.min_size {
+            res.set_body(chunks.into_iter().filter_map(Result::ok).collect::<Vec<_>>().concat());
+            return Ok(Halt(res));
+        }
+
+        let rest = body.map(|chunk| chunk.map_err(BoxError::from));
+        res.origin.headers_mut().remove(header::CONTENT_LENGTH);
+        res.set_header(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        weaken_etag(&mut res);
+        res.set_body(Body::wrap_stream(gzip(stream::iter(chunks).chain(rest))));
+        Ok(Halt(res))
+    }
+}
+
+// The compressed body is another representation, which must not share the
+// strong validator of the original. A weak one still matches it in
+// `If-None-Match`, so conditional requests keep working.
+fn weaken_etag<D: Send + 'static + Sync>(res: &mut Response<D>) {
+    let weakened = match res.headers().get(header::ETAG).and_then(|v| v.to_str().ok()) {
+        Some(etag) if !etag.starts_with("W/") => format!("W/{}", etag),
+        _ => return
+    };
+    if let Ok(value) = HeaderValue::from_str(&weakened) {
+        res.set_header(header::ETAG, value);
+    }
+}
+
+// Whether compressing a body of `content_type` is worth it. Bodies without
+// a type fall back to `text/html`.
+fn is_compressible(content_type: Option<&HeaderValue>) -> bool {
+    let mime = match content_type.and_then(|v| v.to_str().ok()) {
+        Some(value) => match value.parse::<Mime>() {
+            Ok(mime) => mime,
+            Err(_) => return false
+        },
+        None => return true
+    };
+    match (mime.type_(), mime.subtype().as_str()) {
+        (mime::IMAGE, subtype) => subtype == "svg",
+        (mime::VIDEO, _) | (mime::AUDIO, _) | (mime::FONT, "woff") | (mime::FONT, "woff2") => false,
+        (mime::APPLICATION, subtype) => !matches!(subtype, "zip" | "gzip" | "x-gzip" | "x-bzip2" | "x-xz"
+                                                           | "x-7z-compressed" | "x-rar-compressed"
+                                                           | "octet-stream" | "pdf" | "wasm"),
+        _ => true
+    }
+}
+
+// Compresses `body` chunk by chunk, flushing the encoder after each chunk so
+// streamed responses reach the client as they are produced.
+fn gzip<S>(body: S) -> impl Stream<Item=Result<Bytes, BoxError>>
+where S: Stream<Item=Result<Bytes, BoxError>> + Send + Unpin {
+    let encoder = GzEncoder::new(Vec::new(), Compression::default());
+    stream::unfold((body, Some(encoder)), |(mut body, encoder)| async move {
+        let mut encoder = encoder?;
+        loop {
+            let written = match body.next().await {
+                Some(Ok(chunk)) => encoder.write_all(&chunk).and_then(|_| encoder.flush()),
+                Some(Err(e)) => return Some((Err(e), (body, None))),
+                None => {
+                    let last = encoder.finish().map(Bytes::from).map_err(BoxError::from);
+                    return Some((last, (body, None)));
+                }
+            };
+            if let Err(e) = written {
+                return Some((Err(e.into()), (body, None)));
+            }
+            let compressed = std::mem::take(encoder.get_mut());
+            if !compressed.is_empty() {
+                return Some((Ok(Bytes::from(compressed)), (body, Some(encoder))));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use flate2::read::GzDecoder;
+    use hyper::{body, header, Request as HyperRequest};
+    use super::Compress;
+    use crate::{HttpRouter, MediaType, Middleware, Router};
+    use crate::test_helpers::{self, body_string, halted, response};
+
+    const REPORT: &str = "quarterly report, quarterly report, quarterly report, quarterly report";
+
+    fn compressed_router() -> Compress<Router<()>> {
+        let mut router = Router::new();
+        router.get("/report", middleware!(REPORT));
+        router.get("/tagged", middleware! { |_, mut res|
+            res.set_header(header::ETAG, header::HeaderValue::from_static("\"v1\""));
+            REPORT
+        });
+        router.get("/logo", middleware! { |_, mut res|
+            res.set(MediaType::Png);
+            REPORT
+        });
+        Compress::new(router).min_size(16)
+    }
+
+    fn gzip_request(path: &str) -> crate::Request<()> {
+        test_helpers::request(HyperRequest::get(path).header("accept-encoding", "gzip, deflate"))
+    }
+
+    #[tokio::test]
+    async fn compresses_for_clients_accepting_gzip() {
+        let compress = compressed_router();
+        let mut req = gzip_request("/report");
+        let res = halted(compress.invoke(&mut req, response()).await);
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(res.headers()[header::VARY], "accept-encoding");
+        assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+
+        let compressed = body::to_bytes(res.origin.into_body()).await.unwrap();
+        let mut decompressed = String::new();
+        GzDecoder::new(&compressed[..]).read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, REPORT);
+
+        let mut req = test_helpers::get("/report");
+        let res = halted(compress.invoke(&mut req, response()).await);
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(res.headers()[header::VARY], "accept-encoding");
+        assert_eq!(body_string(res).await, REPORT);
+    }
+
+    #[tokio::test]
+    async fn compressed_responses_get_weak_etags() {
+        let compress = compressed_router();
+        let mut req = gzip_request("/tagged");
+        let res = halted(compress.invoke(&mut req, response()).await);
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(res.headers()[header::ETAG], "W/\"v1\"");
+
+        let mut req = test_helpers::get("/tagged");
+        let res = halted(compress.invoke(&mut req, response()).await);
+        assert_eq!(res.headers()[header::ETAG], "\"v1\"");
+    }
+
+    #[tokio::test]
+    async fn skips_small_and_compressed_bodies() {
+        let compress = compressed_router().min_size(1024);
+        let mut req = gzip_request("/report");
+        let res = halted(compress.invoke(&mut req, response()).await);
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        assert_eq!(body_string(res).await, REPORT);
+
+        let compress = compressed_router();
+        let mut req = gzip_request("/logo");
+        let res = halted(compress.invoke(&mut req, response()).await);
+        assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+        assert!(res.headers().get(header::VARY).is_none());
+        assert_eq!(body_string(res).await, REPORT);
+    }
+}
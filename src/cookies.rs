@@ -0,0 +1,363 @@
+use std::error::Error;
+use std::fmt;
+use std::slice;
+use std::time::Duration;
+use hyper::header::{self, HeaderValue};
+
+use crate::request::Request;
+use crate::response::Response;
+use crate::util;
+
+/// The plain cookies sent with a request, parsed from its `Cookie` headers.
+/// Obtained from `Request::cookies`.
+///
+/// Values are returned as sent, without any decoding or signature checks.
+pub struct Cookies<'a> {
+    pairs: Vec<(&'a str, &'a str)>,
+}
+
+impl<'a> Cookies<'a> {
+    fn parse<I: Iterator<Item=&'a str>>(headers: I) -> Cookies<'a> {
+        let pairs = headers.flat_map(|header| header.split(';'))
+                           .filter_map(|pair| {
+                               let mut parts = pair.splitn(2, '=');
+                               let name = parts.next()?.trim();
+                               let value = parts.next()?.trim();
+                               if name.is_empty() {
+                                   return None;
+                               }
+                               Some((name, unquote(value)))
+                           })
+                           .collect();
+        Cookies { pairs }
+    }
+
+    /// The value of the first cookie called `name`.
+    pub fn find(&self, name: &str) -> Option<&'a str> {
+        self.pairs.iter().find(|&&(n, _)| n == name).map(|&(_, v)| v)
+    }
+
+    /// All cookies as name/value pairs, in the order they were sent.
+    pub fn iter(&self) -> Iter<'_, 'a> {
+        Iter(self.pairs.iter())
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// Iterator over the cookies of a request, see `Cookies::iter`.
+pub struct Iter<'c, 'a>(slice::Iter<'c, (&'a str, &'a str)>);
+
+impl<'c, 'a> Iterator for Iter<'c, 'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().copied()
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+impl<D> Request<D> {
+    /// The cookies sent with the request.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// #[macro_use] extern crate nickel;
+    /// use nickel::{Nickel, HttpRouter};
+    ///
+    /// fn main() {
+    ///     let mut server = Nickel::new();
+    ///     server.get("/", middleware! { |req|
+    ///         let cookies = req.cookies();
+    ///         match cookies.find("session") {
+    ///             Some(session) => format!("Welcome back, session {}", session),
+    ///             None => format!("Hello stranger, you sent {} cookies", cookies.len())
+    ///         }
+    ///     });
+    /// }
+    /// ```
+    pub fn cookies(&self) -> Cookies<'_> {
+        Cookies::parse(self.origin.headers()
+                                  .get_all(header::COOKIE)
+                                  .iter()
+                                  .filter_map(|v| v.to_str().ok()))
+    }
+}
+
+/// A cookie to send with a response, see `Response::set_cookie`.
+///
+/// # Examples
+/// ```{rust}
+/// use std::time::Duration;
+/// use nickel::cookies::Cookie;
+///
+/// let session = Cookie::new("session", "abc123")
+///                   .path("/")
+///                   .max_age(Duration::from_secs(3600))
+///                   .secure(true)
+///                   .http_only(true);
+/// ```
+#[derive(Clone, Debug)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    secure: bool,
+    http_only: bool,
+}
+
+impl Cookie {
+    /// A session cookie, kept until the browser is closed.
+    pub fn new<N: Into<String>, V: Into<String>>(name: N, value: V) -> Cookie {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            secure: false,
+            http_only: false,
+        }
+    }
+
+    /// Restricts the cookie to paths below `path`.
+    pub fn path<S: Into<String>>(mut self, path: S) -> Cookie {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Sends the cookie to `domain` and its subdomains as well.
+    pub fn domain<S: Into<String>>(mut self, domain: S) -> Cookie {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// How long the browser keeps the cookie, instead of until it is closed.
+    pub fn max_age(mut self, max_age: Duration) -> Cookie {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Only send the cookie over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Cookie {
+        self.secure = secure;
+        self
+    }
+
+    /// Hide the cookie from scripts.
+    pub fn http_only(mut self, http_only: bool) -> Cookie {
+        self.http_only = http_only;
+        self
+    }
+
+    fn to_header(&self) -> Result<HeaderValue, InvalidCookie> {
+        if !util::is_token(&self.name) {
+            return Err(InvalidCookie(format!("invalid name '{}'", self.name.escape_debug())));
+        }
+        let value = match self.value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(quoted) => quoted,
+            None => &self.value[..]
+        };
+        if !value.bytes().all(is_cookie_octet) {
+            return Err(InvalidCookie(format!("invalid value for '{}'", self.name)));
+        }
+        for (attribute, value) in &[("Path", &self.path), ("Domain", &self.domain)] {
+            if let Some(value) = value {
+                if !value.bytes().all(|b| (0x20..0x7f).contains(&b) && b != b';') {
+                    return Err(InvalidCookie(format!("invalid {} for '{}'", attribute, self.name)));
+                }
+            }
+        }
+
+        let mut cookie = format!("{}={}", self.name, self.value);
+        if let Some(ref path) = self.path {
+            cookie.push_str(&format!("; Path={}", path));
+        }
+        if let Some(ref domain) = self.domain {
+            cookie.push_str(&format!("; Domain={}", domain));
+        }
+        if let Some(max_age) = self.max_age {
+            cookie.push_str(&format!("; Max-Age={}", max_age.as_secs()));
+            if max_age.as_secs() == 0 {
+                // for clients predating Max-Age
+                cookie.push_str("; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+            }
+        }
+        if self.secure {
+            cookie.push_str("; Secure");
+        }
+        if self.http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        HeaderValue::from_str(&cookie).map_err(|e| InvalidCookie(e.to_string()))
+    }
+}
+
+// A `cookie-octet` of RFC 6265: visible ASCII but `"`, `,`, `;` and `\`
+fn is_cookie_octet(b: u8) -> bool {
+    b.is_ascii_graphic() && !b"\",;\\".contains(&b)
+}
+
+/// A cookie that can't be sent, as its name, value, path or domain contains
+/// characters a `Set-Cookie` header doesn't allow there, which could
+/// otherwise inject attributes like `; Domain=evil.com`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidCookie(String);
+
+impl fmt::Display for InvalidCookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid cookie: {}", self.0)
+    }
+}
+
+impl Error for InvalidCookie {}
+
+impl<D: Send + 'static + Sync> Response<D> {
+    /// Sends `cookie` with a `Set-Cookie` header, in addition to any cookies
+    /// set before.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    /// use nickel::cookies::Cookie;
+    ///
+    /// # #[allow(dead_code)]
+    /// fn login<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.set_cookie(Cookie::new("session", "abc123").path("/").http_only(true)).unwrap();
+    ///     res.send("Welcome back")
+    /// }
+    /// ```
+    pub fn set_cookie(&mut self, cookie: Cookie) -> Result<(), InvalidCookie> {
+        let value = cookie.to_header()?;
+        self.append_header(header::SET_COOKIE, value);
+        Ok(())
+    }
+
+    /// Sends each of `cookies` with its own `Set-Cookie` header. Nothing is
+    /// sent if any of them is invalid.
+    pub fn set_cookies<I>(&mut self, cookies: I) -> Result<(), InvalidCookie>
+    where I: IntoIterator<Item=Cookie> {
+        let values = cookies.into_iter()
+                            .map(|cookie| cookie.to_header())
+                            .collect::<Result<Vec<_>, _>>()?;
+        for value in values {
+            self.append_header(header::SET_COOKIE, value);
+        }
+        Ok(())
+    }
+
+    /// Tells the browser to delete `cookie`, e.g. the session cookie on
+    /// logout, by sending it expired and without a value. Its path and domain
+    /// have to match the ones it was set with.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    /// use nickel::cookies::Cookie;
+    ///
+    /// # #[allow(dead_code)]
+    /// fn logout<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.remove_cookie(Cookie::new("session", "").path("/")).unwrap();
+    ///     res.send("Bye")
+    /// }
+    /// ```
+    pub fn remove_cookie(&mut self, cookie: Cookie) -> Result<(), InvalidCookie> {
+        let mut expired = cookie.max_age(Duration::from_secs(0));
+        expired.value.clear();
+        self.set_cookie(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use hyper::Request as HyperRequest;
+    use hyper::header;
+    use super::Cookie;
+    use crate::test_helpers;
+
+    #[test]
+    fn iterates_all_cookies() {
+        let req = test_helpers::request(HyperRequest::get("/")
+                                            .header("cookie", "session=abc123; theme=dark")
+                                            .header("cookie", "lang=\"en-US\";tracking=;=broken"));
+        let cookies = req.cookies();
+
+        assert_eq!(cookies.iter().collect::<Vec<_>>(),
+                   vec![("session", "abc123"), ("theme", "dark"), ("lang", "en-US"), ("tracking", "")]);
+        assert_eq!(cookies.len(), 4);
+        assert_eq!(cookies.find("theme"), Some("dark"));
+        assert_eq!(cookies.find("missing"), None);
+    }
+
+    #[test]
+    fn no_cookies() {
+        let req = test_helpers::get("/");
+        assert!(req.cookies().is_empty());
+        assert_eq!(req.cookies().iter().next(), None);
+    }
+
+    #[test]
+    fn sets_multiple_cookies() {
+        let mut res = test_helpers::response();
+        res.set_cookie(Cookie::new("theme", "dark")).unwrap();
+        res.set_cookies(vec![
+            Cookie::new("session", "abc123").path("/").max_age(Duration::from_secs(3600)).secure(true).http_only(true),
+            Cookie::new("lang", "en").domain("example.com"),
+        ]).unwrap();
+
+        let cookies: Vec<_> = res.headers().get_all(header::SET_COOKIE).iter().collect();
+        assert_eq!(cookies, vec!["theme=dark",
+                                 "session=abc123; Path=/; Max-Age=3600; Secure; HttpOnly",
+                                 "lang=en; Domain=example.com"]);
+
+        assert!(res.set_cookies(vec![Cookie::new("ok", "1"), Cookie::new("bad", "line\nbreak")]).is_err());
+        assert_eq!(res.headers().get_all(header::SET_COOKIE).iter().count(), 3);
+    }
+
+    #[test]
+    fn rejects_cookies_injecting_attributes() {
+        let invalid = |cookie: Cookie| test_helpers::response().set_cookie(cookie).is_err();
+
+        assert!(invalid(Cookie::new("session", "x; Domain=evil.com")));
+        assert!(invalid(Cookie::new("session", "a,b")));
+        assert!(invalid(Cookie::new("session", "a b")));
+        assert!(invalid(Cookie::new("session", "tab\there")));
+        assert!(invalid(Cookie::new("session", "bell\u{7}")));
+        assert!(invalid(Cookie::new("session", "caf\u{e9}")));
+        assert!(invalid(Cookie::new("ses;sion", "x")));
+        assert!(invalid(Cookie::new("ses sion", "x")));
+        assert!(invalid(Cookie::new("ses,sion", "x")));
+        assert!(invalid(Cookie::new("ses\u{1}sion", "x")));
+        assert!(invalid(Cookie::new("", "x")));
+        assert!(invalid(Cookie::new("session", "x").path("/; Domain=evil.com")));
+
+        assert!(!invalid(Cookie::new("session", "\"quoted=\"")));
+        assert!(!invalid(Cookie::new("session", "")));
+        assert!(!invalid(Cookie::new("__Host-id", "a1!#$%&'()*+-./:<=>?@[]^_`{|}~")));
+    }
+
+    #[test]
+    fn removed_cookies_expire() {
+        let mut res = test_helpers::response();
+        res.remove_cookie(Cookie::new("session", "abc123").path("/app").domain("example.com")).unwrap();
+
+        assert_eq!(res.headers()[header::SET_COOKIE],
+                   "session=; Path=/app; Domain=example.com; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+}
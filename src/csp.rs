@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use hyper::header::{self, HeaderValue};
+use rand::RngCore;
+use rand::rngs::OsRng;
+use typemap::Key;
+
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::{Middleware, MiddlewareResult};
+
+/// Placeholder in a policy that gets replaced with the per-request nonce.
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// The template variable holding the nonce, see `Response::render`.
+#[cfg(feature = "templates")]
+pub const CSP_NONCE_VARIABLE: &str = "csp_nonce";
+
+struct CspNonce;
+
+impl Key for CspNonce {
+    type Value = String;
+}
+
+/// Sets a `Content-Security-Policy` (or `Content-Security-Policy-Report-Only`)
+/// header and generates a fresh random nonce for every request.
+///
+/// Every occurrence of `{nonce}` in the policy is replaced with the nonce. The
+/// same nonce is available to handlers through `Request::csp_nonce`, and to
+/// templates rendered with the response as `{{csp_nonce}}`, for inline
+/// `<script nonce="{{csp_nonce}}">` tags.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::{Nickel, Csp};
+/// let mut server = Nickel::new();
+///
+/// server.utilize(Csp::new("script-src 'self' 'nonce-{nonce}'").report_only(true));
+/// ```
+#[derive(Clone)]
+pub struct Csp {
+    policy: String,
+    report_only: bool,
+}
+
+impl Csp {
+    /// Create a new middleware enforcing `policy`.
+    pub fn new<S: Into<String>>(policy: S) -> Csp {
+        Csp {
+            policy: policy.into(),
+            report_only: false
+        }
+    }
+
+    /// Only report violations instead of enforcing the policy. Useful while
+    /// rolling out a new policy.
+    ///
+    /// Defaults to `false`.
+    pub fn report_only(mut self, report_only: bool) -> Self {
+        self.report_only = report_only;
+        self
+    }
+}
+
+#[async_trait]
+impl<D: Send + 'static + Sync> Middleware<D> for Csp {
+    async fn invoke(&self, req: &mut Request<D>, mut res: Response<D>)
+            -> MiddlewareResult<D> {
+        let nonce = generate_nonce();
+        let policy = self.policy.replace(NONCE_PLACEHOLDER, &nonce);
+
+        let value = match HeaderValue::from_str(&policy) {
+            Ok(v) => v,
+            Err(e) => return res.error(hyper::StatusCode::INTERNAL_SERVER_ERROR,
+                                       format!("Invalid Content-Security-Policy: {}", e))
+        };
+        let name = if self.report_only {
+            header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            header::CONTENT_SECURITY_POLICY
+        };
+        res.set_header(name, value);
+
+        #[cfg(feature = "templates")]
+        {
+            let mut context = std::collections::HashMap::new();
+            context.insert(CSP_NONCE_VARIABLE, &nonce);
+            if let Err(e) = res.add_template_context(&context) {
+                return res.error(hyper::StatusCode::INTERNAL_SERVER_ERROR, e);
+            }
+        }
+        req.extensions_mut().insert::<CspNonce>(nonce);
+        res.next_middleware()
+    }
+}
+
+impl<D> Request<D> {
+    /// The nonce generated by the `Csp` middleware for this request, if it
+    /// has been run.
+    pub fn csp_nonce(&self) -> Option<&str> {
+        self.extensions().get::<CspNonce>().map(|n| &n[..])
+    }
+}
+
+// 128 bits from the OS CSPRNG, hex encoded (a subset of the base64 charset
+// the CSP grammar allows for nonces).
+fn generate_nonce() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::header;
+    use super::{generate_nonce, Csp};
+    use crate::{Action, Middleware, Request, Response};
+    use crate::test_helpers::{get, response};
+
+    async fn run(csp: Csp) -> (Request<()>, Response<()>) {
+        let mut req = get("/");
+        match csp.invoke(&mut req, response()).await {
+            Ok(Action::Continue(res)) => (req, res),
+            _ => panic!("Csp should continue")
+        }
+    }
+
+    #[tokio::test]
+    async fn header_contains_request_nonce() {
+        let (req, res) = run(Csp::new("script-src 'nonce-{nonce}'")).await;
+
+        let nonce = req.csp_nonce().unwrap();
+        assert_eq!(nonce.len(), 32);
+        assert_eq!(res.headers().get(header::CONTENT_SECURITY_POLICY).unwrap(),
+                   &format!("script-src 'nonce-{}'", nonce));
+    }
+
+    #[tokio::test]
+    async fn report_only_header() {
+        let (req, res) = run(Csp::new("default-src 'self'; script-src 'nonce-{nonce}'").report_only(true)).await;
+
+        assert!(res.headers().get(header::CONTENT_SECURITY_POLICY).is_none());
+        let value = res.headers().get(header::CONTENT_SECURITY_POLICY_REPORT_ONLY).unwrap();
+        assert!(value.to_str().unwrap().contains(req.csp_nonce().unwrap()));
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn templates_get_the_header_nonce() {
+        use std::fs;
+        use crate::test_helpers::{body_string, halted};
+
+        let dir = std::env::temp_dir().join(format!("nickel-csp-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("page.tpl");
+        fs::write(&template, "<script nonce=\"{{csp_nonce}}\"></script>").unwrap();
+
+        let (_, res) = run(Csp::new("script-src 'nonce-{nonce}'")).await;
+        let header = res.headers()[header::CONTENT_SECURITY_POLICY].to_str().unwrap().to_string();
+        let data: std::collections::HashMap<&str, &str> = std::collections::HashMap::new();
+        let res = halted(res.render(template.to_str().unwrap().to_string(), &data).await);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let body = body_string(res).await;
+        let nonce = body.trim_start_matches("<script nonce=\"").trim_end_matches("\"></script>");
+        assert_eq!(nonce.len(), 32);
+        assert_eq!(header, format!("script-src 'nonce-{}'", nonce));
+    }
+
+    #[test]
+    fn nonces_are_unique() {
+        assert_ne!(generate_nonce(), generate_nonce());
+    }
+}
@@ -7,12 +7,18 @@ use crate::nickel_error::NickelError;
 pub struct DefaultErrorHandler;
 
 impl<D: Send + 'static + Sync> ErrorHandler<D> for DefaultErrorHandler {
-    fn handle_error(&self, err: &mut NickelError<D>, _req: &mut Request<D>) -> Action {
+    fn handle_error(&self, err: &mut NickelError<D>, req: &mut Request<D>) -> Action {
         if let Some(ref mut res) = err.stream {
-            let msg : &[u8] = match res.status() {
-                StatusCode::NOT_FOUND => b"Not Found",
-                StatusCode::BAD_REQUEST => b"Bad Request",
-                _ => b"Internal Server Error"
+            let msg = match res.status() {
+                // The path is routed, just not for this method
+                StatusCode::NOT_FOUND if !req.allowed_methods().is_empty() => {
+                    let allowed = req.allowed_methods().iter().map(|m| m.as_str()).collect::<Vec<_>>();
+                    format!("Not Found: {} {} isn't routed, use one of {}",
+                            req.origin.method(), req.path_without_query(), allowed.join(", "))
+                },
+                StatusCode::NOT_FOUND => "Not Found".to_string(),
+                StatusCode::BAD_REQUEST => "Bad Request".to_string(),
+                _ => "Internal Server Error".to_string()
             };
 
             let _ = res.set_body(msg);
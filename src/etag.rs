@@ -0,0 +1,170 @@
+use async_trait::async_trait;
+use futures::{future, stream, StreamExt};
+use hyper::{Body, Method};
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{self, HeaderValue};
+
+use crate::status::StatusCode;
+use crate::request::Request;
+use crate::response::{self, Response};
+use crate::middleware::{Halt, Middleware, MiddlewareResult};
+use crate::util::fnv1a;
+
+// 1 MiB
+const DEFAULT_MAX_BUFFER: usize = 1024 * 1024;
+
+/// Adds a strong `ETag` to the `200 OK` responses of the wrapped middleware
+/// for `GET` requests, computed from the body, and answers requests whose
+/// `If-None-Match` matches it with `304 Not Modified`. Dynamic pages can so
+/// be revalidated by clients without the handler keeping track of versions,
+/// though the page is still generated for each request.
+///
+/// The body is buffered to compute the tag. Bodies larger than the buffer
+/// limit are passed through as they are, without an `ETag`. Responses that
+/// already have an `ETag` are left alone.
+///
+/// # Examples
+/// ```{rust}
+/// #[macro_use] extern crate nickel;
+/// use nickel::{Nickel, HttpRouter, ETag};
+///
+/// fn main() {
+///     let mut server = Nickel::new();
+///     let mut router = Nickel::router();
+///     router.get("/report", middleware!("expensive report"));
+///
+///     server.utilize(ETag::new(router).max_buffer(64 * 1024));
+/// }
+/// ```
+pub struct ETag<M> {
+    middleware: M,
+    max_buffer: usize,
+}
+
+impl<M> ETag<M> {
+    /// Tags the responses of `middleware`.
+    pub fn new(middleware: M) -> ETag<M> {
+        ETag {
+            middleware,
+            max_buffer: DEFAULT_MAX_BUFFER,
+        }
+    }
+
+    /// The largest body in bytes that is buffered to compute an `ETag`.
+    ///
+    /// Defaults to 1 MiB.
+    pub fn max_buffer(mut self, bytes: usize) -> Self {
+        self.max_buffer = bytes;
+        self
+    }
+}
+
+#[async_trait]
+impl<D: Send + 'static + Sync, M: Middleware<D>> Middleware<D> for ETag<M> {
+    async fn invoke(&self, req: &mut Request<D>, res: Response<D>)
+            -> MiddlewareResult<D> {
+        let mut res = match self.middleware.invoke(req, res).await {
+            Ok(Halt(res)) => res,
+            result => return result
+        };
+        let taggable = req.origin.method() == Method::GET
+                       && res.status() == StatusCode::OK
+                       && !res.headers().contains_key(header::ETAG);
+        if !taggable {
+            return Ok(Halt(res));
+        }
+
+        let mut body = std::mem::replace(res.origin.body_mut(), Body::empty());
+        let mut chunks: Vec<Bytes> = Vec::new();
+        let mut size = 0;
+        loop {
+            match body.data().await {
+                Some(Ok(chunk)) => {
+                    size += chunk.len();
+                    chunks.push(chunk);
+                    if size > self.max_buffer {
+                        let read = stream::iter(chunks.into_iter().map(Ok));
+                        res.set_body(Body::wrap_stream(read.chain(body)));
+                        return Ok(Halt(res));
+                    }
+                },
+                Some(Err(e)) => {
+                    // pass the failure on to the client
+                    let read = stream::iter(chunks.into_iter().map(Ok));
+                    let failed = stream::once(future::ready(Err(e)));
+                    res.set_body(Body::wrap_stream(read.chain(failed).chain(body)));
+                    return Ok(Halt(res));
+                },
+                None => break
+            }
+        }
+
+        let bytes = chunks.concat();
+        let etag = format!("\"{:016x}\"", fnv1a(&bytes));
+        res.set_header(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+
+        let fresh = req.origin.headers()
+                              .get_all(header::IF_NONE_MATCH)
+                              .iter()
+                              .any(|v| response::etag_matches(v, &etag));
+        if fresh {
+            res.set(StatusCode::NOT_MODIFIED);
+        } else {
+            res.set_body(bytes);
+        }
+        Ok(Halt(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{header, Request as HyperRequest, StatusCode};
+    use super::ETag;
+    use crate::{HttpRouter, Middleware, Router};
+    use crate::test_helpers::{self, body_string, halted, response};
+
+    fn tagged_router() -> ETag<Router<()>> {
+        let mut router = Router::new();
+        router.get("/report", middleware!("quarterly report"));
+        router.post("/report", middleware!("saved"));
+        ETag::new(router)
+    }
+
+    #[tokio::test]
+    async fn conditional_requests_are_not_modified() {
+        let etag = tagged_router();
+
+        let mut req = test_helpers::get("/report");
+        let res = halted(etag.invoke(&mut req, response()).await);
+        assert_eq!(res.status(), StatusCode::OK);
+        let tag = res.headers()[header::ETAG].to_str().unwrap().to_string();
+        // the same everywhere, for every build
+        assert_eq!(tag, "\"29701d41ccd6a852\"");
+        assert_eq!(body_string(res).await, "quarterly report");
+
+        let mut req = test_helpers::request(HyperRequest::get("/report").header("if-none-match", tag.as_str()));
+        let res = halted(etag.invoke(&mut req, response()).await);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], tag.as_str());
+
+        let mut req = test_helpers::request(HyperRequest::get("/report").header("if-none-match", "\"outdated\""));
+        let res = halted(etag.invoke(&mut req, response()).await);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body_string(res).await, "quarterly report");
+    }
+
+    #[tokio::test]
+    async fn large_bodies_and_other_methods_pass_through() {
+        let etag = tagged_router().max_buffer(8);
+        let mut req = test_helpers::get("/report");
+        let res = halted(etag.invoke(&mut req, response()).await);
+        assert!(res.headers().get(header::ETAG).is_none());
+        assert_eq!(body_string(res).await, "quarterly report");
+
+        let etag = tagged_router();
+        let mut req = test_helpers::request(HyperRequest::post("/report"));
+        let res = halted(etag.invoke(&mut req, response()).await);
+        assert!(res.headers().get(header::ETAG).is_none());
+        assert_eq!(body_string(res).await, "saved");
+    }
+}
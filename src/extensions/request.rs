@@ -15,9 +15,9 @@ impl<D> Referer for Request<D> {
     /// use nickel::{Nickel, HttpRouter, Request, Response, MiddlewareResult};
     /// use nickel::extensions::{Referer, Redirect};
     ///
-    /// fn referer<'mw, 'conn>(req: &mut Request<'mw, 'conn>, res: Response<'mw>) -> MiddlewareResult<'mw> {
+    /// fn referer<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
     ///     let back = req.referer().unwrap_or("http://nickel-org.github.io/");
-    ///     return res.redirect(back)
+    ///     res.redirect(back)
     /// }
     ///
     /// fn main() {
@@ -5,7 +5,10 @@ use crate::status::StatusCode;
 pub trait Redirect: Sized {
     type Result;
 
-    /// Redirect the response to a given target
+    /// Redirect the response to a given target with a `302 Found`.
+    ///
+    /// The `Location` header is set to `target` and the response is sent
+    /// with an empty body, so no further middleware runs.
     ///
     /// # Examples
     /// ```{rust}
@@ -14,8 +17,8 @@ pub trait Redirect: Sized {
     /// use nickel::{Nickel, HttpRouter, Request, Response, MiddlewareResult};
     /// use nickel::extensions::Redirect;
     ///
-    /// fn redirect<'mw, 'conn>(_: &mut Request<'mw, 'conn>, res: Response<'mw>) -> MiddlewareResult<'mw> {
-    ///     return res.redirect("http://nickel.rs")
+    /// fn redirect<D: Send + 'static + Sync>(_: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.redirect("http://nickel.rs")
     /// }
     ///
     /// fn main() {
@@ -28,11 +31,14 @@ pub trait Redirect: Sized {
         self.redirect_with(target, StatusCode::FOUND)
     }
 
+    /// Like `redirect`, but with a `301 Moved Permanently`.
     fn redirect_permanently<T>(self, target: T) -> Self::Result
     where T: Into<String> {
         self.redirect_with(target, StatusCode::MOVED_PERMANENTLY)
     }
 
+    /// Like `redirect`, but with `status`, which has to be a 3xx status,
+    /// e.g. `307 Temporary Redirect` to keep the method of the request.
     fn redirect_with<T>(self, target: T, status: StatusCode) -> Self::Result
     where T: Into<String>;
 }
@@ -60,3 +66,26 @@ impl<D: Send + 'static + Sync> Redirect for Response<D> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use hyper::header;
+    use super::Redirect;
+    use crate::status::StatusCode;
+    use crate::test_helpers::{body_string, errored, halted, response};
+
+    #[tokio::test]
+    async fn redirects_with_location() {
+        let res = halted(response().redirect("/login"));
+        assert_eq!(res.status(), StatusCode::FOUND);
+        assert_eq!(res.headers()[header::LOCATION], "/login");
+        assert_eq!(body_string(res).await, "");
+
+        let res = halted(response().redirect_permanently("https://example.com/"));
+        assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(res.headers()[header::LOCATION], "https://example.com/");
+
+        let res = errored(response().redirect_with("/login", StatusCode::OK));
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,108 @@
+use async_trait::async_trait;
+use hyper::header::{self, HeaderMap, HeaderName};
+
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::{Middleware, MiddlewareResult};
+
+// Hop-by-hop headers that are always removed, in addition to `Proxy-*` and
+// those listed in `Connection`.
+const HOP_BY_HOP: [HeaderName; 5] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    header::TE,
+    header::TRAILER,
+    header::UPGRADE,
+];
+
+/// Removes the hop-by-hop headers from `headers`, as a proxy must before
+/// forwarding a request or response (RFC 7230, section 6.1). These are
+/// `Connection`, `Keep-Alive`, `TE`, `Trailer`, `Transfer-Encoding`,
+/// `Upgrade`, all `Proxy-*` headers and the headers listed in `Connection`.
+///
+/// Use it on the upstream response of a proxying handler before copying its
+/// headers, `StripHopByHop` takes care of incoming requests.
+pub fn strip_hop_by_hop_headers(headers: &mut HeaderMap) {
+    let listed: Vec<HeaderName> = headers.get_all(header::CONNECTION)
+                                         .iter()
+                                         .filter_map(|v| v.to_str().ok())
+                                         .flat_map(|v| v.split(','))
+                                         .filter_map(|name| name.trim().parse().ok())
+                                         .collect();
+    let proxy: Vec<HeaderName> = headers.keys()
+                                        .filter(|name| name.as_str().starts_with("proxy-"))
+                                        .cloned()
+                                        .collect();
+
+    for name in listed.iter().chain(&proxy).chain(&HOP_BY_HOP) {
+        headers.remove(name);
+    }
+    // The body is forwarded as is, so its framing has to be redone as well
+    headers.remove(header::TRANSFER_ENCODING);
+}
+
+/// Strips hop-by-hop headers from requests, see `strip_hop_by_hop_headers`,
+/// so proxying handlers can forward the remaining headers as they are.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::{Nickel, StripHopByHop};
+///
+/// let mut server = Nickel::new();
+/// server.utilize(StripHopByHop);
+/// ```
+#[derive(Clone, Copy)]
+pub struct StripHopByHop;
+
+#[async_trait]
+impl<D: Send + 'static + Sync> Middleware<D> for StripHopByHop {
+    async fn invoke(&self, req: &mut Request<D>, res: Response<D>)
+            -> MiddlewareResult<D> {
+        strip_hop_by_hop_headers(req.origin.headers_mut());
+        res.next_middleware()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{header, HeaderMap, Request as HyperRequest};
+    use hyper::header::HeaderValue;
+    use super::{strip_hop_by_hop_headers, StripHopByHop};
+    use crate::{Action, Middleware};
+    use crate::test_helpers;
+
+    #[tokio::test]
+    async fn strips_request_headers() {
+        let mut req = test_helpers::request(HyperRequest::get("/")
+                                                .header("connection", "keep-alive, X-Trace")
+                                                .header("keep-alive", "timeout=5")
+                                                .header("x-trace", "abc")
+                                                .header("proxy-authorization", "Basic Zm9vOmJhcg==")
+                                                .header("upgrade", "h2c")
+                                                .header("accept", "text/html")
+                                                .header("authorization", "Bearer token"));
+
+        let result = StripHopByHop.invoke(&mut req, test_helpers::response()).await;
+        assert!(matches!(result, Ok(Action::Continue(_))));
+
+        let mut names: Vec<_> = req.origin.headers().keys().map(|name| name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["accept", "authorization"]);
+    }
+
+    #[test]
+    fn strips_response_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+        headers.insert(header::TRANSFER_ENCODING, HeaderValue::from_static("chunked"));
+        headers.insert(header::PROXY_AUTHENTICATE, HeaderValue::from_static("Basic"));
+        headers.insert(header::TRAILER, HeaderValue::from_static("expires"));
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+        strip_hop_by_hop_headers(&mut headers);
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/plain");
+        assert_eq!(headers[header::CACHE_CONTROL], "no-cache");
+    }
+}
@@ -0,0 +1,204 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use futures::StreamExt;
+use hyper::{Body, Response};
+use hyper::body::HttpBody;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{self, Instant, Sleep};
+
+// A connection that fails with `TimedOut` once nothing was read or written
+// for `timeout`, so hyper closes it. Connections with requests in progress
+// (see `busy`) are never idle, however long the handler takes.
+pub(crate) struct IdleTimeout<T> {
+    inner: T,
+    timeout: Option<Duration>,
+    deadline: Pin<Box<Sleep>>,
+    requests: Arc<AtomicUsize>,
+}
+
+// Marks its connection busy while alive.
+pub(crate) struct Busy(Arc<AtomicUsize>);
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Keeps `busy` alive along with the body of `response`, so a streamed body
+// whose producer pauses for longer than the timeout isn't cut off. Bodies of
+// a known size are sent in one go and release the connection right away.
+pub(crate) fn busy_until_sent(response: Response<Body>, busy: Busy) -> Response<Body> {
+    if response.body().size_hint().exact().is_some() {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = body.map(move |chunk| {
+        let _ = &busy;
+        chunk
+    });
+    Response::from_parts(parts, Body::wrap_stream(body))
+}
+
+impl<T> IdleTimeout<T> {
+    pub(crate) fn new(inner: T, timeout: Option<Duration>) -> IdleTimeout<T> {
+        let deadline = Instant::now() + timeout.unwrap_or_default();
+        IdleTimeout {
+            inner,
+            timeout,
+            deadline: Box::pin(time::sleep_until(deadline)),
+            requests: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub(crate) fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    // A function marking the connection busy until the returned guard is
+    // dropped, for the service handling its requests.
+    pub(crate) fn busy(&self) -> impl Fn() -> Busy + Clone {
+        let requests = self.requests.clone();
+        move || {
+            requests.fetch_add(1, Ordering::SeqCst);
+            Busy(requests.clone())
+        }
+    }
+
+    fn touch(&mut self) {
+        if let Some(timeout) = self.timeout {
+            self.deadline.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+
+    // Called when the connection has nothing to do.
+    fn poll_idle<R>(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<R>> {
+        if self.timeout.is_none() {
+            return Poll::Pending;
+        }
+        if self.requests.load(Ordering::SeqCst) > 0 {
+            self.touch();
+            return Poll::Pending;
+        }
+        match self.deadline.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut,
+                                                              "connection idle timeout"))),
+            Poll::Pending => Poll::Pending
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for IdleTimeout<T> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match Pin::new(&mut self.inner).poll_read(cx, buf) {
+            Poll::Ready(result) => {
+                self.touch();
+                Poll::Ready(result)
+            },
+            Poll::Pending => self.poll_idle(cx)
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for IdleTimeout<T> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match Pin::new(&mut self.inner).poll_write(cx, buf) {
+            Poll::Ready(result) => {
+                self.touch();
+                Poll::Ready(result)
+            },
+            // e.g. a client that stopped reading
+            Poll::Pending => self.poll_idle(cx)
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::time::Duration;
+    use hyper::{Body, Response};
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+    use super::{busy_until_sent, IdleTimeout};
+
+    #[tokio::test]
+    async fn closes_idle_connections() {
+        let (_client, server) = duplex(64);
+        let mut conn = IdleTimeout::new(server, Some(Duration::from_millis(50)));
+
+        let mut buf = [0; 8];
+        let err = conn.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn keeps_active_and_busy_connections() {
+        let (mut client, server) = duplex(64);
+        let mut conn = IdleTimeout::new(server, Some(Duration::from_millis(50)));
+
+        let writer = tokio::spawn(async move {
+            for _ in 0..10 {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                client.write_all(b"ping").await.unwrap();
+            }
+            client
+        });
+        let mut buf = [0; 4];
+        for _ in 0..10 {
+            conn.read_exact(&mut buf).await.unwrap();
+        }
+        let _client = writer.await.unwrap();
+
+        // a request taking longer than the timeout
+        let busy = conn.busy()();
+        let read = tokio::time::timeout(Duration::from_millis(150), conn.read(&mut buf)).await;
+        assert!(read.is_err(), "busy connection timed out");
+        drop(busy);
+        let err = conn.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn streamed_responses_keep_connections_busy() {
+        let (_client, server) = duplex(64);
+        let mut conn = IdleTimeout::new(server, Some(Duration::from_millis(50)));
+
+        let delayed = futures::stream::once(async {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            Ok::<_, io::Error>("late chunk")
+        });
+        let response = busy_until_sent(Response::new(Body::wrap_stream(delayed)), conn.busy()());
+        let body = tokio::spawn(hyper::body::to_bytes(response.into_body()));
+
+        let mut buf = [0; 8];
+        let read = tokio::time::timeout(Duration::from_millis(100), conn.read(&mut buf)).await;
+        assert!(read.is_err(), "connection timed out while streaming");
+        assert_eq!(&body.await.unwrap().unwrap()[..], b"late chunk");
+        let err = conn.read(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn without_timeout_connections_stay_open() {
+        let (_client, server) = duplex(64);
+        let mut conn = IdleTimeout::new(server, None);
+
+        let mut buf = [0; 8];
+        let read = tokio::time::timeout(Duration::from_millis(100), conn.read(&mut buf)).await;
+        assert!(read.is_err());
+    }
+}
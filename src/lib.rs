@@ -1,37 +1,82 @@
 #![doc(test(attr(deny(warnings))))]
 
 pub use hyper;
+#[cfg(feature = "templates")]
+pub use erased_serde;
 
 #[macro_use] extern crate log;
 #[macro_use] extern crate lazy_static;
 
 pub use crate::nickel::{Nickel, Options};
-pub use crate::request::Request;
-pub use crate::response::Response;
-pub use crate::middleware::{Action, Continue, Halt, Middleware, ErrorHandler, MiddlewareResult};
+pub use crate::server::StackHandle;
+pub use crate::request::{ContentRange, ExtensionSnapshot, Request};
+pub use crate::request_builder::RequestBuilder;
+pub use crate::cookies::Cookies;
+pub use crate::response::{Response, RetryAfter};
+pub use crate::multipart::MultipartWriter;
+pub use crate::precompressed::PrecompressedBody;
+pub use crate::compress::Compress;
+pub use crate::ranges::RangedBody;
+pub use crate::etag::ETag;
+pub use crate::middleware::{Action, Continue, Halt, Middleware, ErrorHandler, MiddlewareResult, ResponseHead, ResponseSummary};
+#[cfg(feature = "static-files")]
 pub use crate::static_files_handler::StaticFilesHandler;
 pub use crate::mount::{Mount, Mountable};
 pub use crate::favicon_handler::FaviconHandler;
+pub use crate::csp::Csp;
+#[cfg(feature = "templates")]
+pub use crate::csp::CSP_NONCE_VARIABLE;
+pub use crate::security_headers::SecurityHeaders;
+pub use crate::require_ext::RequireExt;
+pub use crate::trailing_slash::{TrailingSlash, TrailingSlashPolicy};
+pub use crate::hop_by_hop::{strip_hop_by_hop_headers, StripHopByHop};
+pub use crate::maintenance::Maintenance;
 pub use crate::default_error_handler::DefaultErrorHandler;
+#[cfg(feature = "json")]
+pub use crate::problem::{Problem, ProblemErrorHandler};
+#[cfg(feature = "json")]
+pub use crate::access_log::{JsonAccessLog, ACCESS_LOG_TARGET};
+#[cfg(all(feature = "json", feature = "templates"))]
+pub use crate::negotiated::Negotiated;
 //pub use crate::body_parser::{BodyError, FormBody, JsonBody};
+#[cfg(feature = "query")]
 pub use crate::query_string::QueryString;
 pub use crate::urlencoded::{Params, Query};
 pub use crate::router::{Router, Route, RouteResult, HttpRouter};
 pub use crate::nickel_error::NickelError;
 pub use crate::mimes::MediaType;
 pub use crate::responder::Responder;
-pub use crate::template_cache::{ReloadPolicy, TemplateCache};
+pub use crate::template_cache::{HeadRender, ReloadPolicy, TemplateCache};
+#[cfg(feature = "templates")]
+pub use crate::template_cache::{MustacheEngine, TemplateEngine, TemplateError};
+#[cfg(feature = "templates")]
+pub use crate::template_context::TemplateContext;
 
 #[macro_use] pub mod macros;
 
 pub mod router;
 mod server;
+mod idle_timeout;
 mod nickel;
 mod request;
+mod request_builder;
+pub mod cookies;
 mod response;
+mod multipart;
+mod precompressed;
+mod compress;
+mod ranges;
+mod etag;
 mod middleware;
 mod responder;
 mod favicon_handler;
+mod csp;
+mod security_headers;
+mod require_ext;
+mod trailing_slash;
+mod hop_by_hop;
+mod maintenance;
+#[cfg(feature = "static-files")]
 mod static_files_handler;
 mod mount;
 
@@ -39,13 +84,26 @@ mod mount;
 // documentation as part of migration to async.
 //mod body_parser;
 
+#[cfg(feature = "query")]
 mod query_string;
 pub mod mimes;
 mod urlencoded;
 mod nickel_error;
 mod default_error_handler;
+#[cfg(feature = "json")]
+mod problem;
+#[cfg(feature = "json")]
+mod access_log;
+#[cfg(all(feature = "json", feature = "templates"))]
+mod negotiated;
 pub mod extensions;
+mod util;
 pub mod template_cache;
+#[cfg(feature = "templates")]
+mod template_context;
+
+#[cfg(test)]
+mod test_helpers;
 
 pub mod status {
     pub use hyper::StatusCode;
@@ -13,6 +13,30 @@ macro_rules! router {
 macro_rules! _router_inner {
     ($router:ident)
         => { $router }; // Base case
+    ($router:ident if ($cond:expr) $method:ident $path:expr => |$req:tt, mut $res:ident| { $($b:tt)* } $($rest:tt)*)
+        => {{
+            if $cond {
+                $router.$method($path, middleware!(|$req, mut $res| $($b)*));
+            }
+
+            _router_inner!($router $($rest)*)
+        }};
+    ($router:ident if ($cond:expr) $method:ident $path:expr => |$req:tt, $res:ident| { $($b:tt)* } $($rest:tt)*)
+        => {{
+            if $cond {
+                $router.$method($path, middleware!(|$req, $res| $($b)*));
+            }
+
+            _router_inner!($router $($rest)*)
+        }};
+    ($router:ident if ($cond:expr) $method:ident $path:expr => |$req:tt| { $($b:tt)* } $($rest:tt)*)
+        => {
+            _router_inner!($router if ($cond) $method $path => |$req, _res| { $($b)* } $($rest)*)
+        };
+    ($router:ident if ($cond:expr) $method:ident $path:expr => { $($b:tt)* } $($rest:tt)*)
+        => {
+            _router_inner!($router if ($cond) $method $path => |_, _res| { $($b)* } $($rest)*)
+        };
     ($router:ident $method:ident $path:expr => |$req:tt, mut $res:ident| { $($b:tt)* } $($rest:tt)*)
         => {{
             $router.$method($path, middleware!(|$req, mut $res| $($b)*));
@@ -0,0 +1,172 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use async_trait::async_trait;
+
+use crate::status::StatusCode;
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::{Middleware, MiddlewareResult};
+
+/// Answers all requests with `503 Service Unavailable` and a maintenance page
+/// while a shared flag is set, e.g. during deploys. Requests for allowed
+/// paths, like health checks, and from allowed addresses, like an admin's,
+/// pass through as usual.
+///
+/// The flag can be toggled at any time from other threads, the change applies
+/// to the next request.
+///
+/// # Examples
+/// ```{rust}
+/// use std::sync::Arc;
+/// use std::sync::atomic::{AtomicBool, Ordering};
+/// use std::time::Duration;
+/// use nickel::{Nickel, Maintenance};
+///
+/// let down = Arc::new(AtomicBool::new(false));
+/// let mut server = Nickel::new();
+/// server.utilize(Maintenance::new(down.clone())
+///                    .allow_path("/health")
+///                    .allow_ip("10.0.0.5".parse().unwrap())
+///                    .retry_after(Duration::from_secs(300)));
+///
+/// // later, e.g. in a signal handler
+/// down.store(true, Ordering::SeqCst);
+/// ```
+pub struct Maintenance {
+    enabled: Arc<AtomicBool>,
+    status: StatusCode,
+    body: String,
+    allowed_paths: Vec<String>,
+    allowed_ips: Vec<IpAddr>,
+    retry_after: Option<Duration>,
+}
+
+impl Maintenance {
+    /// Create a new middleware that is active while `enabled` is set.
+    pub fn new(enabled: Arc<AtomicBool>) -> Maintenance {
+        Maintenance {
+            enabled,
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            body: "Down for maintenance, please try again later.".to_string(),
+            allowed_paths: Vec::new(),
+            allowed_ips: Vec::new(),
+            retry_after: None,
+        }
+    }
+
+    /// The status to respond with.
+    ///
+    /// Defaults to `503 Service Unavailable`.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// The maintenance page, sent as HTML.
+    pub fn body<S: Into<String>>(mut self, body: S) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Lets requests for `path` through, ignoring the query string. Can be
+    /// called multiple times.
+    pub fn allow_path<S: Into<String>>(mut self, path: S) -> Self {
+        self.allowed_paths.push(path.into());
+        self
+    }
+
+    /// Lets requests from `ip` through. Can be called multiple times.
+    pub fn allow_ip(mut self, ip: IpAddr) -> Self {
+        self.allowed_ips.push(ip);
+        self
+    }
+
+    /// Tells clients when to come back, see `Response::retry_after`.
+    ///
+    /// Defaults to `None`, where no `Retry-After` header is sent.
+    pub fn retry_after(mut self, delay: Duration) -> Self {
+        self.retry_after = Some(delay);
+        self
+    }
+
+    fn allows<D>(&self, req: &Request<D>) -> bool {
+        let path = req.path_without_query();
+        if self.allowed_paths.iter().any(|allowed| allowed == path) {
+            return true;
+        }
+        match req.remote_addr() {
+            Some(addr) => self.allowed_ips.contains(&addr.ip()),
+            None => false
+        }
+    }
+}
+
+#[async_trait]
+impl<D: Send + 'static + Sync> Middleware<D> for Maintenance {
+    async fn invoke(&self, req: &mut Request<D>, mut res: Response<D>)
+            -> MiddlewareResult<D> {
+        if !self.enabled.load(Ordering::SeqCst) || self.allows(req) {
+            return res.next_middleware();
+        }
+
+        res.set(self.status);
+        if let Some(delay) = self.retry_after {
+            res.retry_after(delay);
+        }
+        res.send(self.body.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use hyper::{header, Request as HyperRequest, StatusCode};
+    use super::Maintenance;
+    use crate::{Action, Middleware, Request};
+    use crate::test_helpers::{self, body_string};
+
+    async fn passes(maintenance: &Maintenance, mut req: Request<()>) -> bool {
+        match maintenance.invoke(&mut req, test_helpers::response()).await {
+            Ok(Action::Continue(_)) => true,
+            Ok(Action::Halt(res)) => {
+                assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+                assert_eq!(res.headers()[header::RETRY_AFTER], "60");
+                assert_eq!(body_string(res).await, "Back soon");
+                false
+            },
+            Err(err) => panic!("unexpected error: {}", err.message)
+        }
+    }
+
+    #[tokio::test]
+    async fn toggling_the_flag_flips_behavior() {
+        let down = Arc::new(AtomicBool::new(false));
+        let maintenance = Maintenance::new(down.clone())
+                              .body("Back soon")
+                              .retry_after(Duration::from_secs(60));
+
+        assert!(passes(&maintenance, test_helpers::get("/users")).await);
+        down.store(true, Ordering::SeqCst);
+        assert!(!passes(&maintenance, test_helpers::get("/users")).await);
+        down.store(false, Ordering::SeqCst);
+        assert!(passes(&maintenance, test_helpers::get("/users")).await);
+    }
+
+    #[tokio::test]
+    async fn allow_list_passes_during_maintenance() {
+        let maintenance = Maintenance::new(Arc::new(AtomicBool::new(true)))
+                              .body("Back soon")
+                              .retry_after(Duration::from_secs(60))
+                              .allow_path("/health")
+                              .allow_ip("10.0.0.5".parse().unwrap());
+
+        assert!(passes(&maintenance, test_helpers::get("/health?verbose=1")).await);
+        assert!(passes(&maintenance, test_helpers::request_from(HyperRequest::get("/admin"), "10.0.0.5:4711")).await);
+        assert!(!passes(&maintenance, test_helpers::request_from(HyperRequest::get("/admin"), "10.0.0.6:4711")).await);
+        assert!(!passes(&maintenance, test_helpers::get("/health/db")).await);
+    }
+}
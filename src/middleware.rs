@@ -2,7 +2,15 @@ use async_trait::async_trait;
 use crate::request::Request;
 use crate::response::Response;
 use crate::nickel_error::NickelError;
-use hyper::{Body, Response as HyperResponse};
+use futures::{stream, StreamExt};
+use hyper::{Body, Method, Response as HyperResponse, StatusCode};
+use hyper::body::HttpBody;
+use hyper::header::{self, HeaderValue};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 pub use self::Action::{Continue, Halt};
 
@@ -21,6 +29,11 @@ pub trait Middleware<D: Send + 'static + Sync>: Send + 'static + Sync {
     async fn invoke(&self, _req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
         res.next_middleware()
     }
+
+    /// Identifies the middleware in log output. Defaults to the type name.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
 }
 
 #[async_trait]
@@ -43,9 +56,63 @@ impl<D: Send + 'static + Sync> ErrorHandler<D> for fn(&mut NickelError<D>, &mut
     }
 }
 
+/// Callback observing every error returned by a middleware, see
+/// `Nickel::on_error`.
+pub type ErrorObserver<D> = dyn Fn(&NickelError<D>, &Request<D>) + Send + Sync;
+
+/// The status line and headers of a finished response, without its body.
+pub type ResponseHead = hyper::http::response::Parts;
+
+/// Transform applied to the head of every response, see
+/// `Nickel::map_response`.
+pub type ResponseMap = dyn Fn(&mut ResponseHead) + Send + Sync;
+
+/// What was sent in response to a request, see `Request::on_response_sent`.
+#[derive(Clone, Debug)]
+pub struct ResponseSummary {
+    status: StatusCode,
+    bytes_in: u64,
+    bytes_out: u64,
+    elapsed: Duration,
+    route: Option<String>,
+}
+
+impl ResponseSummary {
+    /// The status of the response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The number of request body bytes read, see `Request::bytes_in`.
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in
+    }
+
+    /// The number of body bytes sent, which is less than the full body if
+    /// the client went away early.
+    pub fn bytes_out(&self) -> u64 {
+        self.bytes_out
+    }
+
+    /// The time from the start of request handling until the body was sent.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The path template of the route that handled the request, see
+    /// `RouteResult::template`.
+    pub fn route(&self) -> Option<&str> {
+        self.route.as_deref()
+    }
+}
+
+pub(crate) type ResponseHook = dyn FnOnce(&ResponseSummary) + Send;
+
 pub struct MiddlewareStack<D: Send + 'static + Sync = ()> {
     handlers: Vec<Box<dyn Middleware<D> + Send + Sync>>,
-    error_handlers: Vec<Box<dyn ErrorHandler<D> + Send + Sync>>
+    error_handlers: Vec<Box<dyn ErrorHandler<D> + Send + Sync>>,
+    error_observers: Vec<Box<ErrorObserver<D>>>,
+    response_maps: Vec<Box<ResponseMap>>
 }
 
 impl<D: Send + 'static + Sync> MiddlewareStack<D> {
@@ -57,11 +124,78 @@ impl<D: Send + 'static + Sync> MiddlewareStack<D> {
         self.error_handlers.push(Box::new(handler));
     }
 
-    pub async fn invoke(&self, mut req: Request<D>, mut res: Response<D>) -> HyperResponse<Body> {
+    /// Inserts an error handler at `position` in the order the handlers run,
+    /// 0 being the first. Handlers run in reverse order of registration, so
+    /// a position past the end runs it after all existing handlers.
+    pub fn insert_error_handler<T: ErrorHandler<D>> (&mut self, position: usize, handler: T) {
+        let index = self.error_handlers.len() - position.min(self.error_handlers.len());
+        self.error_handlers.insert(index, Box::new(handler));
+    }
+
+    pub(crate) fn error_handler_count(&self) -> usize {
+        self.error_handlers.len()
+    }
+
+    pub fn add_error_observer<F>(&mut self, observer: F)
+    where F: Fn(&NickelError<D>, &Request<D>) + Send + Sync + 'static {
+        self.error_observers.push(Box::new(observer));
+    }
+
+    pub fn add_response_map<F>(&mut self, map: F)
+    where F: Fn(&mut ResponseHead) + Send + Sync + 'static {
+        self.response_maps.push(Box::new(map));
+    }
+
+    pub async fn invoke(&self, mut req: Request<D>, res: Response<D>) -> HyperResponse<Body> {
+        let started = Instant::now();
+        let mut response = self.invoke_handlers(&mut req, res).await;
+        if !self.response_maps.is_empty() {
+            let (mut head, body) = response.into_parts();
+            for map in self.response_maps.iter() {
+                map(&mut head);
+            }
+            response = HyperResponse::from_parts(head, body);
+        }
+        let response = strip_bodiless(response);
+        let response = check_streamed_length(no_ranges_for_streams(response));
+
+        let hooks = req.take_response_hooks();
+        let bytes_out = if hooks.is_empty() {
+            None
+        } else {
+            let bytes_out = Arc::new(AtomicU64::new(0));
+            let status = response.status();
+            let route = req.route_result.as_ref().and_then(|r| r.template()).map(str::to_string);
+            for hook in hooks {
+                let bytes_in = req.bytes_in_counter();
+                let bytes_out = bytes_out.clone();
+                let route = route.clone();
+                req.spawn_after_response(move || hook(&ResponseSummary {
+                    status,
+                    bytes_in: bytes_in.load(Ordering::SeqCst),
+                    bytes_out: bytes_out.load(Ordering::SeqCst),
+                    elapsed: started.elapsed(),
+                    route
+                }));
+            }
+            Some(bytes_out)
+        };
+
+        let deferred = req.take_deferred();
+        if deferred.is_empty() {
+            response
+        } else {
+            let head_request = req.origin.method() == Method::HEAD;
+            run_after_body(response, deferred, bytes_out, head_request)
+        }
+    }
+
+    async fn invoke_handlers(&self, req: &mut Request<D>, mut res: Response<D>) -> HyperResponse<Body> {
         for handler in self.handlers.iter() {
-            match handler.invoke(&mut req, res).await {
+            match handler.invoke(req, res).await {
                 Ok(Halt(res)) => {
-                    debug!("Halted {:?} {:?} {:?} {:?}",
+                    debug!("Halted by {} {:?} {:?} {:?} {:?}",
+                           handler.name(),
                            req.origin.method(),
                            req.remote_addr(),
                            req.origin.uri(),
@@ -71,22 +205,25 @@ impl<D: Send + 'static + Sync> MiddlewareStack<D> {
                 },
                 Ok(Continue(fresh)) => res = fresh,
                 Err(mut err) => {
-                    warn!("{:?} {:?} {:?} {:?} {:?}",
+                    warn!("Error in {} {:?} {:?} {:?} {:?} {:?}",
+                          handler.name(),
                           req.origin.method(),
                           req.remote_addr(),
                           req.origin.uri(),
                           err.message,
                           err.stream.as_ref().map(|s| s.status()));
 
+                    for observer in self.error_observers.iter() {
+                        observer(&err, req);
+                    }
+
                     for error_handler in self.error_handlers.iter().rev() {
-                        if let Halt(()) = error_handler.handle_error(&mut err, &mut req) {
+                        if let Halt(()) = error_handler.handle_error(&mut err, req) {
                             if let Some(res) = err.stream {
                                 return res.origin;
                             } else {
                                 error!("Error without Response struct");
-                                // Create a new Response with an InternalServerError
-
-                                panic!("Error without Response struct"); // Todo: migration cleanup - return error
+                                return internal_server_error();
                             }
                         }
                     }
@@ -96,8 +233,15 @@ impl<D: Send + 'static + Sync> MiddlewareStack<D> {
                           req.remote_addr(),
                           req.origin.uri(),
                           err.message,
-                          err.stream.map(|s| s.status()));
-                    panic!("Unhandled Error"); // Todo: migration cleanup - return error
+                          err.stream.as_ref().map(|s| s.status()));
+                    // Send the error status without a body
+                    return match err.stream {
+                        Some(mut res) => {
+                            res.set_body(Body::empty());
+                            res.origin
+                        },
+                        None => internal_server_error()
+                    };
                 }
             }
         }
@@ -108,7 +252,456 @@ impl<D: Send + 'static + Sync> MiddlewareStack<D> {
     pub fn new () -> MiddlewareStack<D> {
         MiddlewareStack{
             handlers: Vec::new(),
-            error_handlers: Vec::new()
+            error_handlers: Vec::new(),
+            error_observers: Vec::new(),
+            response_maps: Vec::new()
+        }
+    }
+}
+
+fn internal_server_error() -> HyperResponse<Body> {
+    let mut res = HyperResponse::new(Body::empty());
+    *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    res
+}
+
+// Responses with a status that doesn't allow a body (1xx, 204 and 304) are
+// sent without one, and without the content headers handlers or the fallback
+// headers may have set.
+fn strip_bodiless(response: HyperResponse<Body>) -> HyperResponse<Body> {
+    let status = response.status();
+    if !(status.is_informational() || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    if body.size_hint().exact() != Some(0) {
+        warn!("Dropping the body of a response with status {}", status);
+    }
+    for name in &[header::CONTENT_LENGTH, header::CONTENT_TYPE, header::TRANSFER_ENCODING] {
+        parts.headers.remove(name);
+    }
+    HyperResponse::from_parts(parts, Body::empty())
+}
+
+// Streamed bodies can't serve byte ranges, so clients shouldn't try unless the
+// handler says otherwise, as `Response::send_file` does.
+fn no_ranges_for_streams(mut response: HyperResponse<Body>) -> HyperResponse<Body> {
+    if response.body().size_hint().exact().is_none() && !response.headers().contains_key(header::ACCEPT_RANGES) {
+        response.headers_mut().insert(header::ACCEPT_RANGES, HeaderValue::from_static("none"));
+    }
+    response
+}
+
+// Logs streamed bodies whose length differs from the `Content-Length` set
+// with `Response::set_content_length`. Hyper cuts off longer bodies, and
+// clients waiting for the missing bytes of shorter ones are left hanging.
+fn check_streamed_length(response: HyperResponse<Body>) -> HyperResponse<Body> {
+    let expected = response.headers()
+                           .get(header::CONTENT_LENGTH)
+                           .and_then(|v| v.to_str().ok())
+                           .and_then(|v| v.parse::<u64>().ok());
+    let expected = match expected {
+        Some(len) if response.body().size_hint().exact().is_none() => len,
+        _ => return response
+    };
+
+    let (parts, body) = response.into_parts();
+    let body = stream::unfold((body, 0u64), move |(mut body, written)| async move {
+        match body.data().await {
+            Some(Ok(chunk)) => {
+                let total = written + chunk.len() as u64;
+                if total > expected && written <= expected {
+                    error!("Streamed body exceeds its Content-Length of {} bytes", expected);
+                }
+                Some((Ok(chunk), (body, total)))
+            },
+            Some(Err(e)) => Some((Err(e), (body, written))),
+            None => {
+                if written < expected {
+                    error!("Streamed body of {} bytes is shorter than its Content-Length of {} bytes",
+                           written, expected);
+                }
+                None
+            }
+        }
+    });
+    HyperResponse::from_parts(parts, Body::wrap_stream(body))
+}
+
+// Runs the closures registered with `Request::spawn_after_response` once the
+// body has been dropped by hyper, i.e. after it was written out completely or
+// the client disconnected.
+// Counts the body bytes sent into `bytes_out`, if given.
+fn run_after_body(response: HyperResponse<Body>,
+                  deferred: Vec<Box<dyn FnOnce() + Send>>,
+                  bytes_out: Option<Arc<AtomicU64>>,
+                  head_request: bool) -> HyperResponse<Body> {
+    let (mut parts, body) = response.into_parts();
+
+    // Wrapping the body loses its size, keep the framing by setting it
+    // explicitly. The empty body of a HEAD response says nothing about the
+    // length of the GET body though, and bodiless statuses have no framing.
+    let status = parts.status;
+    let bodiless = head_request || status.is_informational() ||
+                   status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED;
+    if let Some(len) = body.size_hint().exact() {
+        if !bodiless {
+            parts.headers.entry(header::CONTENT_LENGTH).or_insert_with(|| HeaderValue::from(len));
+        }
+    }
+
+    let guard = Deferred(deferred);
+    let body = body.map(move |chunk| {
+        let _ = &guard;
+        if let (Some(bytes_out), Ok(chunk)) = (&bytes_out, &chunk) {
+            bytes_out.fetch_add(chunk.len() as u64, Ordering::SeqCst);
+        }
+        chunk
+    });
+    HyperResponse::from_parts(parts, Body::wrap_stream(body))
+}
+
+struct Deferred(Vec<Box<dyn FnOnce() + Send>>);
+
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        let tasks = mem::take(&mut self.0);
+        let run = move || {
+            for task in tasks {
+                if panic::catch_unwind(AssertUnwindSafe(task)).is_err() {
+                    error!("Deferred task panicked");
+                }
+            }
+        };
+
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => { handle.spawn_blocking(run); },
+            Err(_) => run()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use hyper::{Body, Request as HyperRequest, Response as HyperResponse};
+    use super::MiddlewareStack;
+    use crate::{MiddlewareResult, Request, Response};
+    use crate::test_helpers;
+
+    fn invoke(stack: &MiddlewareStack<()>, req: Request<()>)
+                  -> impl std::future::Future<Output = HyperResponse<Body>> + '_ {
+        stack.invoke(req, test_helpers::response())
+    }
+
+    #[tokio::test]
+    async fn deferred_work_runs_after_body_is_sent() {
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = ran.clone();
+
+        let mut stack = MiddlewareStack::new();
+        stack.add_middleware(move |req: &mut Request, res: Response| -> MiddlewareResult {
+            let flag = flag.clone();
+            req.spawn_after_response(move || flag.store(true, Ordering::SeqCst));
+            res.send("deferred")
+        });
+
+        let response = invoke(&stack, test_helpers::get("/")).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!ran.load(Ordering::SeqCst), "ran before the body was sent");
+
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"deferred");
+
+        for _ in 0..100 {
+            if ran.load(Ordering::SeqCst) { return }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("deferred closure never ran");
+    }
+
+    #[tokio::test]
+    async fn deferred_work_keeps_content_length_of_head_responses() {
+        use hyper::{header, Request as HyperRequest, StatusCode};
+
+        let mut stack = MiddlewareStack::new();
+        stack.add_middleware(|req: &mut Request, mut res: Response| -> MiddlewareResult {
+            req.spawn_after_response(|| {});
+            if req.path_without_query() == "/switch" {
+                res.set(StatusCode::SWITCHING_PROTOCOLS);
+            }
+            // as a HEAD response skipping the render
+            res.send("")
+        });
+
+        let response = invoke(&stack, test_helpers::request(HyperRequest::head("/"))).await;
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+        let response = invoke(&stack, test_helpers::get("/switch")).await;
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+
+        let response = invoke(&stack, test_helpers::get("/")).await;
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "0");
+    }
+
+    #[tokio::test]
+    async fn response_hooks_get_a_summary() {
+        use std::sync::Mutex;
+        use hyper::StatusCode;
+        use crate::{HttpRouter, Router};
+
+        let summaries = Arc::new(Mutex::new(Vec::new()));
+        let seen = summaries.clone();
+
+        let mut stack = MiddlewareStack::new();
+        stack.add_middleware(move |req: &mut Request, res: Response| -> MiddlewareResult {
+            let seen = seen.clone();
+            req.on_response_sent(move |summary| seen.lock().unwrap().push(summary.clone()));
+            res.next_middleware()
+        });
+        let mut router = Router::new();
+        router.get("/users/:id", middleware!("hello world"));
+        stack.add_middleware(router);
+
+        let response = invoke(&stack, test_helpers::get("/users/42")).await;
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        for _ in 0..100 {
+            if let Some(summary) = summaries.lock().unwrap().first() {
+                assert_eq!(summary.status(), StatusCode::OK);
+                assert_eq!(summary.bytes_out(), 11);
+                assert_eq!(summary.route(), Some("/users/:id"));
+                return
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("response hook never ran");
+    }
+
+    #[tokio::test]
+    async fn summaries_count_request_and_response_bytes() {
+        use std::sync::Mutex;
+        use async_trait::async_trait;
+        use crate::{Middleware, ResponseSummary};
+
+        struct Upload(Arc<Mutex<Vec<ResponseSummary>>>);
+
+        #[async_trait]
+        impl Middleware<()> for Upload {
+            async fn invoke(&self, req: &mut Request, res: Response) -> MiddlewareResult {
+                let seen = self.0.clone();
+                req.on_response_sent(move |summary| seen.lock().unwrap().push(summary.clone()));
+                let len = req.raw_body().await.unwrap().len();
+                res.send(format!("stored {} bytes", len))
+            }
+        }
+
+        let summaries = Arc::new(Mutex::new(Vec::new()));
+        let mut stack = MiddlewareStack::new();
+        stack.add_middleware(Upload(summaries.clone()));
+
+        let upload = test_helpers::request_with_body(HyperRequest::post("/upload"), "0123456789abcdef");
+        let response = invoke(&stack, upload).await;
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"stored 16 bytes");
+
+        for _ in 0..100 {
+            if let Some(summary) = summaries.lock().unwrap().first() {
+                assert_eq!(summary.bytes_in(), 16);
+                assert_eq!(summary.bytes_out(), 15);
+                return
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("response hook never ran");
+    }
+
+    #[tokio::test]
+    async fn observers_see_handled_and_unhandled_errors() {
+        use std::sync::Mutex;
+        use hyper::StatusCode;
+        use crate::{Action, Continue, Halt, NickelError};
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let observed = seen.clone();
+
+        let mut stack = MiddlewareStack::new();
+        stack.add_middleware(|req: &mut Request, res: Response| -> MiddlewareResult {
+            match req.path_without_query() {
+                "/missing" => res.error(StatusCode::NOT_FOUND, "no such page"),
+                _ => res.error(StatusCode::BAD_GATEWAY, "upstream is down"),
+            }
+        });
+        // Only renders 404s
+        stack.add_error_handler(|err: &mut NickelError, _: &mut Request| -> Action {
+            match err.stream.as_mut() {
+                Some(res) if res.status() == StatusCode::NOT_FOUND => {
+                    res.set_body("Not Found");
+                    Halt(())
+                },
+                _ => Continue(())
+            }
+        } as fn(&mut NickelError, &mut Request) -> Action);
+        stack.add_error_observer(move |err: &NickelError, req: &Request| {
+            observed.lock().unwrap().push(format!("{} {}", req.path_without_query(), err.message));
+        });
+
+        let handled = invoke(&stack, test_helpers::get("/missing")).await;
+        assert_eq!(handled.status(), StatusCode::NOT_FOUND);
+        let body = hyper::body::to_bytes(handled.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"Not Found");
+
+        let unhandled = invoke(&stack, test_helpers::get("/proxy")).await;
+        assert_eq!(unhandled.status(), StatusCode::BAD_GATEWAY);
+
+        assert_eq!(*seen.lock().unwrap(), vec!["/missing no such page", "/proxy upstream is down"]);
+    }
+
+    #[tokio::test]
+    async fn response_maps_apply_to_all_outcomes() {
+        use hyper::StatusCode;
+        use hyper::header::HeaderValue;
+        use crate::Halt;
+        use crate::default_error_handler::DefaultErrorHandler;
+
+        let mut stack = MiddlewareStack::new();
+        stack.add_middleware(|req: &mut Request, mut res: Response| -> MiddlewareResult {
+            match req.path_without_query() {
+                "/ok" => res.send("ok"),
+                "/stream" => {
+                    let chunks: Vec<Result<&'static str, std::io::Error>> = vec![Ok("a"), Ok("b")];
+                    res.set_body(Body::wrap_stream(futures::stream::iter(chunks)));
+                    res.start();
+                    Ok(Halt(res))
+                },
+                _ => res.error(StatusCode::NOT_FOUND, "File Not Found")
+            }
+        });
+        stack.add_error_handler(DefaultErrorHandler);
+        stack.add_response_map(|head: &mut super::ResponseHead| {
+            head.headers.insert("x-trace-id", HeaderValue::from_static("abc123"));
+        });
+
+        for (path, status) in vec![("/ok", StatusCode::OK), ("/missing", StatusCode::NOT_FOUND), ("/stream", StatusCode::OK)] {
+            let response = invoke(&stack, test_helpers::get(path)).await;
+            assert_eq!(response.status(), status, "{}", path);
+            assert_eq!(response.headers()["x-trace-id"], "abc123", "{}", path);
+        }
+    }
+
+    #[tokio::test]
+    async fn bodiless_statuses_drop_body_and_content_headers() {
+        use hyper::StatusCode;
+        use hyper::header;
+
+        let mut stack = MiddlewareStack::new();
+        stack.add_middleware(|req: &mut Request, mut res: Response| -> MiddlewareResult {
+            match req.path_without_query() {
+                "/not-modified" => res.set(StatusCode::NOT_MODIFIED),
+                "/no-content" => res.set(StatusCode::NO_CONTENT),
+                _ => res.set(StatusCode::OK)
+            };
+            res.set_header(header::CONTENT_LENGTH, header::HeaderValue::from(7));
+            res.send("changed")
+        });
+
+        for path in &["/not-modified", "/no-content"] {
+            let response = invoke(&stack, test_helpers::get(path)).await;
+            assert!(response.headers().get(header::CONTENT_LENGTH).is_none(), "{}", path);
+            assert!(response.headers().get(header::CONTENT_TYPE).is_none(), "{}", path);
+            let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+            assert!(body.is_empty(), "{}", path);
+        }
+
+        let response = invoke(&stack, test_helpers::get("/ok")).await;
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "7");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"changed");
+    }
+
+    #[tokio::test]
+    async fn streamed_body_uses_content_length() {
+        use hyper::header;
+        use log::Level;
+        use crate::{Halt, test_helpers};
+
+        test_helpers::capture_logs();
+        let mut stack = MiddlewareStack::new();
+        stack.add_middleware(|req: &mut Request, mut res: Response| -> MiddlewareResult {
+            let chunks: Vec<Result<&'static str, std::io::Error>> = match req.path_without_query() {
+                "/exact" => vec![Ok("hello "), Ok("world")],
+                _ => vec![Ok("hello")]
+            };
+            res.set_content_length(11);
+            res.set_body(Body::wrap_stream(futures::stream::iter(chunks)));
+            res.start();
+            Ok(Halt(res))
+        });
+
+        let response = invoke(&stack, test_helpers::get("/exact")).await;
+        assert_eq!(response.headers()[header::CONTENT_LENGTH], "11");
+        assert_eq!(response.headers()[header::ACCEPT_RANGES], "none");
+        assert!(response.headers().get(header::TRANSFER_ENCODING).is_none());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello world");
+
+        let response = invoke(&stack, test_helpers::get("/short")).await;
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+
+        let errors: Vec<String> = test_helpers::logs_of("nickel::middleware")
+            .into_iter()
+            .filter(|(level, _)| *level == Level::Error)
+            .map(|(_, message)| message)
+            .collect();
+        assert_eq!(errors, vec!["Streamed body of 5 bytes is shorter than its Content-Length of 11 bytes"]);
+    }
+
+    #[tokio::test]
+    async fn error_log_names_the_middleware() {
+        use async_trait::async_trait;
+        use hyper::StatusCode;
+        use log::Level;
+        use crate::{Middleware, test_helpers};
+
+        struct Flaky;
+
+        #[async_trait]
+        impl Middleware<()> for Flaky {
+            async fn invoke(&self, _: &mut Request, res: Response) -> MiddlewareResult {
+                res.error(StatusCode::SERVICE_UNAVAILABLE, "flaky middleware failed")
+            }
         }
+
+        struct Named;
+
+        #[async_trait]
+        impl Middleware<()> for Named {
+            async fn invoke(&self, _: &mut Request, res: Response) -> MiddlewareResult {
+                res.error(StatusCode::SERVICE_UNAVAILABLE, "named middleware failed")
+            }
+
+            fn name(&self) -> &str {
+                "session-loader"
+            }
+        }
+
+        test_helpers::capture_logs();
+        for middleware in vec![Box::new(Flaky) as Box<dyn Middleware<()>>, Box::new(Named)] {
+            let mut stack = MiddlewareStack::new();
+            stack.handlers.push(middleware);
+            invoke(&stack, test_helpers::get("/")).await;
+        }
+
+        let warnings: Vec<String> = test_helpers::logs_of("nickel::middleware")
+            .into_iter()
+            .filter(|(level, message)| *level == Level::Warn && message.starts_with("Error in"))
+            .map(|(_, message)| message)
+            .collect();
+        assert!(warnings.iter().any(|m| m.contains("::Flaky ")
+                                        && m.contains("flaky middleware failed")));
+        assert!(warnings.iter().any(|m| m.starts_with("Error in session-loader ")
+                                        && m.contains("named middleware failed")));
     }
 }
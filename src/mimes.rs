@@ -29,6 +29,8 @@ macro_rules! mimes {
             }
         }
 
+        // Text types declare their charset, as clients may guess otherwise.
+        // Use `Response::set_charset` for content in other encodings.
         impl From<MediaType> for HeaderValue {
             fn from(mt: MediaType) -> HeaderValue {
                 let mime_str = match mt {
@@ -38,7 +40,11 @@ macro_rules! mimes {
                         ),*
                     ),*
                 };
-                HeaderValue::from_str(mime_str).unwrap()
+                if mime_str.starts_with("text/") {
+                    HeaderValue::from_str(&format!("{}; charset=utf-8", mime_str)).unwrap()
+                } else {
+                    HeaderValue::from_str(mime_str).unwrap()
+                }
             }
         }
 
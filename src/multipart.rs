@@ -0,0 +1,205 @@
+use std::io;
+use hyper::Body;
+use hyper::body::{Bytes, Sender};
+use hyper::header::{self, HeaderName, HeaderValue};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+
+use crate::response::Response;
+use crate::util;
+
+/// Writes the parts of a streamed `multipart/*` response body, e.g.
+/// `multipart/mixed` for batch responses or `multipart/x-mixed-replace` for
+/// MJPEG streams. Obtained from `Response::multipart`.
+///
+/// Every part is framed with the response's boundary. The body only ends
+/// once `finish` was called (which writes the closing delimiter) or the
+/// writer is dropped.
+pub struct MultipartWriter {
+    sender: Sender,
+    boundary: String,
+}
+
+impl MultipartWriter {
+    /// The boundary separating the parts.
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Write a part with the given headers and body.
+    ///
+    /// Fails with `InvalidInput` for header names or values that aren't
+    /// valid in a header, e.g. a filename with a line break, which could
+    /// otherwise inject headers or boundaries. Nothing is written then.
+    ///
+    /// Fails with `BrokenPipe` if the client went away. That's a normal end
+    /// for long running streams, so it is only logged at debug level and the
+    /// caller should just stop writing.
+    pub async fn write_part(&mut self, headers: &[(&str, &str)], body: &[u8]) -> io::Result<()> {
+        let mut part = format!("--{}\r\n", self.boundary).into_bytes();
+        for &(name, value) in headers {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() || HeaderValue::from_str(value).is_err() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("invalid part header '{}'", name.escape_debug())));
+            }
+            part.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        part.extend_from_slice(b"\r\n");
+        part.extend_from_slice(body);
+        part.extend_from_slice(b"\r\n");
+        self.send(part).await
+    }
+
+    /// Write the closing delimiter and end the body.
+    pub async fn finish(mut self) -> io::Result<()> {
+        let end = format!("--{}--\r\n", self.boundary);
+        self.send(end.into_bytes()).await
+    }
+
+    async fn send(&mut self, bytes: Vec<u8>) -> io::Result<()> {
+        self.sender.send_data(Bytes::from(bytes)).await.map_err(|e| {
+            debug!("Client disconnected from multipart response: {}", e);
+            io::Error::new(io::ErrorKind::BrokenPipe, e)
+        })
+    }
+}
+
+impl<D: Send + 'static + Sync> Response<D> {
+    /// Turn the response into a streamed `multipart/<subtype>` response and
+    /// return the writer for its parts. The boundary is generated randomly and
+    /// added to the `Content-Type`.
+    ///
+    /// Fails with `InvalidInput`, leaving the response as it is, if
+    /// `subtype` isn't a token, e.g. `mixed; boundary=x`.
+    ///
+    /// The parts are only sent once the handler returned the response, so
+    /// the writer has to be moved into a spawned task.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult, Halt};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     let mut writer = res.multipart("mixed").unwrap();
+    ///     tokio::spawn(async move {
+    ///         writer.write_part(&[("Content-Type", "text/plain")], b"first").await?;
+    ///         writer.write_part(&[("Content-Type", "text/plain")], b"second").await?;
+    ///         writer.finish().await
+    ///     });
+    ///     Ok(Halt(res))
+    /// }
+    /// ```
+    pub fn multipart(&mut self, subtype: &str) -> io::Result<MultipartWriter> {
+        if !util::is_token(subtype) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("invalid multipart subtype '{}'", subtype.escape_debug())));
+        }
+
+        let boundary: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect();
+
+        let content_type = format!("multipart/{}; boundary={}", subtype, boundary);
+        // a token and an alphanumeric boundary, always a valid header value
+        let content_type = HeaderValue::from_str(&content_type).unwrap();
+        self.set_header(header::CONTENT_TYPE, content_type);
+        self.headers_mut().remove(header::CONTENT_LENGTH);
+
+        let (sender, body) = Body::channel();
+        self.set_body(body);
+        self.start();
+
+        Ok(MultipartWriter {
+            sender,
+            boundary
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use hyper::header;
+    use log::Level;
+    use crate::Response;
+    use crate::test_helpers;
+
+    #[tokio::test]
+    async fn writes_framed_parts() {
+        let mut res: Response<()> = test_helpers::response();
+        let mut writer = res.multipart("mixed").unwrap();
+        let boundary = writer.boundary().to_string();
+
+        tokio::spawn(async move {
+            writer.write_part(&[("Content-Type", "text/plain")], b"first").await.unwrap();
+            writer.write_part(&[("Content-Type", "application/json")], b"{}").await.unwrap();
+            writer.finish().await.unwrap();
+        });
+
+        assert_eq!(res.headers().get(header::CONTENT_TYPE).unwrap(),
+                   &format!("multipart/mixed; boundary={}", boundary));
+
+        let body = hyper::body::to_bytes(res.origin.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let delimiter = format!("--{}", boundary);
+
+        assert!(body.ends_with(&format!("{}--\r\n", delimiter)));
+        let parts: Vec<&str> = body.split(&delimiter[..])
+                                   .filter(|p| !p.is_empty() && *p != "--\r\n")
+                                   .collect();
+        assert_eq!(parts, vec!["\r\nContent-Type: text/plain\r\n\r\nfirst\r\n",
+                               "\r\nContent-Type: application/json\r\n\r\n{}\r\n"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_injected_headers() {
+        let mut res: Response<()> = test_helpers::response();
+        let err = res.multipart("mixed; boundary=evil").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(res.headers().get(header::CONTENT_TYPE).is_none());
+
+        let mut writer = res.multipart("form-data").unwrap();
+        let boundary = writer.boundary().to_string();
+        let disposition = "attachment; filename=\"a.txt\"\r\nContent-Type: text/html";
+        for headers in &[[("Content-Disposition", disposition)], [("Content\r\nX", "1")]] {
+            let err = writer.write_part(headers, b"<script>").await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+        tokio::spawn(writer.finish());
+
+        // nothing of the rejected parts made it into the body
+        let body = hyper::body::to_bytes(res.origin.into_body()).await.unwrap();
+        assert_eq!(body, format!("--{}--\r\n", boundary));
+    }
+
+    #[tokio::test]
+    async fn client_disconnect_ends_stream_quietly() {
+        test_helpers::capture_logs();
+
+        let mut res: Response<()> = test_helpers::response();
+        let mut writer = res.multipart("x-mixed-replace").unwrap();
+
+        // The client reads the first part and goes away
+        let mut body = res.origin.into_body();
+        let streaming = tokio::spawn(async move {
+            let frame = vec![0; 64 * 1024];
+            loop {
+                if let Err(e) = writer.write_part(&[("Content-Type", "image/jpeg")], &frame).await {
+                    return e;
+                }
+            }
+        });
+        hyper::body::HttpBody::data(&mut body).await.unwrap().unwrap();
+        drop(body);
+
+        let err = streaming.await.unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+
+        let logs = test_helpers::logs_of("nickel::multipart");
+        assert!(logs.iter().any(|(level, _)| *level == Level::Debug));
+        assert!(!logs.iter().any(|(level, _)| *level <= Level::Warn));
+    }
+}
@@ -0,0 +1,156 @@
+use hyper::header::{self, HeaderValue};
+use mime::Mime;
+use serde::Serialize;
+
+use crate::status::StatusCode;
+use crate::mimes::MediaType;
+use crate::request::{self, Request};
+use crate::response::Response;
+use crate::middleware::MiddlewareResult;
+
+enum Variant {
+    Html(String),
+    Json,
+}
+
+impl Variant {
+    fn mime(&self) -> Mime {
+        match *self {
+            Variant::Html(_) => MediaType::Html.into(),
+            Variant::Json => MediaType::Json.into(),
+        }
+    }
+}
+
+/// Data that is rendered with a template for clients asking for HTML and
+/// serialized as JSON for clients asking for JSON, so one handler can serve
+/// both browsers and API clients. Sent with `Response::send_negotiated`.
+///
+/// The variant the `Accept` header prefers is sent. Ties, e.g. for `*/*` or
+/// requests without an `Accept` header, go to the variant added first.
+/// Clients accepting neither get `406 Not Acceptable`.
+///
+/// # Examples
+/// ```{rust}
+/// use std::collections::HashMap;
+/// use nickel::{Request, Response, MiddlewareResult, Negotiated};
+///
+/// # #[allow(dead_code)]
+/// async fn user<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+///     let mut user = HashMap::new();
+///     user.insert("name", "alice");
+///     res.send_negotiated(Negotiated::new(&user, req).html("user.tpl").json()).await
+/// }
+/// ```
+pub struct Negotiated<'a, T> {
+    data: &'a T,
+    accept: Option<String>,
+    variants: Vec<Variant>,
+}
+
+impl<'a, T: Serialize> Negotiated<'a, T> {
+    /// `data`, to be sent in the variant `req` prefers.
+    pub fn new<D>(data: &'a T, req: &Request<D>) -> Negotiated<'a, T> {
+        Negotiated {
+            data,
+            accept: req.accept_header(),
+            variants: Vec::new(),
+        }
+    }
+
+    /// Renders the data with the template at `path` for HTML clients.
+    pub fn html<P: Into<String>>(mut self, path: P) -> Self {
+        self.variants.push(Variant::Html(path.into()));
+        self
+    }
+
+    /// Serializes the data as JSON for JSON clients.
+    pub fn json(mut self) -> Self {
+        self.variants.push(Variant::Json);
+        self
+    }
+
+    // The first of the variants the client likes best
+    fn choose(&self) -> Option<&Variant> {
+        let quality = |variant: &Variant| match self.accept {
+            Some(ref accept) => request::accept_quality(accept, &variant.mime()),
+            None => 1.0
+        };
+        self.variants.iter()
+                     .map(|variant| (variant, quality(variant)))
+                     .filter(|&(_, q)| q > 0.0)
+                     .fold(None, |best: Option<(&Variant, f32)>, (variant, q)| match best {
+                         Some((_, best_q)) if best_q >= q => best,
+                         _ => Some((variant, q))
+                     })
+                     .map(|(variant, _)| variant)
+    }
+}
+
+impl<D: Send + 'static + Sync> Response<D> {
+    /// Sends `negotiated` in the variant the client prefers, see
+    /// `Negotiated`. Adds `Vary: Accept`, so caches keep the variants apart.
+    pub async fn send_negotiated<T: Serialize>(mut self, negotiated: Negotiated<'_, T>) -> MiddlewareResult<D> {
+        self.add_vary(&header::ACCEPT);
+
+        match negotiated.choose() {
+            Some(Variant::Html(path)) => self.render(path.clone(), negotiated.data).await,
+            Some(Variant::Json) => match serde_json::to_string(negotiated.data) {
+                Ok(json) => {
+                    self.set_header(header::CONTENT_TYPE, HeaderValue::from(MediaType::Json));
+                    self.send(json)
+                },
+                Err(e) => self.error(StatusCode::INTERNAL_SERVER_ERROR,
+                                     format!("Failed to serialize response: {}", e))
+            },
+            None => self.error(StatusCode::NOT_ACCEPTABLE,
+                               "None of the acceptable content types can be produced")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use hyper::{header, Request as HyperRequest, StatusCode};
+    use serde_json::{json, Value};
+    use super::Negotiated;
+    use crate::MiddlewareResult;
+    use crate::test_helpers::{self, body_string, errored, halted, response};
+
+    async fn send_user(accept: Option<&str>) -> MiddlewareResult<()> {
+        let mut builder = HyperRequest::get("/users/1");
+        if let Some(accept) = accept {
+            builder = builder.header("accept", accept);
+        }
+        let req = test_helpers::request(builder);
+        let mut user = HashMap::new();
+        user.insert("name", "user");
+
+        let negotiated = Negotiated::new(&user, &req).html("examples/assets/template.tpl").json();
+        response().send_negotiated(negotiated).await
+    }
+
+    #[tokio::test]
+    async fn html_clients_get_the_rendered_template() {
+        for accept in &[Some("text/html,application/xhtml+xml,*/*;q=0.8"), Some("*/*"), None] {
+            let res = halted(send_user(*accept).await);
+            assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8", "{:?}", accept);
+            assert_eq!(res.headers()[header::VARY], "accept");
+            assert!(body_string(res).await.contains("Hello user!"), "{:?}", accept);
+        }
+    }
+
+    #[tokio::test]
+    async fn json_clients_get_the_serialized_data() {
+        for accept in &["application/json", "text/html;q=0.5, application/json"] {
+            let res = halted(send_user(Some(*accept)).await);
+            assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json", "{}", accept);
+            let body: Value = serde_json::from_str(&body_string(res).await).unwrap();
+            assert_eq!(body, json!({"name": "user"}));
+        }
+
+        let res = errored(send_user(Some("image/png")).await);
+        assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+}
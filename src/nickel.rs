@@ -1,12 +1,22 @@
-use std::net::ToSocketAddrs;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
 use std::time::Duration;
 use std::env;
+use std::mem;
 use std::error::Error as StdError;
 use crate::router::{Router, HttpRouter, Matcher};
 use crate::middleware::{MiddlewareStack, Middleware, ErrorHandler};
-use crate::server::Server;
-use crate::template_cache::ReloadPolicy;
+use crate::server::{Server, StackHandle};
+use crate::{MediaType, NickelError, Request, ResponseHead};
+use crate::request::{BodyLimits, JsonLimits, ProxySettings};
+use crate::template_cache::{HeadRender, ReloadPolicy, TemplateCache};
+#[cfg(feature = "templates")]
+use crate::template_cache::{AnyEngine, TemplateEngine};
 use hyper::{Method, StatusCode};
+use hyper::header::HeaderName;
+use mime::Mime;
+#[cfg(feature = "json")]
+use serde_json::json;
 //use hyper::net::SslServer;
 
 //pre defined middleware
@@ -31,6 +41,16 @@ pub struct Options {
     output_on_listen: bool,
     thread_count: Option<usize>,
     reload_policy: ReloadPolicy,
+    template_roots: Vec<PathBuf>,
+    head_render: HeadRender,
+    cache_key_headers: Vec<HeaderName>,
+    // Limits are reported by the config endpoint as well, see `config_json`
+    max_body_size: Option<u64>,
+    max_body_sizes: Vec<(Mime, u64)>,
+    json_limits: JsonLimits,
+    idle_timeout: Option<Duration>,
+    trusted_proxies: Vec<IpAddr>,
+    public_host: Option<String>,
 }
 
 impl Options {
@@ -56,6 +76,196 @@ impl Options {
         self.reload_policy = reload_policy;
         self
     }
+
+    /// Directories to look up relative template paths in, in order of
+    /// precedence. A template found in an earlier directory shadows one of
+    /// the same name in a later directory.
+    ///
+    /// Defaults to none, where template paths are used as given.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::Options;
+    ///
+    /// // `res.render("button.tpl", &data)` prefers app/views/button.tpl
+    /// // over components/button.tpl
+    /// let options = Options::default()
+    ///                   .template_roots(vec!["app/views", "components"]);
+    /// ```
+    pub fn template_roots<I, P>(mut self, roots: I) -> Self
+    where I: IntoIterator<Item=P>, P: Into<PathBuf> {
+        self.template_roots = roots.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Whether `Response::render` renders templates for `HEAD` requests, to
+    /// send their `Content-Length`, or skips the rendering cost.
+    ///
+    /// Defaults to `HeadRender::Skip`.
+    pub fn head_render(mut self, head_render: HeadRender) -> Self {
+        self.head_render = head_render;
+        self
+    }
+
+    /// Request headers that select between variants of a response cached
+    /// with `Response::render_cached`, e.g. `Accept-Language` for a
+    /// localized page. Their values become part of the ETag, so a cached
+    /// variant is only revalidated for requests sending the same values,
+    /// and they are listed in `Vary` so shared caches keep variants apart.
+    ///
+    /// Defaults to none.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, Options};
+    /// use nickel::hyper::header;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.options = Options::default()
+    ///                   .cache_key_headers(vec![header::ACCEPT_LANGUAGE]);
+    /// ```
+    pub fn cache_key_headers<I>(mut self, headers: I) -> Self
+    where I: IntoIterator<Item=HeaderName> {
+        self.cache_key_headers = headers.into_iter().collect();
+        self
+    }
+
+    /// The largest request body in bytes that `Request::raw_body` and the
+    /// methods built on it will read, or `None` for no limit. Larger bodies
+    /// are rejected with `413 Payload Too Large`. Content types can override
+    /// this with `max_body_size_for`, and routes with `Router::max_body`.
+    ///
+    /// Defaults to `None`.
+    pub fn max_body_size(mut self, max_body_size: Option<u64>) -> Self {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    /// Overrides `max_body_size` for request bodies with a `Content-Type` of
+    /// `media_type`, e.g. to accept large image uploads while keeping JSON
+    /// bodies small. Can be called for several types. `Router::max_body`
+    /// still takes precedence for its route.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{MediaType, Nickel, Options};
+    ///
+    /// let mut server = Nickel::new();
+    /// server.options = Options::default()
+    ///                   .max_body_size(Some(1_000_000))
+    ///                   .max_body_size_for(MediaType::Json, 64 * 1024)
+    ///                   .max_body_size_for(MediaType::Png, 10_000_000);
+    /// ```
+    pub fn max_body_size_for(mut self, media_type: MediaType, max_body_size: u64) -> Self {
+        let mime: Mime = media_type.into();
+        self.max_body_sizes.retain(|(m, _)| *m != mime);
+        self.max_body_sizes.push((mime, max_body_size));
+        self
+    }
+
+    /// Closes connections that have not sent or received anything for
+    /// `timeout`, e.g. idle keep-alive connections or clients that stopped
+    /// halfway through a request, or `None` to keep them open. Connections
+    /// stay open while a request on them is being handled or its response
+    /// streamed, however long that takes.
+    ///
+    /// Defaults to `None`.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use std::time::Duration;
+    /// use nickel::{Nickel, Options};
+    ///
+    /// let mut server = Nickel::new();
+    /// server.options = Options::default()
+    ///                   .idle_timeout(Some(Duration::from_secs(30)));
+    /// ```
+    pub fn idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// The deepest nesting of arrays and objects that `Request::json_as`
+    /// accepts, or `None` for no limit besides serde's own. Deeper bodies
+    /// are rejected with `400 Bad Request` before deserializing them, which
+    /// guards against payloads crafted to exhaust the stack or the CPU.
+    ///
+    /// Defaults to `None`.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, Options};
+    ///
+    /// let mut server = Nickel::new();
+    /// server.options = Options::default()
+    ///                   .max_json_depth(Some(16))
+    ///                   .max_json_elements(Some(1000));
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn max_json_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.json_limits.max_depth = max_depth;
+        self
+    }
+
+    /// The most elements of a single array or object in a body that
+    /// `Request::json_as` accepts, or `None` for no limit. Larger bodies are
+    /// rejected with `400 Bad Request` before deserializing them.
+    ///
+    /// Defaults to `None`.
+    #[cfg(feature = "json")]
+    pub fn max_json_elements(mut self, max_elements: Option<usize>) -> Self {
+        self.json_limits.max_elements = max_elements;
+        self
+    }
+
+    /// The addresses of the reverse proxies in front of the server, whose
+    /// `X-Forwarded-Proto` header `Request::is_secure` and `Request::url`
+    /// believe. The header
+    /// of requests from anywhere else is ignored, as any client can send it.
+    ///
+    /// Defaults to none.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, Options};
+    ///
+    /// let mut server = Nickel::new();
+    /// server.options = Options::default()
+    ///                   .trusted_proxies(vec!["127.0.0.1".parse().unwrap()]);
+    /// ```
+    pub fn trusted_proxies<I>(mut self, proxies: I) -> Self
+    where I: IntoIterator<Item=IpAddr> {
+        self.trusted_proxies = proxies.into_iter().collect();
+        self
+    }
+
+    /// The host, with an optional port, under which clients reach the
+    /// server, e.g. `shop.example.com`. `Request::url` uses it instead of
+    /// the `Host` header, which clients can set to anything, so links in
+    /// emails or OAuth redirects can't be pointed at another host.
+    ///
+    /// Defaults to `None`, using the `Host` header.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, Options};
+    ///
+    /// let mut server = Nickel::new();
+    /// server.options = Options::default()
+    ///                   .public_host(Some("shop.example.com".to_string()));
+    /// ```
+    pub fn public_host(mut self, host: Option<String>) -> Self {
+        self.public_host = host;
+        self
+    }
+
+    pub(crate) fn proxy_settings(&self) -> ProxySettings {
+        ProxySettings::new(self.trusted_proxies.clone(), self.public_host.clone())
+    }
+
+    pub(crate) fn body_limits(&self) -> BodyLimits {
+        BodyLimits::new(self.max_body_size, self.max_body_sizes.clone(), self.json_limits)
+    }
 }
 
 impl Default for Options {
@@ -64,6 +274,15 @@ impl Default for Options {
             output_on_listen: true,
             thread_count: None,
             reload_policy: ReloadPolicy::Never,
+            template_roots: Vec::new(),
+            head_render: HeadRender::Skip,
+            cache_key_headers: Vec::new(),
+            max_body_size: None,
+            max_body_sizes: Vec::new(),
+            json_limits: JsonLimits::default(),
+            idle_timeout: None,
+            trusted_proxies: Vec::new(),
+            public_host: None,
         }
     }
 }
@@ -72,8 +291,13 @@ impl Default for Options {
 /// holds all public APIs.
 pub struct Nickel<D: Sync + Send + 'static = ()> {
     middleware_stack: MiddlewareStack<D>,
+    stack_handle: StackHandle<D>,
     data: D,
     keep_alive_timeout: Option<Duration>,
+    #[cfg(feature = "json")]
+    config_endpoint: Option<String>,
+    #[cfg(feature = "templates")]
+    template_engine: Option<Box<dyn AnyEngine>>,
 
     /// Configuration options for the server.
     pub options: Options,
@@ -113,10 +337,15 @@ impl<D: Sync + Send + 'static> Nickel<D> {
 
         Nickel {
             middleware_stack: middleware_stack,
+            stack_handle: StackHandle::new(MiddlewareStack::new()),
             options: options,
             data: data,
             // Default value from nginx
             keep_alive_timeout: Some(Duration::from_secs(75)),
+            #[cfg(feature = "json")]
+            config_endpoint: None,
+            #[cfg(feature = "templates")]
+            template_engine: None,
         }
     }
 
@@ -126,6 +355,15 @@ impl<D: Sync + Send + 'static> Nickel<D> {
         Nickel::with_data_and_options(data, Options::default())
     }
 
+    /// Renders templates with `engine` instead of Mustache, e.g. to use
+    /// Handlebars or Tera syntax in `Response::render`. See
+    /// `TemplateEngine` for an example.
+    #[cfg(feature = "templates")]
+    pub fn with_template_engine<E: TemplateEngine>(mut self, engine: E) -> Nickel<D> {
+        self.template_engine = Some(Box::new(engine));
+        self
+    }
+
     /// Registers a middleware handler which will be invoked among other middleware
     /// handlers before each request. Middleware can be stacked and is invoked in the
     /// same order it was registered.
@@ -157,6 +395,12 @@ impl<D: Sync + Send + 'static> Nickel<D> {
     /// A error handler is nearly identical to a regular middleware handler with the only
     /// difference that it takes an additional error parameter or type `NickelError.
     ///
+    /// Error handlers run in reverse order of registration, the most recently
+    /// registered first. Each returns `Halt` once it has rendered the error,
+    /// or `Continue` to pass it on to the next one. The `DefaultErrorHandler`
+    /// always comes last and renders whatever is left. Use
+    /// `insert_error_handler` to run a handler at another position.
+    ///
     /// # Examples
     ///
     /// ```{rust}
@@ -189,6 +433,82 @@ impl<D: Sync + Send + 'static> Nickel<D> {
         self.middleware_stack.add_error_handler(handler);
     }
 
+    /// Registers an error handler to run at `position` among the error
+    /// handlers, 0 being the first to run. Positions past the registered
+    /// handlers run it last, but still before the `DefaultErrorHandler`.
+    ///
+    /// This lets libraries install fallbacks that run after the handlers
+    /// of the application, whenever they are registered.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, Request, NickelError, Action, Continue, Halt};
+    /// use nickel::status::StatusCode;
+    ///
+    /// fn teapots<D>(err: &mut NickelError<D>, _req: &mut Request<D>) -> Action {
+    ///     match err.stream {
+    ///         Some(ref mut res) if res.status() == StatusCode::IM_A_TEAPOT => {
+    ///             res.set_body("short and stout");
+    ///             Halt(())
+    ///         },
+    ///         _ => Continue(())
+    ///     }
+    /// }
+    ///
+    /// let mut server = Nickel::new();
+    /// // runs after all other error handlers but the default one
+    /// server.insert_error_handler(usize::MAX, teapots as fn(&mut NickelError, &mut Request) -> Action);
+    /// ```
+    pub fn insert_error_handler<T: ErrorHandler<D>>(&mut self, position: usize, handler: T) {
+        // Keep the DefaultErrorHandler registered first, and so run last
+        let handlers = self.middleware_stack.error_handler_count().saturating_sub(1);
+        self.middleware_stack.insert_error_handler(position.min(handlers), handler);
+    }
+
+    /// Registers an observer which is called with every error returned by a
+    /// middleware, before any error handler runs and regardless of which one
+    /// ends up rendering it. This is the place for reporting errors, e.g.
+    /// logging them or counting them in metrics.
+    ///
+    /// Observers only get to look at the error; responding is left to the
+    /// error handlers.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, NickelError, Request};
+    ///
+    /// let mut server = Nickel::new();
+    /// server.on_error(|err: &NickelError, req: &Request| {
+    ///     eprintln!("{} failed: {}", req.origin.uri(), err.message);
+    /// });
+    /// ```
+    pub fn on_error<F>(&mut self, observer: F)
+    where F: Fn(&NickelError<D>, &Request<D>) + Send + Sync + 'static {
+        self.middleware_stack.add_error_observer(observer);
+    }
+
+    /// Registers a transform applied to the status and headers of every
+    /// response just before it is sent, whether it was produced by a
+    /// middleware, an error handler or is streamed. This is the place for
+    /// cross-cutting changes like adding a trace header.
+    ///
+    /// Transforms run in registration order and never see the body.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, ResponseHead};
+    /// use nickel::hyper::header::HeaderValue;
+    ///
+    /// let mut server = Nickel::new();
+    /// server.map_response(|head: &mut ResponseHead| {
+    ///     head.headers.insert("x-served-by", HeaderValue::from_static("app-1"));
+    /// });
+    /// ```
+    pub fn map_response<F>(&mut self, map: F)
+    where F: Fn(&mut ResponseHead) + Send + Sync + 'static {
+        self.middleware_stack.add_response_map(map);
+    }
+
     /// Create a new middleware to serve as a router.
     ///
     ///
@@ -214,6 +534,24 @@ impl<D: Sync + Send + 'static> Nickel<D> {
 
     /// Bind and listen for connections on the given host and port.
     ///
+    /// Failing to bind, e.g. because the port is in use, returns an error
+    /// instead of panicking. It is an `io::Error` of the original kind, so
+    /// supervised apps can tell the cases apart:
+    ///
+    /// ```{rust,no_run}
+    /// use std::io;
+    /// use nickel::Nickel;
+    ///
+    /// # async fn run() {
+    /// if let Err(e) = Nickel::new().listen("127.0.0.1:6767").await {
+    ///     match e.downcast_ref::<io::Error>().map(|e| e.kind()) {
+    ///         Some(io::ErrorKind::AddrInUse) => std::process::exit(2),
+    ///         _ => panic!("server failed: {}", e)
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    ///
     /// # Examples
     /// ```rust
     /// use nickel::Nickel;
@@ -224,41 +562,158 @@ impl<D: Sync + Send + 'static> Nickel<D> {
     /// # // unblock the server so the test doesn't block forever
     /// # listening.detach();
     /// ```
-    pub async fn listen<T: ToSocketAddrs>(mut self, addr: T) -> Result<(), Box<dyn StdError>> {
-        self.middleware_stack.add_middleware(middleware! {
-            (StatusCode::NOT_FOUND, "File Not Found")
-        });
-
-        let server = Server::new(self.middleware_stack, self.options.reload_policy, self.data);
+    pub async fn listen<T: ToSocketAddrs>(self, addr: T) -> Result<(), Box<dyn StdError>> {
+        let output_on_listen = self.options.output_on_listen;
+        let keep_alive_timeout = self.keep_alive_timeout;
+        let thread_count = self.options.thread_count;
+        let server = self.into_server();
 
         let is_test_harness = env::var_os("NICKEL_TEST_HARNESS").is_some();
 
         if is_test_harness {
             // If we're under a test harness, we'll pass zero to get assigned a random
             // port. See http://doc.rust-lang.org/std/net/struct.TcpListener.html#method.bind
-            if self.options.output_on_listen {
+            if output_on_listen {
                 println!("Listening on http://{}", "localhost:0");
             }
             server.serve("localhost:0",
-                         self.keep_alive_timeout,
-                         self.options.thread_count).await?
+                         keep_alive_timeout,
+                         thread_count).await?
         } else {
             // TODO: fixme
             // if self.options.output_on_listen {
             //     println!("Listening on http://{}", addr);
             // }
             server.serve(addr,
-                         self.keep_alive_timeout,
-                         self.options.thread_count).await?
+                         keep_alive_timeout,
+                         thread_count).await?
         };
 
-        if self.options.output_on_listen {
+        if output_on_listen {
             println!("Ctrl-C to shutdown server");
         }
 
         Ok(())
     }
 
+    /// Bind and listen for connections on several addresses at once, e.g. an
+    /// IPv4 and an IPv6 address. All addresses share the same middleware
+    /// stack and server data.
+    ///
+    /// If any of the addresses cannot be bound, an error naming that address
+    /// is returned and none of the addresses are served. Like for `listen`,
+    /// it is an `io::Error` of the original kind.
+    ///
+    /// # Examples
+    /// ```{rust,no_run}
+    /// use nickel::Nickel;
+    /// use std::net::SocketAddr;
+    ///
+    /// # async fn run() {
+    /// let server = Nickel::new();
+    /// let addrs: Vec<SocketAddr> = vec!["127.0.0.1:6767".parse().unwrap(),
+    ///                                   "[::1]:6767".parse().unwrap()];
+    /// server.listen_multi(&addrs).await.expect("Failed to launch server");
+    /// # }
+    /// ```
+    pub async fn listen_multi(self, addrs: &[SocketAddr]) -> Result<(), Box<dyn StdError>> {
+        let keep_alive_timeout = self.keep_alive_timeout;
+        let thread_count = self.options.thread_count;
+        let server = self.into_server();
+
+        if env::var_os("NICKEL_TEST_HARNESS").is_some() {
+            // See `listen`, let the OS assign random ports under the test harness
+            let addrs: Vec<SocketAddr> = addrs.iter().map(|a| SocketAddr::new(a.ip(), 0)).collect();
+            server.serve_multi(&addrs, keep_alive_timeout, thread_count).await
+        } else {
+            server.serve_multi(addrs, keep_alive_timeout, thread_count).await
+        }
+    }
+
+    /// Serve the effective server configuration as JSON on `path`, e.g.
+    /// `/__config`, for troubleshooting. Requires the `json` feature. The
+    /// endpoint only exists if this is called, consider restricting it to
+    /// debug builds:
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::Nickel;
+    /// let mut server = Nickel::new();
+    ///
+    /// if cfg!(debug_assertions) {
+    ///     server.config_endpoint("/__config");
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn config_endpoint<S: Into<String>>(&mut self, path: S) {
+        self.config_endpoint = Some(path.into());
+    }
+
+    #[cfg(feature = "json")]
+    fn config_json(&self) -> serde_json::Value {
+        let reload_policy = match self.options.reload_policy {
+            ReloadPolicy::Never => json!("never"),
+            ReloadPolicy::Always => json!("always"),
+            ReloadPolicy::Periodic(period) => json!({ "periodic_secs": period.as_secs_f64() }),
+        };
+        let max_body_sizes: serde_json::Map<_, _> = self.options.max_body_sizes
+                                                        .iter()
+                                                        .map(|(mime, size)| (mime.to_string(), json!(size)))
+                                                        .collect();
+
+        json!({
+            "output_on_listen": self.options.output_on_listen,
+            "thread_count": self.options.thread_count,
+            "keep_alive_timeout_secs": self.keep_alive_timeout.map(|t| t.as_secs_f64()),
+            "idle_timeout_secs": self.options.idle_timeout.map(|t| t.as_secs_f64()),
+            "reload_policy": reload_policy,
+            "max_body_size": self.options.max_body_size,
+            "max_body_size_for": max_body_sizes,
+            "max_json_depth": self.options.json_limits.max_depth,
+            "max_json_elements": self.options.json_limits.max_elements,
+        })
+    }
+
+    /// A handle for replacing the middleware of the server once it is
+    /// running, see `StackHandle::swap`.
+    pub fn stack_handle(&self) -> StackHandle<D> {
+        self.stack_handle.clone()
+    }
+
+    // The complete middleware stack to serve, leaving an empty one behind
+    pub(crate) fn finish_stack(&mut self) -> MiddlewareStack<D> {
+        // Options can change until the server starts, so snapshot them now
+        #[cfg(feature = "json")]
+        if let Some(path) = self.config_endpoint.take() {
+            let config = self.config_json();
+            self.get(&path[..], middleware! { config.clone() });
+        }
+
+        self.middleware_stack.add_middleware(middleware! {
+            (StatusCode::NOT_FOUND, "File Not Found")
+        });
+
+        mem::replace(&mut self.middleware_stack, MiddlewareStack::new())
+    }
+
+    fn into_server(mut self) -> Server<D> {
+        let stack = self.finish_stack();
+        self.stack_handle.set(stack);
+
+        let body_limits = self.options.body_limits();
+        let proxies = self.options.proxy_settings();
+        let templates = TemplateCache::with_policy(self.options.reload_policy)
+                                      .with_roots(self.options.template_roots)
+                                      .with_head_render(self.options.head_render)
+                                      .with_cache_key_headers(self.options.cache_key_headers);
+        #[cfg(feature = "templates")]
+        let templates = match self.template_engine {
+            Some(engine) => templates.with_any_engine(engine),
+            None => templates
+        };
+        Server::new(self.stack_handle, templates, body_limits, proxies, self.options.idle_timeout, self.data)
+    }
+
     /// Set the timeout for the keep-alive loop
     ///
     /// # Performance
@@ -343,7 +798,7 @@ impl<D: Sync + Send + 'static> Nickel<D> {
 
 #[cfg(test)]
 mod tests {
-    use crate::Nickel;
+    use crate::{Nickel, Options};
     use std::str::FromStr;
     use std::net::SocketAddr;
 
@@ -368,4 +823,233 @@ mod tests {
         assert_eq!(server.socket().ip().to_string(), "127.0.0.1");
         server.detach();
     }
+
+    #[tokio::test]
+    async fn can_listen_on_multiple_addresses() {
+        use crate::HttpRouter;
+        use std::net::TcpListener;
+
+        // Grab two free ports from the OS
+        let addrs: Vec<SocketAddr> = (0..2).map(|_| {
+            TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+        }).collect();
+
+        let mut server = Nickel::with_options(Options::default().output_on_listen(false));
+        server.get("/", middleware!("Hello World"));
+
+        let client = hyper::Client::new();
+        let requests = async {
+            let mut bodies = vec![];
+            for addr in &addrs {
+                let uri = format!("http://{}/", addr).parse().unwrap();
+                let res = client.get(uri).await.unwrap();
+                let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+                bodies.push(String::from_utf8(body.to_vec()).unwrap());
+            }
+            bodies
+        };
+
+        tokio::select! {
+            biased;
+            result = server.listen_multi(&addrs) => panic!("server stopped: {:?}", result.err()),
+            bodies = requests => assert_eq!(bodies, vec!["Hello World", "Hello World"]),
+        }
+    }
+
+    async fn get_from(server: Nickel, path: &str) -> (hyper::StatusCode, String) {
+        use std::net::TcpListener;
+
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let addrs = [addr];
+        let request = async {
+            let uri = format!("http://{}{}", addr, path).parse().unwrap();
+            let res = hyper::Client::new().get(uri).await.unwrap();
+            let status = res.status();
+            let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        };
+
+        tokio::select! {
+            biased;
+            result = server.listen_multi(&addrs) => panic!("server stopped: {:?}", result.err()),
+            response = request => response,
+        }
+    }
+
+    #[tokio::test]
+    async fn binding_a_used_port_is_an_error() {
+        use std::io;
+        use std::net::TcpListener;
+
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+
+        let server = Nickel::with_options(Options::default().output_on_listen(false));
+        let err = server.listen(addr).await.unwrap_err();
+        let err = err.downcast_ref::<io::Error>().expect("bind errors are io::Errors");
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(err.to_string().contains(&addr.to_string()), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn error_handlers_pass_errors_on() {
+        use std::sync::{Arc, Mutex};
+        use hyper::StatusCode;
+        use crate::{Action, Continue, ErrorHandler, Halt, HttpRouter, NickelError, Request};
+
+        struct Recorder {
+            name: &'static str,
+            handles: StatusCode,
+            seen: Arc<Mutex<Vec<&'static str>>>
+        }
+
+        impl ErrorHandler<()> for Recorder {
+            fn handle_error(&self, err: &mut NickelError<()>, _: &mut Request<()>) -> Action {
+                self.seen.lock().unwrap().push(self.name);
+                match err.stream {
+                    Some(ref mut res) if res.status() == self.handles => {
+                        res.set_body(self.name);
+                        Halt(())
+                    },
+                    _ => Continue(())
+                }
+            }
+        }
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let app = || {
+            let recorder = |name, handles| Recorder { name, handles, seen: seen.clone() };
+            let mut server = Nickel::with_options(Options::default().output_on_listen(false));
+            server.get("/teapot", middleware!((StatusCode::IM_A_TEAPOT, "teapot")));
+            server.get("/broken", middleware!((StatusCode::INTERNAL_SERVER_ERROR, "broken")));
+            server.handle_error(recorder("teapots", StatusCode::IM_A_TEAPOT));
+            server.handle_error(recorder("gateway", StatusCode::BAD_GATEWAY));
+            server.insert_error_handler(0, recorder("first", StatusCode::GONE));
+            server.insert_error_handler(100, recorder("last", StatusCode::IM_A_TEAPOT));
+            server
+        };
+
+        let (status, body) = get_from(app(), "/teapot").await;
+        assert_eq!((status, &body[..]), (StatusCode::IM_A_TEAPOT, "teapots"));
+        assert_eq!(*seen.lock().unwrap(), vec!["first", "gateway", "teapots"]);
+
+        seen.lock().unwrap().clear();
+        let (status, body) = get_from(app(), "/broken").await;
+        assert_eq!((status, &body[..]), (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"));
+        assert_eq!(*seen.lock().unwrap(), vec!["first", "gateway", "teapots", "last"]);
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn config_endpoint_reports_options() {
+        use std::time::Duration;
+
+        let mut server = Nickel::with_options(Options::default()
+                                                  .output_on_listen(false)
+                                                  .thread_count(Some(4))
+                                                  .max_body_size(Some(1_000_000))
+                                                  .max_body_size_for(crate::MediaType::Json, 64 * 1024)
+                                                  .max_json_depth(Some(16))
+                                                  .max_json_elements(Some(1000)));
+        server.keep_alive_timeout(Some(Duration::from_secs(30)));
+        server.config_endpoint("/__config");
+
+        let (status, body) = get_from(server, "/__config").await;
+        let config: serde_json::Value = serde_json::from_str(&body).unwrap();
+
+        assert_eq!(status, hyper::StatusCode::OK);
+        assert_eq!(config["thread_count"], 4);
+        assert_eq!(config["keep_alive_timeout_secs"], 30.0);
+        assert_eq!(config["reload_policy"], "never");
+        assert_eq!(config["max_body_size"], 1_000_000);
+        assert_eq!(config["max_body_size_for"]["application/json"], 64 * 1024);
+        assert_eq!(config["max_json_depth"], 16);
+        assert_eq!(config["max_json_elements"], 1000);
+    }
+
+    #[tokio::test]
+    async fn body_limits_depend_on_content_type() {
+        use hyper::{Request as HyperRequest, StatusCode};
+        use crate::MediaType;
+        use crate::test_helpers;
+
+        let limits = Options::default()
+                         .max_body_size(Some(1024))
+                         .max_body_size_for(MediaType::Json, 16)
+                         .max_body_size_for(MediaType::Png, 1_000_000)
+                         .body_limits();
+        let upload = |content_type: &str, size: usize| {
+            let builder = HyperRequest::post("/upload").header("content-type", content_type);
+            let mut req = test_helpers::request_with_body(builder, vec![b'x'; size]);
+            req.set_body_limit(limits.for_request(&req));
+            req
+        };
+
+        let mut json = upload("application/json; charset=utf-8", 32);
+        assert_eq!(json.raw_body().await.unwrap_err().0, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let mut image = upload("image/png", 500_000);
+        assert_eq!(image.raw_body().await.unwrap().len(), 500_000);
+
+        let mut text = upload("text/plain", 2048);
+        assert_eq!(text.raw_body().await.unwrap_err().0, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(upload("text/plain", 1024).raw_body().await.unwrap().len(), 1024);
+    }
+
+    #[tokio::test]
+    async fn config_endpoint_absent_by_default() {
+        let server = Nickel::with_options(Options::default().output_on_listen(false));
+
+        let (status, _) = get_from(server, "/__config").await;
+        assert_eq!(status, hyper::StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn swapped_stack_serves_next_request() {
+        use std::net::TcpListener;
+        use crate::HttpRouter;
+
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let addrs = [addr];
+        let mut server = Nickel::with_options(Options::default().output_on_listen(false));
+        server.get("/", middleware!("version 1"));
+        let handle = server.stack_handle();
+
+        let requests = async {
+            let get = || async {
+                let uri = format!("http://{}/", addr).parse().unwrap();
+                let res = hyper::Client::new().get(uri).await.unwrap();
+                (res.status(), hyper::body::to_bytes(res.into_body()).await.unwrap())
+            };
+            let before = get().await;
+
+            let mut reloaded = Nickel::new();
+            reloaded.get("/", middleware!("version 2"));
+            handle.swap(reloaded);
+            (before, get().await)
+        };
+
+        tokio::select! {
+            biased;
+            result = server.listen_multi(&addrs) => panic!("server stopped: {:?}", result.err()),
+            (before, after) = requests => {
+                assert_eq!(&before.1[..], b"version 1");
+                assert_eq!(after.0, hyper::StatusCode::OK);
+                assert_eq!(&after.1[..], b"version 2");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn listen_multi_reports_failing_address() {
+        use std::net::TcpListener;
+
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = taken.local_addr().unwrap();
+
+        let server = Nickel::with_options(Options::default().output_on_listen(false));
+        let err = server.listen_multi(&[addr]).await.unwrap_err();
+
+        assert!(err.to_string().contains(&addr.to_string()), "{}", err);
+    }
 }
@@ -0,0 +1,118 @@
+use std::io::Read;
+use flate2::read::GzDecoder;
+use hyper::header::{self, HeaderValue};
+
+use crate::status::StatusCode;
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::MiddlewareResult;
+use crate::responder::Responder;
+
+/// A body that is already gzip compressed, e.g. a cached rendering of a page.
+///
+/// Clients accepting gzip get the bytes as they are, with `Content-Encoding`
+/// and `Content-Length` set accordingly. Other clients get the decompressed
+/// content. Either way `Vary: Accept-Encoding` is added so caches keep the
+/// two apart.
+///
+/// The `Content-Type` is not derived from the bytes and should be set on the
+/// response beforehand, it falls back to `application/octet-stream`.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::{Request, Response, MiddlewareResult, MediaType, PrecompressedBody};
+///
+/// # #[allow(dead_code)]
+/// fn cached_page<'mw>(req: &mut Request, mut res: Response) -> MiddlewareResult {
+///     let gzipped: Vec<u8> = load_from_cache();
+///     res.set(MediaType::Html);
+///     res.send(PrecompressedBody::gzip(gzipped, req))
+/// }
+/// # fn load_from_cache() -> Vec<u8> { vec![] }
+/// ```
+pub struct PrecompressedBody {
+    gzipped: Vec<u8>,
+    accepts_gzip: bool,
+}
+
+impl PrecompressedBody {
+    /// Gzip compressed `bytes`, to be sent in response to `req`.
+    pub fn gzip<B: Into<Vec<u8>>, D>(bytes: B, req: &Request<D>) -> PrecompressedBody {
+        PrecompressedBody {
+            gzipped: bytes.into(),
+            accepts_gzip: req.accepts_encoding("gzip"),
+        }
+    }
+}
+
+impl<D: Send + 'static + Sync> Responder<D> for PrecompressedBody {
+    fn respond(self, mut res: Response<D>) -> MiddlewareResult<D> {
+        res.add_vary(&header::ACCEPT_ENCODING);
+
+        if self.accepts_gzip {
+            res.set_header(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+            res.set_header(header::CONTENT_LENGTH, HeaderValue::from(self.gzipped.len()));
+            return res.send(self.gzipped);
+        }
+
+        let mut body = Vec::new();
+        if let Err(e) = GzDecoder::new(&self.gzipped[..]).read_to_end(&mut body) {
+            return res.error(StatusCode::INTERNAL_SERVER_ERROR,
+                             format!("Failed to decompress precompressed body: {}", e));
+        }
+        res.set_header(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+        res.send(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use hyper::{Request as HyperRequest, StatusCode};
+    use hyper::body;
+    use hyper::header;
+    use super::PrecompressedBody;
+    use crate::test_helpers::{self, body_string, errored, halted, response};
+
+    fn gzip(content: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_gzipped_bytes_to_capable_clients() {
+        let gzipped = gzip("<h1>cached</h1>");
+        let req = test_helpers::request(HyperRequest::get("/").header("accept-encoding", "br;q=1.0, gzip;q=0.8"));
+
+        let res = halted(response().send(PrecompressedBody::gzip(gzipped.clone(), &req)));
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], gzipped.len().to_string().as_str());
+        assert_eq!(res.headers()[header::VARY], "accept-encoding");
+        let sent = body::to_bytes(res.origin.into_body()).await.unwrap();
+        assert_eq!(&sent[..], &gzipped[..]);
+    }
+
+    #[tokio::test]
+    async fn decompresses_for_other_clients() {
+        let gzipped = gzip("<h1>cached</h1>");
+        let refusing = test_helpers::request(HyperRequest::get("/").header("accept-encoding", "*, gzip;q=0"));
+
+        for req in &[test_helpers::get("/"), refusing] {
+            let res = halted(response().send(PrecompressedBody::gzip(gzipped.clone(), req)));
+            assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+            assert_eq!(res.headers()[header::CONTENT_LENGTH], "15");
+            assert_eq!(res.headers()[header::VARY], "accept-encoding");
+            assert_eq!(body_string(res).await, "<h1>cached</h1>");
+        }
+    }
+
+    #[test]
+    fn invalid_gzip_is_an_error() {
+        let req = test_helpers::get("/");
+        let res = errored(response().send(PrecompressedBody::gzip(&b"not gzip"[..], &req)));
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,218 @@
+use hyper::header::{self, HeaderValue};
+use mime::Mime;
+use serde_json::{json, Map, Value};
+
+use crate::status::StatusCode;
+use crate::request::{self, Request};
+use crate::response::Response;
+use crate::middleware::{Action, Continue, ErrorHandler, Halt, MiddlewareResult};
+use crate::nickel_error::NickelError;
+use crate::responder::Responder;
+
+const PROBLEM_JSON: &str = "application/problem+json";
+
+/// An RFC 7807 problem details object, responded with as
+/// `application/problem+json`.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::{Request, Response, MiddlewareResult, Problem};
+/// use nickel::status::StatusCode;
+///
+/// # #[allow(dead_code)]
+/// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+///     res.send(Problem::new(StatusCode::FORBIDDEN)
+///                  .type_uri("https://example.com/probs/out-of-credit")
+///                  .title("You do not have enough credit.")
+///                  .detail("Your current balance is 30, but that costs 50.")
+///                  .instance("/account/12345/msgs/abc"))
+/// }
+/// ```
+pub struct Problem {
+    status: StatusCode,
+    type_uri: Option<String>,
+    title: Option<String>,
+    detail: Option<String>,
+    instance: Option<String>,
+}
+
+impl Problem {
+    /// A problem with `status`. Without a `type_uri` it is `about:blank`,
+    /// and the title defaults to the status' reason phrase.
+    pub fn new(status: StatusCode) -> Problem {
+        Problem {
+            status,
+            type_uri: None,
+            title: None,
+            detail: None,
+            instance: None,
+        }
+    }
+
+    /// The URI identifying the problem type.
+    pub fn type_uri<S: Into<String>>(mut self, type_uri: S) -> Problem {
+        self.type_uri = Some(type_uri.into());
+        self
+    }
+
+    /// A short summary of the problem type.
+    pub fn title<S: Into<String>>(mut self, title: S) -> Problem {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// An explanation specific to this occurrence of the problem.
+    pub fn detail<S: Into<String>>(mut self, detail: S) -> Problem {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// A URI reference identifying this occurrence of the problem.
+    pub fn instance<S: Into<String>>(mut self, instance: S) -> Problem {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    fn to_json(&self) -> Value {
+        let mut object = Map::new();
+        object.insert("type".to_string(), json!(self.type_uri.as_deref().unwrap_or("about:blank")));
+        let title = match self.title {
+            Some(ref title) => title.as_str(),
+            None => self.status.canonical_reason().unwrap_or("")
+        };
+        object.insert("title".to_string(), json!(title));
+        object.insert("status".to_string(), json!(self.status.as_u16()));
+        if let Some(ref detail) = self.detail {
+            object.insert("detail".to_string(), json!(detail));
+        }
+        if let Some(ref instance) = self.instance {
+            object.insert("instance".to_string(), json!(instance));
+        }
+        Value::Object(object)
+    }
+
+    fn write_to<D: Send + 'static + Sync>(&self, res: &mut Response<D>) {
+        res.set(self.status);
+        res.set_header(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        res.set_body(self.to_json().to_string());
+    }
+}
+
+impl<D: Send + 'static + Sync> Responder<D> for Problem {
+    fn respond(self, mut res: Response<D>) -> MiddlewareResult<D> {
+        self.write_to(&mut res);
+        res.start();
+        Ok(Halt(res))
+    }
+}
+
+/// Renders errors as problem+json for clients preferring JSON over HTML
+/// according to their `Accept` header, leaving all others to the next error
+/// handler.
+///
+/// The error message becomes the `detail` of client errors (4xx). It is left
+/// out for server errors, where it tends to reveal internals.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::{Nickel, ProblemErrorHandler};
+///
+/// let mut server = Nickel::new();
+/// server.handle_error(ProblemErrorHandler);
+/// ```
+#[derive(Clone, Copy)]
+pub struct ProblemErrorHandler;
+
+impl<D: Send + 'static + Sync> ErrorHandler<D> for ProblemErrorHandler {
+    fn handle_error(&self, err: &mut NickelError<D>, req: &mut Request<D>) -> Action {
+        if !prefers_json(req) {
+            return Continue(());
+        }
+
+        if let Some(ref mut res) = err.stream {
+            let status = res.status();
+            let mut problem = Problem::new(status).instance(req.path_without_query());
+            if status.is_client_error() {
+                problem = problem.detail(err.message.to_string());
+            }
+            problem.write_to(res);
+            return Halt(())
+        }
+
+        Continue(())
+    }
+}
+
+fn prefers_json<D>(req: &Request<D>) -> bool {
+    let accept = match req.accept_header() {
+        Some(accept) => accept,
+        None => return false
+    };
+
+    let quality = |mime: &str| request::accept_quality(&accept, &mime.parse::<Mime>().unwrap());
+    let json = quality(PROBLEM_JSON).max(quality("application/json"));
+    json > 0.0 && json > quality("text/html")
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Request as HyperRequest;
+    use hyper::header;
+    use serde_json::{json, Value};
+    use super::{Problem, ProblemErrorHandler};
+    use crate::{Action, ErrorHandler};
+    use crate::status::StatusCode;
+    use crate::test_helpers::{self, body_string, halted, response};
+
+    #[tokio::test]
+    async fn responds_with_problem_json() {
+        let res = halted(response().send(Problem::new(StatusCode::BAD_REQUEST)
+                                             .type_uri("https://example.com/probs/invalid-age")
+                                             .detail("age must be positive")
+                                             .instance("/users/42")));
+
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/problem+json");
+        let body: Value = serde_json::from_str(&body_string(res).await).unwrap();
+        assert_eq!(body, json!({
+            "type": "https://example.com/probs/invalid-age",
+            "title": "Bad Request",
+            "status": 400,
+            "detail": "age must be positive",
+            "instance": "/users/42"
+        }));
+    }
+
+    #[tokio::test]
+    async fn renders_errors_for_json_clients() {
+        let mut req = test_helpers::request(HyperRequest::get("/users?id=x").header("accept", "application/json"));
+        let mut err = response().error(StatusCode::BAD_REQUEST, "id must be a number").err().unwrap();
+
+        match ProblemErrorHandler.handle_error(&mut err, &mut req) {
+            Action::Halt(()) => {},
+            Action::Continue(()) => panic!("expected the error to be rendered")
+        }
+        let res = err.stream.unwrap();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/problem+json");
+        let body: Value = serde_json::from_str(&body_string(res).await).unwrap();
+        assert_eq!(body, json!({
+            "type": "about:blank",
+            "title": "Bad Request",
+            "status": 400,
+            "detail": "id must be a number",
+            "instance": "/users"
+        }));
+    }
+
+    #[test]
+    fn leaves_html_clients_alone() {
+        for accept in &["text/html, application/json;q=0.9", ""] {
+            let mut req = test_helpers::request(HyperRequest::get("/").header("accept", *accept));
+            let mut err = response().error(StatusCode::BAD_REQUEST, "bad").err().unwrap();
+            match ProblemErrorHandler.handle_error(&mut err, &mut req) {
+                Action::Continue(()) => {},
+                Action::Halt(()) => panic!("expected {:?} to be left to other handlers", accept)
+            }
+        }
+    }
+}
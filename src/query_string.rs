@@ -1,4 +1,12 @@
+use std::collections::hash_map;
+use std::collections::HashMap;
+use std::str::FromStr;
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
+use serde::de::value::{Error, SeqDeserializer, StrDeserializer};
+use serde::forward_to_deserialize_any;
+
 use crate::request::Request;
+use crate::status::StatusCode;
 use crate::urlencoded::{Query, parse_uri};
 
 // TODO: migration cleanup - Extensible does not support ShareMap, but TypeMap is not Sync+Send
@@ -32,6 +40,44 @@ pub trait QueryString {
     /// }
     /// ```
     fn query(&mut self) -> Query;
+
+    /// Decodes the URL query string into `T`.
+    ///
+    /// Values are parsed into the type of the field they end up in. Fields
+    /// holding a `Vec` collect all values of a repeated key, other fields
+    /// take the first one. Absent `Option` fields are `None`, absent `Vec`
+    /// fields need `#[serde(default)]` to be empty rather than missing.
+    ///
+    /// A missing required field or a value not parsing as its field's type
+    /// is a `400 Bad Request`, naming the offending field.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// #[macro_use] extern crate nickel;
+    /// #[macro_use] extern crate serde_derive;
+    /// use nickel::{Nickel, HttpRouter, QueryString};
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Search {
+    ///     q: String,
+    ///     page: Option<u32>,
+    ///     #[serde(default)]
+    ///     tag: Vec<String>,
+    /// }
+    ///
+    /// fn main() {
+    ///     let mut server = Nickel::new();
+    ///     server.get("/search", middleware! { |req, res|
+    ///         let search = try_with!(res, req.query_as::<Search>());
+    ///         format!("{} (page {}) in {:?}", search.q, search.page.unwrap_or(1), search.tag)
+    ///     });
+    /// }
+    /// ```
+    fn query_as<T: DeserializeOwned>(&mut self) -> Result<T, (StatusCode, String)> {
+        let query = self.query();
+        T::deserialize(QueryDeserializer(query.map()))
+            .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid query string: {}", e)))
+    }
 }
 
 impl<D> QueryString for Request<D> {
@@ -43,3 +89,180 @@ impl<D> QueryString for Request<D> {
         //     .expect("Bug: QueryStringParser returned None")
     }
 }
+
+// Decodes a parsed query, see `QueryString::query_as`.
+struct QueryDeserializer<'a>(&'a HashMap<String, Vec<String>>);
+
+impl<'de, 'a> de::Deserializer<'de> for QueryDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(QueryMap { entries: self.0.iter(), values: None })
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct QueryMap<'a> {
+    entries: hash_map::Iter<'a, String, Vec<String>>,
+    values: Option<Values<'a>>,
+}
+
+impl<'de, 'a> MapAccess<'de> for QueryMap<'a> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, values)) => {
+                self.values = Some(Values { key, values });
+                let key: StrDeserializer<'_, Error> = key.as_str().into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.values.take() {
+            Some(values) => seed.deserialize(values),
+            None => Err(de::Error::custom("value requested before key"))
+        }
+    }
+}
+
+// The values of one key, decoded as a sequence for `Vec` fields and as the
+// first value otherwise.
+struct Values<'a> {
+    key: &'a str,
+    values: &'a [String],
+}
+
+impl<'a> Values<'a> {
+    fn first(&self) -> &'a str {
+        self.values.first().map(|s| &**s).unwrap_or("")
+    }
+
+    fn parse<T: FromStr>(&self) -> Result<T, Error> where T::Err: std::fmt::Display {
+        self.first().parse().map_err(|e| {
+            de::Error::custom(format_args!("invalid value {:?} for field `{}`: {}", self.first(), self.key, e))
+        })
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Values<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_str(self.first())
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let key = self.key;
+        let values = self.values.iter().map(|value| Values { key, values: std::slice::from_ref(value) });
+        visitor.visit_seq(SeqDeserializer::new(values))
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(self,
+                                         _name: &'static str,
+                                         _variants: &'static [&'static str],
+                                         visitor: V) -> Result<V::Value, Error> {
+        let variant: StrDeserializer<'_, Error> = self.first().into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    forward_to_deserialize_any! {
+        i128 u128 str string bytes byte_buf unit unit_struct tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de, 'a> IntoDeserializer<'de, Error> for Values<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+#[test]
+fn parses_query_of_request() {
+    let mut req = crate::test_helpers::get("/search?q=nickel&tag=web&tag=rust");
+    let query = req.query();
+
+    assert_eq!(query.get("q"), Some("nickel"));
+    assert_eq!(query.all("tag"), Some(&["web".to_string(), "rust".to_string()][..]));
+    assert_eq!(query.get("page"), None);
+}
+
+#[test]
+fn decodes_query_into_struct() {
+    #[derive(Debug, PartialEq, serde_derive::Deserialize)]
+    struct Search {
+        q: String,
+        page: Option<u32>,
+        exact: bool,
+        tag: Vec<String>,
+        lang: Option<String>,
+    }
+
+    let mut req = crate::test_helpers::get("/search?q=nickel&page=2&exact=true&tag=web&tag=rust");
+    assert_eq!(req.query_as::<Search>().unwrap(), Search {
+        q: "nickel".to_string(),
+        page: Some(2),
+        exact: true,
+        tag: vec!["web".to_string(), "rust".to_string()],
+        lang: None,
+    });
+
+    let mut req = crate::test_helpers::get("/search?q=nickel&exact=false&tag=web");
+    let search = req.query_as::<Search>().unwrap();
+    assert_eq!(search.page, None);
+    assert_eq!(search.tag, vec!["web".to_string()]);
+
+    let mut req = crate::test_helpers::get("/search?page=2&exact=true");
+    let (status, message) = req.query_as::<Search>().unwrap_err();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(message.contains("missing field `q`"), "{}", message);
+
+    let mut req = crate::test_helpers::get("/search?q=nickel&page=two&exact=true");
+    let (status, message) = req.query_as::<Search>().unwrap_err();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(message.contains("`page`"), "{}", message);
+}
@@ -0,0 +1,174 @@
+use hyper::Method;
+use hyper::header::{self, HeaderValue};
+
+use crate::status::StatusCode;
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::MiddlewareResult;
+use crate::responder::Responder;
+
+/// The part of a body of `len` bytes a `Range` header asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ByteRange {
+    /// The whole body, for requests without a usable `Range` header.
+    Full,
+    /// The bytes from `start` to `end`, inclusive.
+    Partial(u64, u64),
+    /// A range outside of the body.
+    Unsatisfiable,
+    /// A `bytes` range that doesn't parse, like `bytes=abc` or `bytes=5-2`.
+    Malformed,
+}
+
+impl ByteRange {
+    // Only single ranges are supported. Multiple ranges and units other
+    // than bytes are ignored, which lets the whole body be sent.
+    pub(crate) fn parse(range: Option<&str>, len: u64) -> ByteRange {
+        let spec = match range.and_then(|r| r.trim().strip_prefix("bytes=")) {
+            Some(spec) if !spec.contains(',') => spec.trim(),
+            _ => return ByteRange::Full
+        };
+        let (first, last) = match spec.find('-') {
+            Some(i) => (&spec[..i], &spec[i + 1..]),
+            None => return ByteRange::Malformed
+        };
+
+        match (first.parse::<u64>(), last.parse::<u64>()) {
+            // bytes=-500, the last 500 bytes
+            _ if first.is_empty() => match last.parse::<u64>() {
+                Ok(0) => ByteRange::Unsatisfiable,
+                Ok(_) if len == 0 => ByteRange::Unsatisfiable,
+                Ok(suffix) => ByteRange::Partial(len.saturating_sub(suffix), len - 1),
+                Err(_) => ByteRange::Malformed
+            },
+            (Ok(start), Ok(end)) if start > end => ByteRange::Malformed,
+            (Ok(_), Err(_)) if !last.is_empty() => ByteRange::Malformed,
+            (Ok(start), _) if start >= len => ByteRange::Unsatisfiable,
+            // bytes=500-
+            (Ok(start), Err(_)) => ByteRange::Partial(start, len - 1),
+            (Ok(start), Ok(end)) => ByteRange::Partial(start, end.min(len - 1)),
+            (Err(_), _) => ByteRange::Malformed
+        }
+    }
+
+    // The range `req` asks for, ignored for methods other than GET and HEAD
+    pub(crate) fn of_request<D>(req: &Request<D>, len: u64) -> ByteRange {
+        let method = req.origin.method();
+        if method != Method::GET && method != Method::HEAD {
+            return ByteRange::Full;
+        }
+        ByteRange::parse(req.origin.headers().get(header::RANGE).and_then(|v| v.to_str().ok()), len)
+    }
+}
+
+/// An in-memory body, e.g. a cached asset, that is sent in parts when the
+/// request asks for a byte range. A satisfiable `Range` is answered with
+/// `206 Partial Content` and the matching `Content-Range`, a malformed one or
+/// one beyond the end of the body with `416 Range Not Satisfiable`. Requests
+/// without a `Range` header, or with several ranges, get the whole body.
+///
+/// The `Content-Type` should be set on the response beforehand, it falls
+/// back to `application/octet-stream`.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::{Request, Response, MiddlewareResult, MediaType, RangedBody};
+///
+/// # #[allow(dead_code)]
+/// fn cached_video<'mw>(req: &mut Request, mut res: Response) -> MiddlewareResult {
+///     let video: Vec<u8> = load_from_cache();
+///     res.set(MediaType::Mp4);
+///     res.send(RangedBody::new(video, req))
+/// }
+/// # fn load_from_cache() -> Vec<u8> { vec![] }
+/// ```
+pub struct RangedBody {
+    bytes: Vec<u8>,
+    range: ByteRange,
+}
+
+impl RangedBody {
+    /// `bytes`, to be sent in response to `req`.
+    pub fn new<B: Into<Vec<u8>>, D>(bytes: B, req: &Request<D>) -> RangedBody {
+        let bytes = bytes.into();
+        let range = ByteRange::of_request(req, bytes.len() as u64);
+        RangedBody { bytes, range }
+    }
+}
+
+impl<D: Send + 'static + Sync> Responder<D> for RangedBody {
+    fn respond(self, mut res: Response<D>) -> MiddlewareResult<D> {
+        res.set_header(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        let len = self.bytes.len();
+
+        match self.range {
+            ByteRange::Full => res.send(self.bytes),
+            ByteRange::Partial(start, end) => {
+                res.set(StatusCode::PARTIAL_CONTENT);
+                let content_range = format!("bytes {}-{}/{}", start, end, len);
+                res.set_header(header::CONTENT_RANGE, HeaderValue::from_str(&content_range).unwrap());
+                res.send(self.bytes[start as usize..=end as usize].to_vec())
+            },
+            ByteRange::Unsatisfiable | ByteRange::Malformed => {
+                let content_range = format!("bytes */{}", len);
+                res.set_header(header::CONTENT_RANGE, HeaderValue::from_str(&content_range).unwrap());
+                res.abort(StatusCode::RANGE_NOT_SATISFIABLE)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{header, Request as HyperRequest, StatusCode};
+    use super::{ByteRange, RangedBody};
+    use crate::test_helpers::{self, body_string, halted, response};
+
+    #[test]
+    fn parses_single_ranges() {
+        let parse = |range| ByteRange::parse(Some(range), 100);
+
+        assert_eq!(parse("bytes=0-9"), ByteRange::Partial(0, 9));
+        assert_eq!(parse("bytes=90-"), ByteRange::Partial(90, 99));
+        assert_eq!(parse("bytes=-10"), ByteRange::Partial(90, 99));
+        assert_eq!(parse("bytes=-500"), ByteRange::Partial(0, 99));
+        assert_eq!(parse("bytes=50-500"), ByteRange::Partial(50, 99));
+        assert_eq!(parse("bytes=100-"), ByteRange::Unsatisfiable);
+        assert_eq!(parse("bytes=-0"), ByteRange::Unsatisfiable);
+        assert_eq!(parse("bytes=10-5"), ByteRange::Malformed);
+        assert_eq!(parse("bytes=abc"), ByteRange::Malformed);
+        assert_eq!(parse("bytes=a-5"), ByteRange::Malformed);
+        assert_eq!(parse("bytes=5-b"), ByteRange::Malformed);
+        assert_eq!(parse("bytes=-b"), ByteRange::Malformed);
+        assert_eq!(parse("bytes=0-1,5-6"), ByteRange::Full);
+        assert_eq!(parse("items=0-1"), ByteRange::Full);
+        assert_eq!(ByteRange::parse(None, 100), ByteRange::Full);
+        assert_eq!(ByteRange::parse(Some("bytes=0-"), 0), ByteRange::Unsatisfiable);
+    }
+
+    #[tokio::test]
+    async fn slices_in_memory_bodies() {
+        let req = test_helpers::request(HyperRequest::get("/").header("range", "bytes=6-10"));
+        let res = halted(response().send(RangedBody::new("hello world", &req)));
+
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 6-10/11");
+        assert_eq!(res.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(body_string(res).await, "world");
+
+        let res = halted(response().send(RangedBody::new("hello world", &test_helpers::get("/"))));
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::CONTENT_RANGE).is_none());
+        assert_eq!(body_string(res).await, "hello world");
+    }
+
+    #[tokio::test]
+    async fn rejects_unsatisfiable_ranges() {
+        let req = test_helpers::request(HyperRequest::get("/").header("range", "bytes=20-30"));
+        let res = halted(response().send(RangedBody::new("hello world", &req)));
+
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes */11");
+        assert_eq!(body_string(res).await, "");
+    }
+}
@@ -1,18 +1,23 @@
 use crate::router::RouteResult;
+use crate::middleware::{ResponseHook, ResponseSummary};
 
 // The plugin crate doesn't play well with async
 //use plugin::{Extensible, Pluggable};
 
-use typemap::{ShareMap, TypeMap};
-use hyper::{Body, Request as HyperRequest, StatusCode};
-use hyper::body::{self, Bytes};
+use typemap::{Key, ShareMap, TypeMap};
+use hyper::{Body, Method, Request as HyperRequest, StatusCode};
+use hyper::body::{Bytes, HttpBody};
 use hyper::header;
+#[cfg(feature = "json")]
 use serde::Deserialize;
-use serde_json;
 use std::mem;
-use std::net::SocketAddr;
-use std::sync::Arc;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use futures::StreamExt;
 use crate::urlencoded::{self, Params};
+use crate::mimes::MediaType;
+use mime::Mime;
 
 /// A container for all the request data.
 pub struct Request<D = ()> {
@@ -22,13 +27,28 @@ pub struct Request<D = ()> {
 
     pub route_result: Option<RouteResult>,
 
+    allowed_methods: Vec<Method>,
+
     map: ShareMap,
 
     data: Arc<D>,
 
     remote_addr: Option<SocketAddr>,
 
+    proxies: Option<Arc<ProxySettings>>,
+
     raw_body_cache: Option<Bytes>,
+
+    body_limit: Option<u64>,
+
+    json_limits: JsonLimits,
+
+    bytes_in: Arc<AtomicU64>,
+
+    // Wrapped in a Mutex only to keep `Request` Sync.
+    deferred: Mutex<Vec<Box<dyn FnOnce() + Send>>>,
+
+    response_hooks: Mutex<Vec<Box<ResponseHook>>>,
 }
 
 impl<D> Request<D> {
@@ -39,10 +59,17 @@ impl<D> Request<D> {
             origin: req,
             body_taken: false,
             route_result: None,
+            allowed_methods: Vec::new(),
             map: TypeMap::custom(),
             data: data,
             remote_addr: remote_addr,
-            raw_body_cache: None
+            proxies: None,
+            raw_body_cache: None,
+            body_limit: None,
+            json_limits: JsonLimits::default(),
+            bytes_in: Arc::new(AtomicU64::new(0)),
+            deferred: Mutex::new(Vec::new()),
+            response_hooks: Mutex::new(Vec::new()),
         }
     }
 
@@ -50,6 +77,115 @@ impl<D> Request<D> {
         self.route_result.as_ref().unwrap().param(key)
     }
 
+    /// The route parameters including wildcards, in the order they appear
+    /// in the route's path, see `RouteResult::ordered_params`. Empty if no
+    /// route matched.
+    pub fn ordered_params(&self) -> Vec<(&str, &str)> {
+        self.route_result.as_ref().map(|r| r.ordered_params()).unwrap_or_default()
+    }
+
+    /// Like `param`, but treats a missing or empty parameter as a client
+    /// error. The error names the parameter and converts into a
+    /// `400 Bad Request`, so a handler can bail out with `try_with!`.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// #[macro_use] extern crate nickel;
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     let id = try_with!(res, req.require_param("id"));
+    ///     res.send(format!("User {}", id))
+    /// }
+    /// # fn main() {}
+    /// ```
+    pub fn require_param(&self, key: &str) -> Result<&str, (StatusCode, String)> {
+        match self.route_result.as_ref().and_then(|r| r.param(key)) {
+            Some(value) if !value.is_empty() => Ok(value),
+            _ => Err((StatusCode::BAD_REQUEST, format!("Missing required parameter '{}'", key)))
+        }
+    }
+
+    /// The method of the route handling the request, e.g. `GET` for a
+    /// `HEAD` request answered by a `GET` route. `None` if no route matched.
+    pub fn matched_route_method(&self) -> Option<&Method> {
+        self.route_result.as_ref().and_then(|r| r.method())
+    }
+
+    /// The methods routed for the request's path by routers that had no
+    /// route for its method. Empty if the path isn't routed at all, so an
+    /// error handler can tell a wrong method from a wrong path, e.g.
+    /// "GET /users exists, but you used POST".
+    ///
+    /// Routers with `strict_method_matching` record them before answering
+    /// with a `405`, others before passing the request on, which usually
+    /// ends in a `404`.
+    pub fn allowed_methods(&self) -> &[Method] {
+        &self.allowed_methods
+    }
+
+    pub(crate) fn add_allowed_methods(&mut self, methods: Vec<Method>) {
+        for method in methods {
+            if !self.allowed_methods.contains(&method) {
+                self.allowed_methods.push(method);
+            }
+        }
+    }
+
+    /// Whether the request's `Accept` header allows a response of
+    /// `media_type`, taking wildcards and `q=0` exclusions into account. A
+    /// request without an `Accept` header accepts anything.
+    pub fn accepts(&self, media_type: MediaType) -> bool {
+        self.accepts_mime(&media_type.into())
+    }
+
+    pub(crate) fn accepts_mime(&self, mime: &Mime) -> bool {
+        match self.accept_header() {
+            Some(accept) => accept_quality(&accept, mime) > 0.0,
+            None => true
+        }
+    }
+
+    // The values of all `Accept` headers as one list, `None` if there are
+    // none, as for requests accepting anything.
+    pub(crate) fn accept_header(&self) -> Option<String> {
+        let accept = self.origin.headers()
+                                .get_all(header::ACCEPT)
+                                .iter()
+                                .filter_map(|v| v.to_str().ok())
+                                .collect::<Vec<_>>()
+                                .join(",");
+        if accept.trim().is_empty() { None } else { Some(accept) }
+    }
+
+    /// Whether the request's `Accept-Encoding` header allows a response body
+    /// encoded with `coding`, e.g. `"gzip"`. A `*` entry covers codings not
+    /// listed on their own, `q=0` excludes a coding. Without an
+    /// `Accept-Encoding` header only `identity` is accepted.
+    pub fn accepts_encoding(&self, coding: &str) -> bool {
+        let accept_encoding = self.origin.headers()
+                                         .get_all(header::ACCEPT_ENCODING)
+                                         .iter()
+                                         .filter_map(|v| v.to_str().ok())
+                                         .collect::<Vec<_>>()
+                                         .join(",");
+        if accept_encoding.trim().is_empty() {
+            return coding.eq_ignore_ascii_case("identity");
+        }
+
+        encoding_quality(&accept_encoding, coding) > 0.0
+    }
+
+    /// Iterates over all request headers as name/value pairs, yielding one
+    /// pair per value for repeated headers. Values which aren't visible ASCII
+    /// are skipped; use `req.origin.headers()` to get at their raw bytes.
+    pub fn headers_iter(&self) -> impl Iterator<Item=(&str, &str)> {
+        self.origin.headers()
+                   .iter()
+                   .filter_map(|(name, value)| value.to_str().ok().map(|v| (name.as_str(), v)))
+    }
+
     pub fn path_without_query(&self) -> &str {
         self.origin.uri().path()
     }
@@ -62,6 +198,134 @@ impl<D> Request<D> {
         self.remote_addr.as_ref()
     }
 
+    /// The absolute URL of the request, e.g. for links in emails or OAuth
+    /// redirects. The scheme follows `is_secure`. The host is
+    /// `Options::public_host` if configured, otherwise it is taken from the
+    /// `Host` header, or the request target if it is absolute. Requests
+    /// without either, which HTTP/1.1 clients don't send, get `localhost`.
+    ///
+    /// Clients choose the `Host` header freely, so configure the public host
+    /// before sending these URLs to anyone but the client itself. Values
+    /// which aren't a valid host and port are ignored either way.
+    pub fn url(&self) -> String {
+        let uri = self.origin.uri();
+        let configured = self.proxies.as_ref().and_then(|p| p.public_host.as_deref());
+        let host = configured.or_else(|| {
+                                 self.origin.headers()
+                                            .get(header::HOST)
+                                            .and_then(|v| v.to_str().ok())
+                                            .filter(|host| is_valid_host(host))
+                             })
+                             .or_else(|| uri.authority().map(|a| a.as_str()))
+                             .unwrap_or("localhost");
+        let scheme = if self.is_secure() { "https" } else { "http" };
+        let path = uri.path_and_query().map_or("/", |p| p.as_str());
+
+        format!("{}://{}{}", scheme, host, path)
+    }
+
+    /// The token of an `Authorization: Bearer <token>` header. The scheme is
+    /// matched case-insensitively. Other schemes and tokens which aren't
+    /// valid `b64token`s as defined by RFC 6750 give `None`.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    /// use nickel::status::StatusCode;
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     match req.bearer_token() {
+    ///         Some(token) => res.send(format!("token of {} bytes", token.len())),
+    ///         None => res.error(StatusCode::UNAUTHORIZED, "Missing bearer token")
+    ///     }
+    /// }
+    /// ```
+    pub fn bearer_token(&self) -> Option<&str> {
+        let value = self.origin.headers().get(header::AUTHORIZATION)?.to_str().ok()?.trim();
+        let mut parts = value.splitn(2, ' ');
+        let scheme = parts.next()?;
+        let token = parts.next()?.trim_start_matches(' ');
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+
+        let data = token.trim_end_matches('=');
+        let valid = !data.is_empty() && data.bytes().all(|b| b.is_ascii_alphanumeric() || b"-._~+/".contains(&b));
+        if valid { Some(token) } else { None }
+    }
+
+    /// The byte range of the resource the body carries, from the
+    /// `Content-Range` header of ranged `PUT` and `PATCH` requests, e.g. in
+    /// resumable upload protocols. `None` without the header, and for values
+    /// that aren't a valid `bytes` range.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// #[macro_use] extern crate nickel;
+    /// use std::io::SeekFrom;
+    /// use tokio::fs::OpenOptions;
+    /// use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+    /// use nickel::{Request, Response, MiddlewareResult};
+    /// use nickel::status::StatusCode;
+    ///
+    /// # #[allow(dead_code)]
+    /// async fn upload_chunk<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     let range = match req.content_range() {
+    ///         Some(range) => range,
+    ///         None => return res.error(StatusCode::BAD_REQUEST, "Missing Content-Range")
+    ///     };
+    ///     let body = try_with!(res, req.raw_body().await);
+    ///     let mut file = OpenOptions::new().create(true).write(true).open("upload.bin").await.unwrap();
+    ///     file.seek(SeekFrom::Start(range.start)).await.unwrap();
+    ///     file.write_all(body).await.unwrap();
+    ///     // see examples/resumable_upload.rs for telling the client what's missing
+    ///     res.send("chunk stored")
+    /// }
+    /// # fn main() {}
+    /// ```
+    pub fn content_range(&self) -> Option<ContentRange> {
+        let value = self.origin.headers().get(header::CONTENT_RANGE)?.to_str().ok()?;
+        ContentRange::parse(value)
+    }
+
+    /// Whether the request was made over HTTPS. The server itself only speaks
+    /// plain HTTP, so this is true for absolute `https` request targets and
+    /// for requests a TLS terminating proxy marked with
+    /// `X-Forwarded-Proto: https`. The header is only honored for requests
+    /// coming from one of `Options::trusted_proxies`, clients can send it as
+    /// well. Make sure the proxy overwrites it.
+    pub fn is_secure(&self) -> bool {
+        if self.origin.uri().scheme_str() == Some("https") {
+            return true;
+        }
+        if !self.is_from_trusted_proxy() {
+            return false;
+        }
+
+        // a chain of proxies appends, the first value is the client facing one
+        match self.origin.headers().get("x-forwarded-proto").and_then(|v| v.to_str().ok()) {
+            Some(proto) => proto.split(',').next().unwrap_or("").trim().eq_ignore_ascii_case("https"),
+            None => false
+        }
+    }
+
+    /// Whether the request asks to upgrade the connection to a WebSocket, i.e.
+    /// it has an `Upgrade: websocket` header and `Connection` lists `Upgrade`.
+    /// Nickel doesn't speak WebSocket itself, so handlers can use this to
+    /// answer such requests with a `501 Not Implemented`.
+    pub fn is_websocket_upgrade(&self) -> bool {
+        let has_token = |name, token: &str| {
+            self.origin.headers()
+                       .get_all(name)
+                       .iter()
+                       .filter_map(|v| v.to_str().ok())
+                       .flat_map(|v| v.split(','))
+                       .any(|v| v.trim().eq_ignore_ascii_case(token))
+        };
+        has_token(header::UPGRADE, "websocket") && has_token(header::CONNECTION, "upgrade")
+    }
+
     // (Hopefully) temporary replacements for the Extensible trait. We can't
     // support plugins without Extensible, but access to the ShareMap is used by
     // itself.
@@ -73,6 +337,84 @@ impl<D> Request<D> {
         &mut self.map
     }
 
+    /// Registers `f` to run once the response body has been sent to the
+    /// client (or the client went away), without delaying the response.
+    /// This is meant for fire-and-forget work like analytics or cache warming.
+    ///
+    /// Deferred closures run in registration order on tokio's blocking
+    /// thread pool, so they take up worker time but not the connection's. A
+    /// panic in one closure is logged and does not affect the others or the
+    /// already sent response.
+    pub fn spawn_after_response<F>(&mut self, f: F)
+    where F: FnOnce() + Send + 'static {
+        self.deferred.get_mut().unwrap_or_else(|e| e.into_inner()).push(Box::new(f));
+    }
+
+    /// Like `spawn_after_response`, but `f` also gets a summary of the sent
+    /// response, with its status and size, e.g. for access logs.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult, ResponseSummary};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn log_requests<D>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     let uri = req.origin.uri().clone();
+    ///     req.on_response_sent(move |summary: &ResponseSummary| {
+    ///         println!("{} {} {} bytes", uri, summary.status(), summary.bytes_out());
+    ///     });
+    ///     res.next_middleware()
+    /// }
+    /// ```
+    pub fn on_response_sent<F>(&mut self, f: F)
+    where F: FnOnce(&ResponseSummary) + Send + 'static {
+        self.response_hooks.get_mut().unwrap_or_else(|e| e.into_inner()).push(Box::new(f));
+    }
+
+    // The most bytes the body access methods will read, `None` for no limit.
+    pub(crate) fn set_body_limit(&mut self, limit: Option<u64>) {
+        self.body_limit = limit;
+    }
+
+    pub(crate) fn set_json_limits(&mut self, limits: JsonLimits) {
+        self.json_limits = limits;
+    }
+
+    pub(crate) fn set_proxy_settings(&mut self, proxies: Arc<ProxySettings>) {
+        self.proxies = Some(proxies);
+    }
+
+    // Whether the request was sent by one of the trusted proxies, whose
+    // `X-Forwarded-*` headers can be believed
+    fn is_from_trusted_proxy(&self) -> bool {
+        match (&self.proxies, self.remote_addr) {
+            (Some(proxies), Some(addr)) => proxies.trusted.contains(&addr.ip()),
+            _ => false
+        }
+    }
+
+    /// The number of body bytes read from the client so far, through the
+    /// body access methods or the body returned by `take_body`. Bodies that
+    /// are never read count as zero bytes.
+    ///
+    /// `ResponseSummary::bytes_in` has the final count once the response
+    /// was sent.
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn bytes_in_counter(&self) -> Arc<AtomicU64> {
+        self.bytes_in.clone()
+    }
+
+    pub(crate) fn take_response_hooks(&mut self) -> Vec<Box<ResponseHook>> {
+        mem::take(self.response_hooks.get_mut().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub(crate) fn take_deferred(&mut self) -> Vec<Box<dyn FnOnce() + Send>> {
+        mem::take(self.deferred.get_mut().unwrap_or_else(|e| e.into_inner()))
+    }
+
     /// Take the body from the hyper request. Once taken the body is not longer
     /// available. This method will return `None` in that case.
     ///
@@ -82,8 +424,22 @@ impl<D> Request<D> {
     /// modern servers can handle multi-megabyte bodies.
     ///
     /// `take_body` and the body access method are mutually exclusive. Once one
-    /// is called, the other will fail.
+    /// is called, the other will fail. Note that the body size limit only
+    /// applies to the body access methods.
+    ///
+    /// The bytes read from the returned body are counted in `bytes_in`.
     pub fn take_body(&mut self) -> Option<Body> {
+        let bytes_in = self.bytes_in.clone();
+        self.take_origin_body().map(|body| {
+            Body::wrap_stream(body.inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    bytes_in.fetch_add(chunk.len() as u64, Ordering::SeqCst);
+                }
+            }))
+        })
+    }
+
+    fn take_origin_body(&mut self) -> Option<Body> {
         if self.body_taken {
             None
         } else {
@@ -123,13 +479,53 @@ impl<D> Request<D> {
     ///
     /// To allow access to the body in different ways, `string_body`, `json_as`
     /// and `form_body` all call this and use the same underlying cache.
+    ///
+    /// Bodies larger than the limit set with `Options::max_body_size`, or
+    /// with `Router::max_body` for the matched route, are rejected with
+    /// `413 Payload Too Large`.
     pub async fn raw_body(&mut self) -> Result<&[u8], (StatusCode, String)> {
-        if let None = self.raw_body_cache {
+        self.raw_body_with_progress(|_, _| {}).await
+    }
+
+    /// Like `raw_body`, but calls `progress` with the number of bytes read
+    /// so far and the total size each time a chunk of the body arrives, e.g.
+    /// to push upload progress to a channel. The total is `None` for bodies
+    /// of unknown length, like chunked uploads.
+    ///
+    /// If the body has been read before, `progress` is called once with its
+    /// full length.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// #[macro_use] extern crate nickel;
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// async fn upload<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     let body = try_with!(res, req.raw_body_with_progress(|read, total| {
+    ///         match total {
+    ///             Some(total) => println!("received {} of {} bytes", read, total),
+    ///             None => println!("received {} bytes", read)
+    ///         }
+    ///     }).await);
+    ///     res.send(format!("stored {} bytes", body.len()))
+    /// }
+    /// # fn main() {}
+    /// ```
+    pub async fn raw_body_with_progress<F>(&mut self, mut progress: F) -> Result<&[u8], (StatusCode, String)>
+            where F: FnMut(u64, Option<u64>) {
+        if let Some(ref bytes) = self.raw_body_cache {
+            let len = bytes.len() as u64;
+            progress(len, Some(len));
+        } else {
             // read and insert into cache
-            let body = self.take_body().
+            let body = self.take_origin_body().
                 ok_or((StatusCode::INTERNAL_SERVER_ERROR, "body already taken".to_string()))?;
-            let bytes = body::to_bytes::<Body>(body).await.
-                map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            let bytes_in = self.bytes_in.clone();
+            let bytes = read_body(body, self.body_limit, &mut |read, total| {
+                bytes_in.store(read, Ordering::SeqCst);
+                progress(read, total)
+            }).await?;
             self.raw_body_cache = Some(bytes);
         }
         // we've garanteed this unwrap is safe above
@@ -144,12 +540,79 @@ impl<D> Request<D> {
     }
 
     /// Uses serde to deserialze thoe body as json into type `T`.
+    ///
+    /// Bodies nested deeper or with larger arrays or objects than allowed
+    /// by `Options::max_json_depth` and `Options::max_json_elements` are
+    /// rejected with `400 Bad Request` before deserializing them.
+    #[cfg(feature = "json")]
     pub async fn json_as<'a, T: Deserialize<'a>>(&'a mut self) -> Result<T, (StatusCode, String)> {
+        let limits = self.json_limits;
         let bytes = self.raw_body().await?;
+        limits.check(bytes).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
         serde_json::from_slice::<T>(bytes).
             map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
     }
 
+    /// Like `json_as`, but rejects bodies containing fields that `T` does
+    /// not know about, as if `T` had been declared with
+    /// `#[serde(deny_unknown_fields)]`. The error names the first unexpected
+    /// field, which helps catching typos and client bugs early.
+    #[cfg(feature = "json")]
+    pub async fn json_as_strict<'a, T: Deserialize<'a>>(&'a mut self) -> Result<T, (StatusCode, String)> {
+        let limits = self.json_limits;
+        let bytes = self.raw_body().await?;
+        limits.check(bytes).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+        let mut unknown = None;
+        let value = serde_ignored::deserialize(&mut deserializer, |path| {
+            if unknown.is_none() {
+                unknown = Some(path.to_string());
+            }
+        }).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+        deserializer.end().map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+        match unknown {
+            Some(field) => Err((StatusCode::BAD_REQUEST, format!("unknown field `{}`", field))),
+            None => Ok(value)
+        }
+    }
+
+    /// The fields of a form submitted as `application/x-www-form-urlencoded`,
+    /// read like the query string with `get` and `all`. Unlike `form_body`,
+    /// requests of other content types (or without a body) yield no fields
+    /// instead of an error, so handlers can read optional form fields the
+    /// same way as query parameters.
+    ///
+    /// The body is read on the first call and kept, so this can be called
+    /// repeatedly.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// #[macro_use] extern crate nickel;
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// async fn subscribe<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     let form = try_with!(res, req.form().await);
+    ///     let topics = form.all("topic").map(|topics| topics.join(", ")).unwrap_or_default();
+    ///     res.send(format!("{} subscribed to {}", form.get("email").unwrap_or("nobody"), topics))
+    /// }
+    /// # fn main() {}
+    /// ```
+    pub async fn form(&mut self) -> Result<Params, (StatusCode, String)> {
+        let is_form = self.origin.headers()
+                                 .get(header::CONTENT_TYPE)
+                                 .and_then(|v| v.to_str().ok())
+                                 .and_then(|v| v.parse::<Mime>().ok())
+                                 .map(|mime| mime.essence_str() == mime::APPLICATION_WWW_FORM_URLENCODED.essence_str());
+        if is_form != Some(true) {
+            return Ok(Params::default());
+        }
+
+        let s = self.string_body().await?;
+        Ok(urlencoded::parse(&s))
+    }
+
     /// Extract the form data from the body.
     pub async fn form_body(&mut self) -> Result<Params, (StatusCode, String)> {
         // check content type
@@ -162,3 +625,654 @@ impl<D> Request<D> {
         }
     }
 }
+
+// Reads `body` like `body::to_bytes`, reporting progress after each chunk.
+// Gives up as soon as the body turns out to be longer than `limit`.
+async fn read_body<F>(mut body: Body, limit: Option<u64>, progress: &mut F) -> Result<Bytes, (StatusCode, String)>
+        where F: FnMut(u64, Option<u64>) {
+    let too_large = |limit| (StatusCode::PAYLOAD_TOO_LARGE,
+                             format!("Request body exceeds the limit of {} bytes", limit));
+
+    // Exact for bodies with a Content-Length
+    let size_hint = body.size_hint();
+    if let Some(limit) = limit {
+        if size_hint.lower() > limit {
+            return Err(too_large(limit));
+        }
+    }
+
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let read = (buf.len() + chunk.len()) as u64;
+        if let Some(limit) = limit {
+            if read > limit {
+                return Err(too_large(limit));
+            }
+        }
+        buf.extend_from_slice(&chunk);
+        progress(read, size_hint.exact());
+    }
+    Ok(buf.into())
+}
+
+// The body size limits configured with `Options::max_body_size` and
+// `Options::max_body_size_for`, along with the `JsonLimits`.
+pub(crate) struct BodyLimits {
+    default: Option<u64>,
+    by_type: Vec<(Mime, u64)>,
+    json: JsonLimits,
+}
+
+impl BodyLimits {
+    pub(crate) fn new(default: Option<u64>, by_type: Vec<(Mime, u64)>, json: JsonLimits) -> BodyLimits {
+        BodyLimits { default, by_type, json }
+    }
+
+    pub(crate) fn json(&self) -> JsonLimits {
+        self.json
+    }
+
+    // The limit for the `Content-Type` of `req`, falling back to the default
+    pub(crate) fn for_request<D>(&self, req: &Request<D>) -> Option<u64> {
+        let content_type = req.origin.headers()
+                                     .get(header::CONTENT_TYPE)
+                                     .and_then(|v| v.to_str().ok())
+                                     .and_then(|v| v.parse::<Mime>().ok());
+        let limit = content_type.and_then(|ct| {
+            self.by_type.iter().find(|(mime, _)| mime.essence_str() == ct.essence_str())
+        });
+        match limit {
+            Some(&(_, limit)) => Some(limit),
+            None => self.default
+        }
+    }
+}
+
+// The reverse proxies configured with `Options::trusted_proxies` and the
+// host they serve, `Options::public_host`.
+#[derive(Debug, Default)]
+pub(crate) struct ProxySettings {
+    trusted: Vec<IpAddr>,
+    public_host: Option<String>,
+}
+
+impl ProxySettings {
+    pub(crate) fn new(trusted: Vec<IpAddr>, public_host: Option<String>) -> ProxySettings {
+        ProxySettings { trusted, public_host }
+    }
+}
+
+// Whether a `Host` header is a plain host with an optional port, without
+// user info or anything trailing it.
+fn is_valid_host(host: &str) -> bool {
+    !host.is_empty()
+        && !host.contains('@')
+        && host.parse::<hyper::http::uri::Authority>().is_ok_and(|a| a.as_str() == host)
+}
+
+// The structural limits for JSON bodies configured with
+// `Options::max_json_depth` and `Options::max_json_elements`.
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+pub(crate) struct JsonLimits {
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_elements: Option<usize>,
+}
+
+#[cfg(feature = "json")]
+impl JsonLimits {
+    // Scans `json` for arrays and objects exceeding the limits, without
+    // validating it otherwise, which is left to serde.
+    fn check(&self, json: &[u8]) -> Result<(), String> {
+        if self.max_depth.is_none() && self.max_elements.is_none() {
+            return Ok(());
+        }
+
+        // The elements seen in each open array or object so far, and whether
+        // the next value starts another one
+        let mut open: Vec<(usize, bool)> = Vec::new();
+        let mut in_string = false;
+        let mut escaped = false;
+        for &b in json {
+            if in_string {
+                match b {
+                    _ if escaped => escaped = false,
+                    b'\\' => escaped = true,
+                    b'"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match b {
+                _ if b.is_ascii_whitespace() => continue,
+                b']' | b'}' => { open.pop(); continue; },
+                b',' => { if let Some((_, next)) = open.last_mut() { *next = true; } continue; },
+                _ => {}
+            }
+
+            if let Some((count, next @ true)) = open.last_mut() {
+                *next = false;
+                *count += 1;
+                if let Some(max) = self.max_elements {
+                    if *count > max {
+                        return Err(format!("JSON body has an array or object of more than {} elements", max));
+                    }
+                }
+            }
+            match b {
+                b'"' => in_string = true,
+                b'[' | b'{' => {
+                    open.push((0, true));
+                    if let Some(max) = self.max_depth {
+                        if open.len() > max {
+                            return Err(format!("JSON body is nested deeper than {} levels", max));
+                        }
+                    }
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copies of selected request extensions, to take request context like a
+/// request id or the current user along into a spawned task, which can't
+/// borrow the request.
+///
+/// Only the captured entries are copied. Entries the request doesn't have
+/// are left out of the snapshot, so `get` returns `None` for them just like
+/// `extensions().get()` would have.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::{Request, Response, MiddlewareResult, ExtensionSnapshot};
+///
+/// struct RequestId;
+/// impl typemap::Key for RequestId { type Value = String; }
+///
+/// # #[allow(dead_code)]
+/// fn handler<D>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+///     let mut context = ExtensionSnapshot::new();
+///     context.capture::<RequestId>(req.extensions());
+///     req.spawn_after_response(move || {
+///         let id = context.get::<RequestId>().map_or("-", |id| &**id);
+///         println!("[{}] warming caches", id);
+///     });
+///     res.send("ok")
+/// }
+/// ```
+pub struct ExtensionSnapshot {
+    map: ShareMap,
+}
+
+impl ExtensionSnapshot {
+    /// An empty snapshot.
+    pub fn new() -> ExtensionSnapshot {
+        ExtensionSnapshot { map: TypeMap::custom() }
+    }
+
+    /// Copies the entry for `K` from `extensions`, if there is one.
+    pub fn capture<K>(&mut self, extensions: &ShareMap) -> &mut ExtensionSnapshot
+    where K: Key, K::Value: Clone + Send + Sync {
+        if let Some(value) = extensions.get::<K>() {
+            self.map.insert::<K>(value.clone());
+        }
+        self
+    }
+
+    /// The captured entry for `K`, `None` if it wasn't captured or the
+    /// request didn't have it.
+    pub fn get<K>(&self) -> Option<&K::Value>
+    where K: Key, K::Value: Send + Sync {
+        self.map.get::<K>()
+    }
+}
+
+impl Default for ExtensionSnapshot {
+    fn default() -> ExtensionSnapshot {
+        ExtensionSnapshot::new()
+    }
+}
+
+/// A byte range as sent in a request's `Content-Range` header, see
+/// `Request::content_range`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContentRange {
+    /// Offset of the first byte of the body.
+    pub start: u64,
+    /// Offset of the last byte of the body, inclusive.
+    pub end: u64,
+    /// Size of the complete resource, `None` if the client doesn't know it
+    /// yet (`*`).
+    pub total: Option<u64>,
+}
+
+impl ContentRange {
+    /// The number of bytes in the range.
+    pub fn size(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// Whether the range ends with the last byte of the resource.
+    pub fn is_last(&self) -> bool {
+        self.total == Some(self.end + 1)
+    }
+
+    // Parses `bytes <start>-<end>/<total or *>`
+    fn parse(value: &str) -> Option<ContentRange> {
+        let mut parts = value.trim().splitn(2, ' ');
+        if !parts.next()?.eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+        let mut range_and_total = parts.next()?.trim().splitn(2, '/');
+        let mut range = range_and_total.next()?.splitn(2, '-');
+        let start: u64 = range.next()?.parse().ok()?;
+        let end: u64 = range.next()?.parse().ok()?;
+        let total = match range_and_total.next()? {
+            "*" => None,
+            total => Some(total.parse::<u64>().ok()?)
+        };
+
+        let valid = start <= end && !matches!(total, Some(total) if end >= total);
+        if valid { Some(ContentRange { start, end, total }) } else { None }
+    }
+}
+
+// The media ranges listed in an `Accept` header, skipping malformed ones.
+pub(crate) fn media_ranges(accept: &str) -> impl Iterator<Item=Mime> + '_ {
+    accept.split(',').filter_map(|r| r.trim().parse::<Mime>().ok())
+}
+
+// The quality the `Accept` header assigns to `mime`, as given by the most
+// specific matching media range. 0 if no range matches.
+pub(crate) fn accept_quality(accept: &str, mime: &Mime) -> f32 {
+    let mut best: Option<(u8, f32)> = None;
+
+    for range in media_ranges(accept) {
+        let specificity = if range.type_() == mime::STAR && range.subtype() == mime::STAR {
+            0
+        } else if range.type_() == mime.type_() && range.subtype() == mime::STAR {
+            1
+        } else if range.type_() == mime.type_() && range.subtype() == mime.subtype() {
+            2
+        } else {
+            continue
+        };
+        let quality = range.get_param("q")
+                           .and_then(|q| q.as_str().parse().ok())
+                           .unwrap_or(1.0);
+
+        match best {
+            Some((s, _)) if s >= specificity => {},
+            _ => best = Some((specificity, quality))
+        }
+    }
+
+    best.map_or(0.0, |(_, q)| q)
+}
+
+// The q-value `accept_encoding` gives to `coding`. An explicit entry for the
+// coding takes precedence over `*`, and `identity` is acceptable unless it is
+// excluded.
+pub(crate) fn encoding_quality(accept_encoding: &str, coding: &str) -> f32 {
+    let mut wildcard = None;
+
+    for entry in accept_encoding.split(',') {
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap_or("").trim();
+        let quality = parts.filter_map(|param| {
+                               let mut kv = param.splitn(2, '=');
+                               match (kv.next()?.trim(), kv.next()) {
+                                   ("q", Some(q)) => q.trim().parse().ok(),
+                                   _ => None
+                               }
+                           })
+                           .next()
+                           .unwrap_or(1.0);
+
+        if name.eq_ignore_ascii_case(coding) {
+            return quality;
+        } else if name == "*" {
+            wildcard = Some(quality);
+        }
+    }
+
+    match wildcard {
+        Some(quality) => quality,
+        None if coding.eq_ignore_ascii_case("identity") => 1.0,
+        None => 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use hyper::{Body, Method, Request as HyperRequest, StatusCode};
+    use crate::{HttpRouter, MiddlewareResult, Request, Response, Router};
+    use crate::test_helpers::{self, response};
+
+    fn routed_request(pattern: &str, path: &str) -> Request<()> {
+        let mut router = Router::<()>::new();
+        router.get(pattern, middleware! { "" });
+        let mut req = test_helpers::get(path);
+        req.route_result = router.match_route(&Method::GET, path).map(|(result, _)| result);
+        req
+    }
+
+    fn handler(req: &mut Request<()>, res: Response<()>) -> MiddlewareResult<()> {
+        let id = try_with!(res, req.require_param("id"));
+        res.send(format!("User {}", id))
+    }
+
+    #[cfg(feature = "json")]
+    #[derive(Debug, serde_derive::Deserialize)]
+    struct Person {
+        first_name: String,
+    }
+
+    #[cfg(feature = "json")]
+    fn json_request(body: &'static str) -> Request<()> {
+        test_helpers::request_with_body(HyperRequest::post("/"), body)
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_structure_is_limited() {
+        use crate::Options;
+
+        let limits = Options::default()
+                         .max_json_depth(Some(3))
+                         .max_json_elements(Some(4))
+                         .body_limits()
+                         .json();
+        let parse = |body: &'static str| async move {
+            let mut req = json_request(body);
+            req.set_json_limits(limits);
+            req.json_as::<serde_json::Value>().await.map_err(|(status, _)| status)
+        };
+
+        assert!(parse(r#"{"tags": [[1, 2], [3, 4, 5, 6]], "name": "[[[[,,,,"}"#).await.is_ok());
+        assert_eq!(parse("[[[[1]]]]").await.unwrap_err(), StatusCode::BAD_REQUEST);
+        assert_eq!(parse(r#"{"a": 1, "b": 2, "c": 3, "d": 4, "e": 5}"#).await.unwrap_err(),
+                   StatusCode::BAD_REQUEST);
+        assert_eq!(parse("[1, 2, [3], 4, 5]").await.unwrap_err(), StatusCode::BAD_REQUEST);
+
+        // a limit of 0 only lets empty arrays and objects through
+        let limits = Options::default().max_json_elements(Some(0)).body_limits().json();
+        for (body, ok) in &[("[1]", false), (r#"{"a": 1}"#, false), ("[]", true), ("{ }", true)] {
+            let mut req = json_request(body);
+            req.set_json_limits(limits);
+            assert_eq!(req.json_as::<serde_json::Value>().await.is_ok(), *ok, "{}", body);
+        }
+
+        // no limits by default
+        let mut req = json_request("[[[[[1, 2, 3, 4, 5, 6]]]]]");
+        assert!(req.json_as::<serde_json::Value>().await.is_ok());
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_as_strict_rejects_unknown_fields() {
+        let body = r#"{ "first_name": "John", "last_nmae": "Connor" }"#;
+
+        let person = json_request(body).json_as::<Person>().await.unwrap();
+        assert_eq!(person.first_name, "John");
+
+        let err = json_request(body).json_as_strict::<Person>().await.unwrap_err();
+        assert_eq!(err, (StatusCode::BAD_REQUEST, "unknown field `last_nmae`".to_string()));
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_as_strict_accepts_known_fields() {
+        let person = json_request(r#"{ "first_name": "John" }"#).json_as_strict::<Person>().await.unwrap();
+        assert_eq!(person.first_name, "John");
+    }
+
+    #[test]
+    fn headers_iter_yields_every_value() {
+
+        let req = test_helpers::request(HyperRequest::get("/")
+                                            .header("host", "example.com")
+                                            .header("x-forwarded-for", "10.0.0.1")
+                                            .header("x-forwarded-for", "10.0.0.2"));
+        let headers: Vec<_> = req.headers_iter().collect();
+        assert_eq!(headers, vec![("host", "example.com"),
+                                 ("x-forwarded-for", "10.0.0.1"),
+                                 ("x-forwarded-for", "10.0.0.2")]);
+    }
+
+    #[test]
+    fn detects_websocket_upgrades() {
+
+        let req = test_helpers::request(HyperRequest::get("/chat")
+                                            .header("connection", "keep-alive, Upgrade")
+                                            .header("upgrade", "websocket")
+                                            .header("sec-websocket-version", "13"));
+        assert!(req.is_websocket_upgrade());
+
+        let req = test_helpers::request(HyperRequest::get("/chat").header("connection", "keep-alive"));
+        assert!(!req.is_websocket_upgrade());
+
+        let req = test_helpers::request(HyperRequest::get("/chat").header("upgrade", "websocket"));
+        assert!(!req.is_websocket_upgrade());
+    }
+
+    #[test]
+    fn accepts_media_ranges() {
+        use crate::mimes::MediaType;
+
+        let accepting = |accept: &str| test_helpers::request(HyperRequest::get("/").header("accept", accept));
+
+        assert!(test_helpers::get("/").accepts(MediaType::Json));
+        assert!(accepting("application/json").accepts(MediaType::Json));
+        assert!(accepting("text/html, */*;q=0.1").accepts(MediaType::Json));
+        assert!(accepting("application/*").accepts(MediaType::Json));
+        assert!(!accepting("text/html").accepts(MediaType::Json));
+        assert!(!accepting("*/*, application/json;q=0").accepts(MediaType::Json));
+        assert!(accepting("*/*, application/json;q=0").accepts(MediaType::Html));
+    }
+
+    #[tokio::test]
+    async fn reports_body_progress() {
+
+        let chunks: Vec<Result<&'static str, std::io::Error>> = vec![Ok("hello "), Ok("chunked "), Ok("world")];
+        let mut req = test_helpers::request_with_body(HyperRequest::post("/upload"),
+                                                      Body::wrap_stream(futures::stream::iter(chunks)));
+        let mut seen = Vec::new();
+        let body = req.raw_body_with_progress(|read, total| seen.push((read, total))).await.unwrap().to_vec();
+        assert_eq!(body, b"hello chunked world");
+        assert_eq!(seen, vec![(6, None), (14, None), (19, None)]);
+
+        // already read, reported at once
+        let mut seen = Vec::new();
+        req.raw_body_with_progress(|read, total| seen.push((read, total))).await.unwrap();
+        assert_eq!(seen, vec![(19, Some(19))]);
+
+        let mut req = test_helpers::request_with_body(HyperRequest::post("/upload"), "sized");
+        let mut seen = Vec::new();
+        req.raw_body_with_progress(|read, total| seen.push((read, total))).await.unwrap();
+        assert_eq!(seen, vec![(5, Some(5))]);
+    }
+
+    #[tokio::test]
+    async fn counts_bytes_read() {
+
+        let mut req = test_helpers::request_with_body(HyperRequest::post("/upload"), "twelve bytes");
+        assert_eq!(req.bytes_in(), 0);
+        req.raw_body().await.unwrap();
+        assert_eq!(req.bytes_in(), 12);
+
+        let chunks: Vec<Result<&'static str, std::io::Error>> = vec![Ok("hello "), Ok("world")];
+        let mut req = test_helpers::request_with_body(HyperRequest::post("/upload"),
+                                                      Body::wrap_stream(futures::stream::iter(chunks)));
+        let body = hyper::body::to_bytes(req.take_body().unwrap()).await.unwrap();
+        assert_eq!(req.bytes_in(), body.len() as u64);
+        assert_eq!(req.bytes_in(), 11);
+    }
+
+    #[test]
+    fn snapshots_extensions_for_spawned_tasks() {
+        use typemap::Key;
+        use super::ExtensionSnapshot;
+
+        struct RequestId;
+        impl Key for RequestId { type Value = String; }
+        struct CurrentUser;
+        impl Key for CurrentUser { type Value = String; }
+
+        let mut req = test_helpers::get("/");
+        req.extensions_mut().insert::<RequestId>("f81d4fae".to_string());
+
+        let mut context = ExtensionSnapshot::new();
+        context.capture::<RequestId>(req.extensions())
+               .capture::<CurrentUser>(req.extensions());
+        drop(req);
+
+        let captured = std::thread::spawn(move || {
+            (context.get::<RequestId>().cloned(), context.get::<CurrentUser>().cloned())
+        }).join().unwrap();
+        assert_eq!(captured, (Some("f81d4fae".to_string()), None));
+    }
+
+    #[test]
+    fn builds_absolute_urls() {
+
+        let plain = test_helpers::request(HyperRequest::get("/search?q=nickel&page=2")
+                                              .header("host", "example.com:8080"));
+        assert_eq!(plain.url(), "http://example.com:8080/search?q=nickel&page=2");
+
+        let proxied = test_helpers::from_proxy(HyperRequest::get("/callback?code=abc")
+                                                   .header("host", "example.com")
+                                                   .header("x-forwarded-proto", "https"));
+        assert_eq!(proxied.url(), "https://example.com/callback?code=abc");
+
+        assert_eq!(test_helpers::get("http://example.org/a").url(), "http://example.org/a");
+        assert_eq!(test_helpers::get("/a").url(), "http://localhost/a");
+    }
+
+    #[test]
+    fn url_host_can_be_pinned() {
+        use super::ProxySettings;
+
+        let mut req = test_helpers::request(HyperRequest::get("/reset?token=abc").header("host", "evil.example"));
+        assert_eq!(req.url(), "http://evil.example/reset?token=abc");
+        req.set_proxy_settings(Arc::new(ProxySettings::new(Vec::new(), Some("shop.example".to_string()))));
+        assert_eq!(req.url(), "http://shop.example/reset?token=abc");
+
+        // not a host, whether configured or not
+        for host in &["evil.example/phish?", "user@evil.example", "evil.example:80#"] {
+            let req = test_helpers::request(HyperRequest::get("/a").header("host", *host));
+            assert_eq!(req.url(), "http://localhost/a", "{}", host);
+        }
+    }
+
+    #[test]
+    fn extracts_bearer_tokens() {
+
+        let authorized = |value: &str| test_helpers::request(HyperRequest::get("/").header("authorization", value));
+
+        assert_eq!(authorized("Bearer mF_9.B5f-4.1JqM").bearer_token(), Some("mF_9.B5f-4.1JqM"));
+        assert_eq!(authorized("bearer  abc+/def==").bearer_token(), Some("abc+/def=="));
+        assert_eq!(authorized("Basic YWxhZGRpbjpvcGVuc2VzYW1l").bearer_token(), None);
+        assert_eq!(authorized("Bearer").bearer_token(), None);
+        assert_eq!(authorized("Bearer ").bearer_token(), None);
+        assert_eq!(authorized("Bearer two tokens").bearer_token(), None);
+        assert_eq!(authorized("Bearer =abc").bearer_token(), None);
+        assert_eq!(test_helpers::get("/").bearer_token(), None);
+    }
+
+    #[test]
+    fn parses_content_ranges() {
+        use super::ContentRange;
+
+        let ranged = |value: &str| test_helpers::request(HyperRequest::put("/").header("content-range", value));
+
+        assert_eq!(ranged("bytes 0-99/200").content_range(),
+                   Some(ContentRange { start: 0, end: 99, total: Some(200) }));
+        assert_eq!(ranged("bytes 100-199/*").content_range(),
+                   Some(ContentRange { start: 100, end: 199, total: None }));
+        assert!(ranged("bytes 100-199/200").content_range().unwrap().is_last());
+        assert_eq!(ranged("bytes 100-199/200").content_range().unwrap().size(), 100);
+        assert_eq!(ranged("bytes 100-99/200").content_range(), None);
+        assert_eq!(ranged("bytes 0-200/200").content_range(), None);
+        assert_eq!(ranged("bytes */200").content_range(), None);
+        assert_eq!(ranged("items 0-9/10").content_range(), None);
+        assert_eq!(test_helpers::get("/").content_range(), None);
+    }
+
+    #[tokio::test]
+    async fn reads_form_fields() {
+
+        let form = |content_type: &str, body: &'static str| {
+            test_helpers::request_with_body(HyperRequest::post("/").header("content-type", content_type), body)
+        };
+
+        let mut req = form("application/x-www-form-urlencoded; charset=utf-8",
+                           "email=a%40example.com&topic=rust&topic=web");
+        let fields = req.form().await.unwrap();
+        assert_eq!(fields.get("email"), Some("a@example.com"));
+        assert_eq!(fields.all("topic"), Some(&["rust".to_string(), "web".to_string()][..]));
+        assert_eq!(fields.get("name"), None);
+        // the body is kept for later calls
+        assert_eq!(req.form().await.unwrap().get("email"), Some("a@example.com"));
+
+        let mut json = form("application/json", "{\"email\": \"a@example.com\"}");
+        assert!(json.form().await.unwrap().map().is_empty());
+        assert!(test_helpers::get("/?email=a").form().await.unwrap().map().is_empty());
+    }
+
+    #[test]
+    fn detects_secure_requests() {
+        use super::ProxySettings;
+
+        assert!(!test_helpers::get("/").is_secure());
+        assert!(test_helpers::get("https://example.com/").is_secure());
+        assert!(!test_helpers::get("http://example.com/").is_secure());
+        assert!(test_helpers::from_proxy(HyperRequest::get("/").header("x-forwarded-proto", "https, http")).is_secure());
+        assert!(!test_helpers::from_proxy(HyperRequest::get("/").header("x-forwarded-proto", "http")).is_secure());
+        // anyone else could claim so as well
+        assert!(!test_helpers::request(HyperRequest::get("/").header("x-forwarded-proto", "https")).is_secure());
+        let mut untrusted = test_helpers::request_from(HyperRequest::get("/").header("x-forwarded-proto", "https"),
+                                                       "203.0.113.7:5000");
+        untrusted.set_proxy_settings(Arc::new(ProxySettings::new(vec!["10.0.0.1".parse().unwrap()], None)));
+        assert!(!untrusted.is_secure());
+    }
+
+    #[test]
+    fn accepts_content_codings() {
+
+        let accepting = |encodings: &str| test_helpers::request(HyperRequest::get("/").header("accept-encoding", encodings));
+
+        assert!(!test_helpers::get("/").accepts_encoding("gzip"));
+        assert!(test_helpers::get("/").accepts_encoding("identity"));
+        assert!(accepting("gzip, deflate").accepts_encoding("gzip"));
+        assert!(accepting("GZIP;q=0.5").accepts_encoding("gzip"));
+        assert!(accepting("br, *;q=0.1").accepts_encoding("gzip"));
+        assert!(!accepting("br").accepts_encoding("gzip"));
+        assert!(!accepting("*, gzip;q=0").accepts_encoding("gzip"));
+        assert!(accepting("br").accepts_encoding("identity"));
+        assert!(!accepting("br, identity;q=0").accepts_encoding("identity"));
+    }
+
+    #[test]
+    fn require_param_present() {
+        let mut req = routed_request("/user/:id", "/user/42");
+        assert_eq!(req.require_param("id"), Ok("42"));
+        assert!(handler(&mut req, response()).is_ok());
+    }
+
+    #[test]
+    fn require_param_missing() {
+        let mut req = routed_request("/user/*", "/user/42");
+        let err = match handler(&mut req, response()) {
+            Err(err) => err,
+            Ok(_) => panic!("expected a missing parameter error")
+        };
+
+        assert_eq!(err.message, "Missing required parameter 'id'");
+        assert_eq!(err.stream.unwrap().status(), StatusCode::BAD_REQUEST);
+    }
+}
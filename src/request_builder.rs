@@ -0,0 +1,191 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use hyper::{Body, Method, Request as HyperRequest};
+use hyper::header::{HeaderName, HeaderValue};
+
+use crate::request::Request;
+use crate::router::RouteResult;
+
+/// Builds a `Request` without a running server, for unit testing handlers.
+///
+/// Route parameters given with `param` are available through
+/// `Request::param` just as if the router had matched the request.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::RequestBuilder;
+/// use nickel::hyper::Method;
+///
+/// let req = RequestBuilder::new()
+///     .method(Method::POST)
+///     .uri("/users/42")
+///     .header("content-type", "application/json")
+///     .param("id", "42")
+///     .body(r#"{ "name": "Alice" }"#)
+///     .build();
+///
+/// assert_eq!(req.param("id"), Some("42"));
+/// ```
+pub struct RequestBuilder<D = ()> {
+    method: Method,
+    uri: String,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Body,
+    params: Vec<(String, String)>,
+    remote_addr: Option<SocketAddr>,
+    data: D,
+}
+
+impl RequestBuilder<()> {
+    /// A builder for a `GET /` request without server data.
+    pub fn new() -> RequestBuilder<()> {
+        RequestBuilder {
+            method: Method::GET,
+            uri: "/".to_string(),
+            headers: Vec::new(),
+            body: Body::empty(),
+            params: Vec::new(),
+            remote_addr: None,
+            data: (),
+        }
+    }
+}
+
+impl Default for RequestBuilder<()> {
+    fn default() -> Self {
+        RequestBuilder::new()
+    }
+}
+
+impl<D> RequestBuilder<D> {
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    /// The request target, e.g. `/users/42?verbose=true`.
+    pub fn uri<S: Into<String>>(mut self, uri: S) -> Self {
+        self.uri = uri.into();
+        self
+    }
+
+    /// Adds a header, keeping previously added values of the same name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` or `value` are not valid in a header.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .unwrap_or_else(|_| panic!("invalid header name '{}'", name));
+        let value = HeaderValue::from_str(value)
+            .unwrap_or_else(|_| panic!("invalid value for header '{}'", name));
+        self.headers.push((name, value));
+        self
+    }
+
+    pub fn body<B: Into<Body>>(mut self, body: B) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Sets a route parameter, as if the request had been routed to a path
+    /// like `/users/:id`.
+    pub fn param<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.params.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn remote_addr(mut self, addr: SocketAddr) -> Self {
+        self.remote_addr = Some(addr);
+        self
+    }
+
+    /// The server data returned by `Request::server_data`.
+    pub fn data<E>(self, data: E) -> RequestBuilder<E> {
+        RequestBuilder {
+            method: self.method,
+            uri: self.uri,
+            headers: self.headers,
+            body: self.body,
+            params: self.params,
+            remote_addr: self.remote_addr,
+            data,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the uri is invalid.
+    pub fn build(self) -> Request<D> {
+        let uri = self.uri;
+        let mut origin = HyperRequest::builder()
+            .method(self.method)
+            .uri(&uri[..])
+            .body(self.body)
+            .unwrap_or_else(|e| panic!("invalid request uri '{}': {}", uri, e));
+        for (name, value) in self.headers {
+            origin.headers_mut().append(name, value);
+        }
+
+        let mut req = Request::from_internal(origin, self.remote_addr, Arc::new(self.data));
+        req.route_result = Some(RouteResult::from_params(self.params));
+        req
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::Method;
+    use super::RequestBuilder;
+
+    #[cfg(feature = "json")]
+    mod json {
+        use hyper::{Method, StatusCode};
+        use crate::{MiddlewareResult, Request, RequestBuilder, Response};
+        use crate::test_helpers;
+
+        #[derive(serde_derive::Deserialize)]
+        struct Rename {
+            name: String,
+        }
+
+        async fn rename_user(req: &mut Request<()>, res: Response<()>) -> MiddlewareResult<()> {
+            let id = try_with!(res, req.require_param("id")).to_string();
+            let rename = try_with!(res, req.json_as::<Rename>().await);
+            res.send(format!("User {} is now called {}", id, rename.name))
+        }
+
+        #[tokio::test]
+        async fn invokes_handler_with_params_and_json_body() {
+            let mut req = RequestBuilder::new()
+                .method(Method::PUT)
+                .uri("/users/42")
+                .header("content-type", "application/json")
+                .param("id", "42")
+                .body(r#"{ "name": "Alice" }"#)
+                .build();
+
+            let res = test_helpers::halted(rename_user(&mut req, test_helpers::response()).await);
+            assert_eq!(res.status(), StatusCode::OK);
+            assert_eq!(test_helpers::body_string(res).await, "User 42 is now called Alice");
+        }
+    }
+
+    #[test]
+    fn builds_request_parts() {
+        let req = RequestBuilder::new()
+            .uri("/search?q=nickel")
+            .header("accept", "text/html")
+            .header("accept", "application/json")
+            .remote_addr("127.0.0.1:4000".parse().unwrap())
+            .data(7u32)
+            .build();
+
+        assert_eq!(req.origin.method(), Method::GET);
+        assert_eq!(req.origin.uri().query(), Some("q=nickel"));
+        assert_eq!(req.origin.headers().get_all("accept").iter().count(), 2);
+        assert_eq!(req.remote_addr().map(|a| a.port()), Some(4000));
+        assert_eq!(*req.server_data(), 7);
+        assert_eq!(req.param("id"), None);
+    }
+}
@@ -0,0 +1,122 @@
+use async_trait::async_trait;
+use std::any::Any;
+use std::marker::PhantomData;
+use hyper::StatusCode;
+use typemap::Key;
+
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::{Middleware, MiddlewareResult};
+
+/// Runs the wrapped middleware only if an earlier middleware inserted a value
+/// for the key `K` into the request's extensions, otherwise the request is
+/// answered with an error status (`401 Unauthorized` by default).
+///
+/// This formalizes the common auth-gate pattern, where an authentication
+/// middleware stores the current user and later handlers require it.
+///
+/// # Examples
+/// ```{rust}
+/// #[macro_use] extern crate nickel;
+/// extern crate typemap;
+///
+/// use nickel::{Nickel, HttpRouter, RequireExt};
+/// use nickel::status::StatusCode;
+///
+/// struct CurrentUser;
+/// impl typemap::Key for CurrentUser { type Value = String; }
+///
+/// fn main() {
+///     let mut server = Nickel::new();
+///     server.get("/admin", RequireExt::<CurrentUser, _>::new(middleware! { |req|
+///         format!("Hello {}", req.extensions().get::<CurrentUser>().unwrap())
+///     }).status(StatusCode::FORBIDDEN));
+/// }
+/// ```
+pub struct RequireExt<K, M> {
+    inner: M,
+    status: StatusCode,
+    _key: PhantomData<fn() -> K>,
+}
+
+impl<K: Key, M> RequireExt<K, M> {
+    /// Wrap `inner` so it only runs for requests carrying a `K`.
+    pub fn new(inner: M) -> RequireExt<K, M> {
+        RequireExt {
+            inner,
+            status: StatusCode::UNAUTHORIZED,
+            _key: PhantomData,
+        }
+    }
+
+    /// The status to respond with when the key is missing.
+    ///
+    /// Defaults to `401 Unauthorized`.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+}
+
+#[async_trait]
+impl<D, K, M> Middleware<D> for RequireExt<K, M>
+where D: Send + 'static + Sync,
+      K: Key + 'static,
+      K::Value: Any + Send + Sync,
+      M: Middleware<D> {
+    async fn invoke(&self, req: &mut Request<D>, res: Response<D>)
+            -> MiddlewareResult<D> {
+        if req.extensions().contains::<K>() {
+            self.inner.invoke(req, res).await
+        } else {
+            res.error(self.status, "Required request extension is missing")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::StatusCode;
+    use typemap::Key;
+    use super::RequireExt;
+    use crate::{Action, Middleware, Request, Response};
+    use crate::test_helpers::{get, response};
+
+    struct Authenticated;
+    impl Key for Authenticated { type Value = String; }
+
+    fn pair() -> (Request<()>, Response<()>) {
+        (get("/"), response())
+    }
+
+    fn gate() -> RequireExt<Authenticated, impl Middleware<()>> {
+        RequireExt::new(middleware! { "secret" })
+    }
+
+    #[tokio::test]
+    async fn runs_inner_with_marker() {
+        let (mut req, res) = pair();
+        req.extensions_mut().insert::<Authenticated>("alice".to_string());
+
+        match gate().invoke(&mut req, res).await {
+            Ok(Action::Halt(res)) => assert_eq!(res.status(), StatusCode::OK),
+            _ => panic!("inner middleware should have responded")
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_without_marker() {
+        let (mut req, res) = pair();
+
+        match gate().status(StatusCode::FORBIDDEN).invoke(&mut req, res).await {
+            Err(err) => assert_eq!(err.stream.unwrap().status(), StatusCode::FORBIDDEN),
+            _ => panic!("request without marker should be rejected")
+        }
+
+        let (mut req, res) = pair();
+        match gate().invoke(&mut req, res).await {
+            Err(err) => assert_eq!(err.stream.unwrap().status(), StatusCode::UNAUTHORIZED),
+            _ => panic!("request without marker should be rejected")
+        }
+    }
+}
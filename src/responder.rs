@@ -12,6 +12,7 @@
 use crate::{Response, NickelError, MiddlewareResult, Halt};
 use hyper::StatusCode;
 use hyper::header;
+#[cfg(feature = "json")]
 use serde_json;
 use crate::mimes::MediaType;
 
@@ -30,6 +31,7 @@ impl<D: Send + 'static + Sync> Responder<D> for () {
     }
 }
 
+#[cfg(feature = "json")]
 impl<D: Send + 'static + Sync> Responder<D> for serde_json::Value {
     fn respond(self, mut res: Response<D>) -> MiddlewareResult<D> {
         maybe_set_type(&mut res, MediaType::Json);
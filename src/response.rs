@@ -1,26 +1,70 @@
 use std::borrow::Cow;
 use chrono::prelude::Utc;
 use std::path::Path;
+#[cfg(any(feature = "templates", feature = "json"))]
 use serde::Serialize;
 use hyper::{Body, Response as HyperResponse, StatusCode};
-use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue, InvalidHeaderValue};
 use crate::mimes::MediaType;
 use std::io;
 use crate::{NickelError, Halt, MiddlewareResult, Responder, Action};
+use crate::Request;
+use crate::ranges::ByteRange;
+use crate::hop_by_hop::strip_hop_by_hop_headers;
+use crate::util;
 use crate::template_cache::TemplateCache;
+#[cfg(feature = "templates")]
+use crate::template_cache::HeadRender;
+#[cfg(feature = "templates")]
+use crate::template_context::{self, ContextEntries};
 use modifier::Modifier;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use futures::Stream;
+#[cfg(feature = "json")]
+use futures::{future, stream, StreamExt};
+use hyper::body::Bytes;
 use tokio_util::codec::{BytesCodec, FramedRead};
 use typemap::{ShareMap, TypeMap};
 
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// When a client should retry a request, see `Response::retry_after`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryAfter {
+    /// After a delay, sent in seconds.
+    Delay(Duration),
+    /// At a point in time, sent as an HTTP-date.
+    At(SystemTime),
+}
+
+impl From<Duration> for RetryAfter {
+    fn from(delay: Duration) -> RetryAfter {
+        RetryAfter::Delay(delay)
+    }
+}
+
+impl From<SystemTime> for RetryAfter {
+    fn from(time: SystemTime) -> RetryAfter {
+        RetryAfter::At(time)
+    }
+}
+
 ///A container for the response
 pub struct Response<D: Send + 'static + Sync = ()> {
     ///the original `hyper::server::Response`
     pub origin: HyperResponse<Body>,
+    #[cfg_attr(not(feature = "templates"), allow(dead_code))]
     templates: Arc<TemplateCache>,
+    #[cfg_attr(not(feature = "templates"), allow(dead_code))]
+    head_request: bool,
     data: Arc<D>,
     map: ShareMap,
+    fallback_headers: bool,
+    #[cfg(feature = "templates")]
+    template_context: ContextEntries,
     // This should be FnBox, but that's currently unstable
     //on_send: Vec<Box<dyn FnMut(&mut Response<'a, D>)>>
 }
@@ -33,12 +77,21 @@ impl<D: Send + 'static + Sync> Response<D> {
         Response {
             origin: response,
             templates: templates,
+            head_request: false,
             data: data,
             map: TypeMap::custom(),
+            fallback_headers: true,
+            #[cfg(feature = "templates")]
+            template_context: ContextEntries::new(),
             //on_send: vec![]
         }
     }
 
+    // Marks the response as answering a `HEAD` request, for `render`.
+    pub(crate) fn set_head_request(&mut self) {
+        self.head_request = true;
+    }
+
     /// Get a mutable reference to the status.
     pub fn status_mut(&mut self) -> &mut StatusCode {
         self.origin.status_mut()
@@ -92,11 +145,242 @@ impl<D: Send + 'static + Sync> Response<D> {
         self.origin.headers_mut().insert(name.into(), value.into())
     }
 
+    /// Add a header value, keeping any values already set for `name`.
+    ///
+    /// Each value is sent as its own header line in the order it was added.
+    /// Use this for headers which must not be comma-joined, like `Set-Cookie`.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    /// use nickel::hyper::header::{self, HeaderValue};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.append_header(header::SET_COOKIE, HeaderValue::from_static("a=1"));
+    ///     res.append_header(header::SET_COOKIE, HeaderValue::from_static("b=2"));
+    ///     res.send("two cookies")
+    /// }
+    /// ```
+    pub fn append_header<N: Into<HeaderName>, V: Into<HeaderValue>>(&mut self, name: N, value: V) {
+        self.origin.headers_mut().append(name.into(), value.into());
+    }
+
+    // Adds `name` to the `Vary` header unless it is listed already.
+    pub(crate) fn add_vary(&mut self, name: &HeaderName) {
+        let listed = self.origin.headers()
+                                .get_all(header::VARY)
+                                .iter()
+                                .filter_map(|v| v.to_str().ok())
+                                .flat_map(|v| v.split(','))
+                                .any(|v| v.trim() == "*" || v.trim().eq_ignore_ascii_case(name.as_str()));
+        if !listed {
+            self.origin.headers_mut().append(header::VARY, HeaderValue::from_str(name.as_str()).unwrap());
+        }
+    }
+
+    /// Set a group of headers at once, e.g. a standard set of security
+    /// headers. Headers not mentioned in `headers` are kept. A header in
+    /// `headers` replaces a previously set value of the same name, while
+    /// repeating a name within `headers` adds all of its values.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    /// use nickel::hyper::header::{self, HeaderValue};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.with_headers(vec![
+    ///         (header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY")),
+    ///         (header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+    ///     ]);
+    ///     res.send("protected")
+    /// }
+    /// ```
+    pub fn with_headers<I>(&mut self, headers: I) -> &mut Response<D>
+    where I: IntoIterator<Item=(HeaderName, HeaderValue)> {
+        let mut seen = Vec::new();
+        for (name, value) in headers {
+            if seen.contains(&name) {
+                self.origin.headers_mut().append(name, value);
+            } else {
+                self.origin.headers_mut().insert(name.clone(), value);
+                seen.push(name);
+            }
+        }
+        self
+    }
+
+    /// Add a link to the `Link` header (RFC 8288, formerly RFC 5988), e.g. for
+    /// pagination. Multiple links are comma-joined into a single header.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     // Link: </items?page=3>; rel="next", </items?page=1>; rel="prev"
+    ///     res.add_link("/items?page=3", "next").unwrap();
+    ///     res.add_link("/items?page=1", "prev").unwrap();
+    ///     res.send("page 2")
+    /// }
+    /// ```
+    pub fn add_link(&mut self, url: &str, rel: &str) -> Result<(), InvalidHeaderValue> {
+        let link = format!("<{}>; rel=\"{}\"",
+                           url.replace('>', "%3E"),
+                           rel.replace('\\', "\\\\").replace('"', "\\\""));
+        let value = match self.headers().get(header::LINK).map(|v| v.to_str()) {
+            Some(Ok(existing)) => format!("{}, {}", existing, link),
+            _ => link
+        };
+        self.set_header(header::LINK, HeaderValue::from_str(&value)?);
+        Ok(())
+    }
+
+    /// Declares the charset of the body in the `Content-Type`, replacing the
+    /// `utf-8` that text media types come with, e.g. for legacy content in
+    /// `iso-8859-1`. Without a `Content-Type` set yet, this applies to the
+    /// `text/html` default.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult, MediaType};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.set(MediaType::Txt);
+    ///     // Content-Type: text/plain; charset=iso-8859-1
+    ///     res.set_charset("iso-8859-1").unwrap();
+    ///     res.send(&b"Gr\xfc\xdfe"[..])
+    /// }
+    /// ```
+    pub fn set_charset(&mut self, charset: &str) -> Result<(), InvalidHeaderValue> {
+        let content_type = match self.headers().get(header::CONTENT_TYPE).map(|v| v.to_str()) {
+            Some(Ok(content_type)) => content_type.to_string(),
+            _ => "text/html".to_string()
+        };
+        let mut parts: Vec<&str> = content_type.split(';')
+                                               .map(str::trim)
+                                               .filter(|part| !part.to_ascii_lowercase().starts_with("charset="))
+                                               .collect();
+        let charset = format!("charset={}", charset);
+        parts.push(&charset);
+        self.set_header(header::CONTENT_TYPE, HeaderValue::from_str(&parts.join("; "))?);
+        Ok(())
+    }
+
+    /// Add a metric to the `Server-Timing` header, which browser developer
+    /// tools show next to the request's own timings. Multiple metrics are
+    /// comma-joined into a single header.
+    ///
+    /// `name` must be a token, i.e. without spaces or separators, as a `,`
+    /// or `;` would corrupt the header. Metrics with other names are skipped
+    /// with a warning.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use std::time::Instant;
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     let start = Instant::now();
+    ///     let users = vec!["alice", "bob"]; // query the database
+    ///     // Server-Timing: db;dur=0.012;desc="Load users"
+    ///     res.add_server_timing("db", start.elapsed(), Some("Load users")).unwrap();
+    ///     res.send(users.join(", "))
+    /// }
+    /// ```
+    pub fn add_server_timing(&mut self, name: &str, duration: Duration, description: Option<&str>)
+            -> Result<(), InvalidHeaderValue> {
+        if !util::is_token(name) {
+            warn!("Skipping Server-Timing metric with invalid name '{}'", name.escape_debug());
+            return Ok(());
+        }
+
+        // milliseconds, with trailing zeros trimmed
+        let millis = format!("{:.3}", duration.as_secs_f64() * 1000.0);
+        let millis = millis.trim_end_matches('0').trim_end_matches('.');
+        let mut metric = format!("{};dur={}", name, millis);
+        if let Some(description) = description {
+            metric.push_str(&format!(";desc=\"{}\"",
+                                     description.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+
+        let value = match self.headers().get(SERVER_TIMING).map(|v| v.to_str()) {
+            Some(Ok(existing)) => format!("{}, {}", existing, metric),
+            _ => metric
+        };
+        self.set_header(SERVER_TIMING, HeaderValue::from_str(&value)?);
+        Ok(())
+    }
+
+    /// Sets `Retry-After` on `429 Too Many Requests` and
+    /// `503 Service Unavailable` responses. A `Duration` is sent in seconds,
+    /// rounded up so clients don't retry too early, and a `SystemTime` as an
+    /// HTTP-date.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use std::time::Duration;
+    /// use nickel::{Request, Response, MiddlewareResult};
+    /// use nickel::status::StatusCode;
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     // Retry-After: 30
+    ///     res.retry_after(Duration::from_secs(30));
+    ///     res.abort(StatusCode::TOO_MANY_REQUESTS)
+    /// }
+    /// ```
+    pub fn retry_after<T: Into<RetryAfter>>(&mut self, retry_after: T) {
+        let value = match retry_after.into() {
+            RetryAfter::Delay(delay) => {
+                let secs = delay.as_secs() + if delay.subsec_nanos() > 0 { 1 } else { 0 };
+                HeaderValue::from(secs)
+            },
+            // only ASCII, always a valid header value
+            RetryAfter::At(time) => HeaderValue::from_str(&util::http_date(time)).unwrap()
+        };
+        self.set_header(header::RETRY_AFTER, value);
+    }
+
     /// Set the body of the hyper response, discarding any already set
     pub fn set_body<T: Into<Body>>(&mut self, body: T) {
         *self.origin.body_mut() = body.into();
     }
 
+    /// Sets `Content-Length`, for bodies streamed with `set_body` whose size
+    /// is known up front. The body is then sent with fixed length framing
+    /// instead of chunked transfer encoding.
+    ///
+    /// Streaming a different number of bytes is logged as an error, the
+    /// client gets a truncated response in that case.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult, Halt};
+    /// use nickel::hyper::Body;
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     let (mut sender, body) = Body::channel();
+    ///     res.set_content_length(1024);
+    ///     res.set_body(body);
+    ///     tokio::spawn(async move {
+    ///         let _ = sender.send_data(vec![0u8; 1024].into()).await;
+    ///     });
+    ///     res.start();
+    ///     Ok(Halt(res))
+    /// }
+    /// ```
+    pub fn set_content_length(&mut self, len: u64) {
+        self.headers_mut().remove(header::TRANSFER_ENCODING);
+        self.set_header(header::CONTENT_LENGTH, HeaderValue::from(len));
+    }
+
     /// Writes a response
     ///
     /// # Examples
@@ -113,8 +397,162 @@ impl<D: Send + 'static + Sync> Response<D> {
         data.respond(self)
     }
 
+    /// Serializes `data` as JSON and sends it with a `Content-Type` of
+    /// `application/json`, unless a `Content-Type` was set already, e.g. to
+    /// add a charset.
+    ///
+    /// If `data` can't be serialized, nothing is sent and a
+    /// `500 Internal Server Error` is returned instead.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// #[macro_use] extern crate serde_derive;
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// #[derive(Serialize)]
+    /// struct User { id: u32, name: String }
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.send_json(&User { id: 1, name: "Alice".to_string() })
+    /// }
+    /// # fn main() {}
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn send_json<T: Serialize + ?Sized>(mut self, data: &T) -> MiddlewareResult<D> {
+        let body = match serde_json::to_vec(data) {
+            Ok(body) => body,
+            Err(e) => return self.error(StatusCode::INTERNAL_SERVER_ERROR,
+                                        format!("Failed to serialize JSON: {}", e))
+        };
+        self.set_header_fallback(&header::CONTENT_TYPE, &MediaType::Json.into());
+        self.send(body)
+    }
+
+    /// Streams `items` as a JSON array, serializing one element at a time
+    /// instead of building the whole document in memory. Sets a
+    /// `Content-Type` of `application/json` unless one was set already.
+    ///
+    /// The status and headers are sent before the first element is
+    /// serialized, so a serialization error can't turn into an error
+    /// response anymore. It is logged and the response is aborted, leaving
+    /// the client with an incomplete array.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     // [0,1,4,9,...]
+    ///     res.send_json_array((0..10_000u64).map(|i| i * i))
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn send_json_array<I, T>(mut self, items: I) -> MiddlewareResult<D>
+    where I: IntoIterator<Item=T>, I::IntoIter: Send + 'static, T: Serialize {
+        let elements = items.into_iter().enumerate().map(|(i, item)| {
+            let mut chunk = if i == 0 { Vec::new() } else { vec![b','] };
+            match serde_json::to_writer(&mut chunk, &item) {
+                Ok(()) => Ok(Bytes::from(chunk)),
+                Err(e) => {
+                    error!("Failed to serialize element {} of JSON array: {}", i, e);
+                    Err(e)
+                }
+            }
+        });
+        let body = stream::once(future::ready(Ok(Bytes::from_static(b"["))))
+                       .chain(stream::iter(elements))
+                       .chain(stream::once(future::ready(Ok(Bytes::from_static(b"]")))));
+
+        self.set_header_fallback(&header::CONTENT_TYPE, &MediaType::Json.into());
+        self.set_body(Body::wrap_stream(body));
+        self.start();
+        Ok(Halt(self))
+    }
+
+    /// Streams `body` to the client as it is produced, e.g. rows from a
+    /// database cursor or the body of an upstream response. Sets no
+    /// `Content-Type`, so it falls back to `text/html` unless set
+    /// beforehand.
+    ///
+    /// The status and headers are sent before the first chunk, so an error
+    /// from the stream can't turn into an error response anymore. Instead
+    /// the connection is aborted, which tells the client that the body is
+    /// incomplete.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult, MediaType};
+    /// use futures::stream;
+    ///
+    /// # #[allow(dead_code)]
+    /// fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     let lines = (1..=3).map(|i| Ok::<_, std::io::Error>(format!("line {}\n", i)));
+    ///     res.set(MediaType::Txt);
+    ///     res.send_stream(stream::iter(lines))
+    /// }
+    /// ```
+    pub fn send_stream<S, B, E>(mut self, body: S) -> MiddlewareResult<D>
+    where S: Stream<Item=Result<B, E>> + Send + 'static,
+          B: Into<Bytes> + 'static,
+          E: Into<Box<dyn std::error::Error + Send + Sync>> + 'static {
+        self.origin.headers_mut().remove(header::CONTENT_LENGTH);
+        self.set_body(Body::wrap_stream(body));
+        self.start();
+        Ok(Halt(self))
+    }
+
+    /// Relays `upstream`, e.g. the response of a backend fetched by a
+    /// proxying handler. Its status and end-to-end headers are copied, and
+    /// its body is streamed through as it arrives, without buffering.
+    ///
+    /// Hop-by-hop headers are stripped, see `strip_hop_by_hop_headers`.
+    /// Upstream headers replace headers of the same name set on the response
+    /// beforehand, other headers are kept. As with any response, a missing
+    /// `Date`, `Server` or `Content-Type` gets a fallback unless
+    /// `no_fallback_headers` was called.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    /// use nickel::hyper::{Body, Client};
+    ///
+    /// # #[allow(dead_code)]
+    /// async fn proxy<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     let uri = format!("http://backend.internal{}", req.origin.uri()).parse().unwrap();
+    ///     match Client::new().get(uri).await {
+    ///         Ok(upstream) => res.pipe_from(upstream),
+    ///         Err(e) => res.error(nickel::status::StatusCode::BAD_GATEWAY, e.to_string())
+    ///     }
+    /// }
+    /// ```
+    pub fn pipe_from(mut self, upstream: HyperResponse<Body>) -> MiddlewareResult<D> {
+        let (mut parts, body) = upstream.into_parts();
+        strip_hop_by_hop_headers(&mut parts.headers);
+
+        self.set(parts.status);
+        let headers = self.origin.headers_mut();
+        for name in parts.headers.keys() {
+            headers.remove(name);
+            for value in parts.headers.get_all(name) {
+                headers.append(name, value.clone());
+            }
+        }
+        self.start();
+        self.set_body(body);
+        Ok(Halt(self))
+    }
+
     /// Writes a file to the output.
     ///
+    /// The `Content-Length` is taken from the file on disk. A `Content-Type`
+    /// set beforehand is kept, so a precompressed asset like `app.js.gz` can
+    /// be sent with the type of `app.js` and a `Content-Encoding` of `gzip`.
+    ///
+    /// Files are advertised with `Accept-Ranges: bytes`, unlike other streamed
+    /// bodies, which are sent with `Accept-Ranges: none`.
+    ///
     /// # Examples
     /// ```{rust}
     /// use nickel::{Request, Response, MiddlewareResult};
@@ -128,7 +566,7 @@ impl<D: Send + 'static + Sync> Response<D> {
     /// ```
     pub async fn send_file<P:AsRef<Path>>(mut self, path: P) -> MiddlewareResult<D> {
         let path = path.as_ref();
-        // Chunk the response
+        // Chunk the response unless the file size is known below
         self.origin.headers_mut().remove(header::CONTENT_LENGTH);
         // Determine content type by file extension or default to binary
         let mime = mime_from_filename(path).unwrap_or(MediaType::Bin);
@@ -137,6 +575,12 @@ impl<D: Send + 'static + Sync> Response<D> {
         self.start();
         match File::open(path).await {
             Ok(file) => {
+                self.set_header_fallback(&header::ACCEPT_RANGES, &HeaderValue::from_static("bytes"));
+                // The length of the file actually sent, which for a
+                // precompressed asset is the compressed size
+                if let Ok(metadata) = file.metadata().await {
+                    self.set_header(header::CONTENT_LENGTH, HeaderValue::from(metadata.len()));
+                }
                 let stream = FramedRead::new(file, BytesCodec::new());
                 let body = Body::wrap_stream(stream);
                 self.set_body(body);
@@ -149,11 +593,114 @@ impl<D: Send + 'static + Sync> Response<D> {
         }
     }
 
+    /// Like `send_file`, but honors a `Range` header of `req`, so clients
+    /// can resume downloads. A satisfiable range is answered with
+    /// `206 Partial Content`, the matching `Content-Range` and only the
+    /// requested bytes, a malformed one or one beyond the end of the file
+    /// with `416 Range Not Satisfiable`.
+    ///
+    /// Like `RangedBody`, only single ranges are supported. Requests with
+    /// several ranges get the whole file.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// async fn handler<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.send_file_ranged("/srv/downloads/installer.iso", req).await
+    /// }
+    /// ```
+    pub async fn send_file_ranged<P: AsRef<Path>>(mut self, path: P, req: &Request<D>) -> MiddlewareResult<D> {
+        let path = path.as_ref();
+        let len = match tokio::fs::metadata(path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return self.send_file(path).await
+        };
+        let (start, end) = match ByteRange::of_request(req, len) {
+            ByteRange::Full => return self.send_file(path).await,
+            ByteRange::Partial(start, end) => (start, end),
+            ByteRange::Unsatisfiable | ByteRange::Malformed => {
+                self.set_header(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+                self.set_header(header::CONTENT_RANGE, HeaderValue::from_str(&format!("bytes */{}", len)).unwrap());
+                return self.abort(StatusCode::RANGE_NOT_SATISFIABLE);
+            }
+        };
+
+        let mut file = match File::open(path).await {
+            Ok(file) => file,
+            Err(e) => return self.error(StatusCode::NOT_FOUND,
+                                        format!("Failed to send file '{:?}': {}", path, e))
+        };
+        if let Err(e) = file.seek(io::SeekFrom::Start(start)).await {
+            return self.error(StatusCode::INTERNAL_SERVER_ERROR,
+                              format!("Failed to send file '{:?}': {}", path, e));
+        }
+
+        let mime = mime_from_filename(path).unwrap_or(MediaType::Bin);
+        self.set_header_fallback(&header::CONTENT_TYPE, &mime.into());
+        self.set(StatusCode::PARTIAL_CONTENT);
+        self.set_header(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        self.set_header(header::CONTENT_RANGE,
+                        HeaderValue::from_str(&format!("bytes {}-{}/{}", start, end, len)).unwrap());
+        self.set_header(header::CONTENT_LENGTH, HeaderValue::from(end - start + 1));
+        self.start();
+        let stream = FramedRead::new(file.take(end - start + 1), BytesCodec::new());
+        self.set_body(Body::wrap_stream(stream));
+        Ok(Halt(self))
+    }
+
+    /// Like `send_file`, but asks the browser to display the file itself
+    /// (e.g. a PDF) while suggesting `filename` for saving it, through a
+    /// `Content-Disposition: inline; filename="..."` header.
+    ///
+    /// Non-ASCII filenames are sent UTF-8 encoded as `filename*`, with an
+    /// ASCII approximation in `filename` for older clients.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// async fn handler<D: Send + 'static + Sync>(_: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.send_file_inline("/srv/reports/2024-q1.pdf", "Quarterly report.pdf").await
+    /// }
+    /// ```
+    pub async fn send_file_inline<P: AsRef<Path>>(mut self, path: P, filename: &str) -> MiddlewareResult<D> {
+        self.set_header(header::CONTENT_DISPOSITION, content_disposition("inline", filename));
+        self.send_file(path).await
+    }
+
+    /// Keeps the `Date`, `Server` and `Content-Type` headers from being
+    /// added when the response starts, for byte-exact responses, e.g. when
+    /// relaying an upstream response.
+    ///
+    /// Headers needed for a valid HTTP response are still taken care of by
+    /// hyper, which adds `Date` and frames the body with `Content-Length`
+    /// or chunked `Transfer-Encoding`.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn relay<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     res.no_fallback_headers();
+    ///     res.send("exactly these bytes")
+    /// }
+    /// ```
+    pub fn no_fallback_headers(&mut self) {
+        self.fallback_headers = false;
+    }
+
     // TODO: This needs to be more sophisticated to return the correct headers
     // not just "some headers" :)
     //
     // Also, it should only set them if not already set.
     fn set_fallback_headers(&mut self) {
+        if !self.fallback_headers {
+            return;
+        }
         let now = HeaderValue::from_str(&Utc::now().to_rfc2822()).unwrap(); // rfc2822 should always be valid
         self.set_header_fallback(&header::DATE, &now);
         self.set_header_fallback(&header::SERVER, &HeaderValue::from_static("Nickel"));
@@ -167,6 +714,37 @@ impl<D: Send + 'static + Sync> Response<D> {
         Err(NickelError::new(self, message, status))
     }
 
+    /// Finishes the response right away with `status` and an empty body.
+    ///
+    /// Unlike `error`, this doesn't involve the error handlers: the response
+    /// is sent exactly as built, which suits middleware that just wants to
+    /// turn a request away.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Request, Response, MiddlewareResult};
+    /// use nickel::hyper::{header, StatusCode};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn admins_only<D>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     if req.origin.headers().get(header::AUTHORIZATION).is_none() {
+    ///         return res.abort(StatusCode::FORBIDDEN);
+    ///     }
+    ///     res.next_middleware()
+    /// }
+    /// ```
+    pub fn abort(self, status: StatusCode) -> MiddlewareResult<D> {
+        self.abort_with(status, Body::empty())
+    }
+
+    /// Like `abort`, but sends `body` along with the status.
+    pub fn abort_with<T: Into<Body>>(mut self, status: StatusCode, body: T) -> MiddlewareResult<D> {
+        self.set(status);
+        self.start();
+        self.set_body(body);
+        Ok(Halt(self))
+    }
+
     /// Sets the header if not already set.
     ///
     /// If the header is not set then `f` will be called.
@@ -199,8 +777,45 @@ impl<D: Send + 'static + Sync> Response<D> {
         self.origin.headers_mut().entry(name).or_insert(value.clone());
     }
 
+    /// Adds the entries of `data`, which has to serialize to a map, to the
+    /// variables available to templates rendered with this response. The
+    /// data passed to `render` wins over them on conflicting keys, entries
+    /// added later win over earlier ones. See `TemplateContext` for adding
+    /// variables to every request.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use std::collections::HashMap;
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// fn csrf<D: Send + 'static + Sync>(_: &mut Request<D>, mut res: Response<D>) -> MiddlewareResult<D> {
+    ///     let mut context = HashMap::new();
+    ///     context.insert("csrf_token", "4f2a...".to_string());
+    ///     res.add_template_context(&context).unwrap();
+    ///     res.next_middleware()
+    /// }
+    /// ```
+    #[cfg(feature = "templates")]
+    pub fn add_template_context<T: Serialize>(&mut self, data: &T) -> Result<(), String> {
+        let entries = template_context::context_entries(data)?;
+        self.template_context.extend(entries);
+        Ok(())
+    }
+
     /// Renders the given template bound with the given data.
     ///
+    /// Values interpolated with `{{name}}` are HTML-escaped, which keeps
+    /// user provided content from injecting markup. Trusted markup, e.g.
+    /// HTML rendered from markdown by trusted code, is inserted as it is
+    /// with `{{{name}}}` or `{{& name}}` in the template.
+    ///
+    /// Variables added through `add_template_context` or `TemplateContext`
+    /// are available as well, unless `data` has an entry of the same name.
+    ///
+    /// For `HEAD` requests no body is sent, and the template is only
+    /// rendered to find its length if `Options::head_render` asks for it.
+    ///
     /// # Examples
     /// ```{rust}
     /// use std::collections::HashMap;
@@ -213,11 +828,30 @@ impl<D: Send + 'static + Sync> Response<D> {
     ///     res.render("examples/assets/template.tpl", &data)
     /// }
     /// ```
+    #[cfg(feature = "templates")]
     pub async fn render<T, P>(mut self, path: P, data: &T) -> MiddlewareResult<D>
         where T: Serialize, P: AsRef<Path> + Into<String> {
 
         self.start();
-        match self.templates.render(path, data).await {
+        if self.head_request {
+            if let HeadRender::Skip = self.templates.head_render() {
+                return Ok(Halt(self));
+            }
+        }
+        let rendered = if self.template_context.is_empty() {
+            self.templates.render(path, data).await
+        } else {
+            let context = std::mem::take(&mut self.template_context);
+            match template_context::merge_context(context, data) {
+                Ok(merged) => self.templates.render(path, &merged).await,
+                Err(e) => Err(e.into())
+            }
+        };
+        match rendered {
+            Ok(r) if self.head_request => {
+                self.set_header(header::CONTENT_LENGTH, HeaderValue::from(r.len()));
+                Ok(Halt(self))
+            },
             Ok(r) => self.send(r),
             Err(e) => {
                 let msg = format!("Problem rendering template: {:?}", e);
@@ -227,6 +861,61 @@ impl<D: Send + 'static + Sync> Response<D> {
         }
     }
 
+    /// Renders the given template like `render`, but tags the response with
+    /// the given `etag`. If the request's `If-None-Match` header already
+    /// matches `etag`, the template is not rendered at all and an empty
+    /// `304 Not Modified` is returned instead.
+    ///
+    /// Choosing the ETag is left to the caller, it should change whenever
+    /// the rendered output would change, e.g. a version number or the last
+    /// modification time of the underlying data. Unquoted values are quoted.
+    ///
+    /// If `Options::cache_key_headers` are configured, the ETag is
+    /// suffixed with a hash of the request's values for them, and they are
+    /// added to `Vary`, so each variant is revalidated on its own.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use std::collections::HashMap;
+    /// use nickel::{Request, Response, MiddlewareResult};
+    ///
+    /// # #[allow(dead_code)]
+    /// async fn handler<D: Send + 'static + Sync>(req: &mut Request<D>, res: Response<D>) -> MiddlewareResult<D> {
+    ///     let mut data = HashMap::new();
+    ///     data.insert("name", "user");
+    ///     res.render_cached(req, "examples/assets/template.tpl", &data, "v1").await
+    /// }
+    /// ```
+    #[cfg(feature = "templates")]
+    pub async fn render_cached<T, P>(mut self, req: &Request<D>, path: P, data: &T, etag: &str) -> MiddlewareResult<D>
+        where T: Serialize, P: AsRef<Path> + Into<String> {
+        let templates = self.templates.clone();
+        let key_headers = templates.cache_key_headers();
+        for name in key_headers {
+            self.add_vary(name);
+        }
+
+        let etag = variant_etag(quote_etag(etag), req, key_headers);
+        let etag_value = match HeaderValue::from_str(&etag) {
+            Ok(v) => v,
+            Err(e) => return self.error(StatusCode::INTERNAL_SERVER_ERROR,
+                                        format!("Invalid ETag '{}': {}", etag, e))
+        };
+        self.set_header(header::ETAG, etag_value);
+
+        let fresh = req.origin.headers()
+                              .get_all(header::IF_NONE_MATCH)
+                              .iter()
+                              .any(|v| etag_matches(v, &etag));
+        if fresh {
+            self.set(StatusCode::NOT_MODIFIED);
+            self.start();
+            return Ok(Halt(self));
+        }
+
+        self.render(path, data).await
+    }
+
     // Todo: migration cleanup
     //
     // hyper::Response no longer has a start() method. The api has
@@ -351,6 +1040,81 @@ fn mime_from_filename<P: AsRef<Path>>(path: P) -> Option<MediaType> {
         .and_then(|s| s.parse().ok())
 }
 
+// A `Content-Disposition` header value (RFC 6266) of type `disposition`
+// suggesting `filename`.
+fn content_disposition(disposition: &str, filename: &str) -> HeaderValue {
+    let mut fallback = String::with_capacity(filename.len());
+    for c in filename.chars() {
+        match c {
+            '"' | '\\' => { fallback.push('\\'); fallback.push(c); },
+            ' '..='~' => fallback.push(c),
+            _ => fallback.push('_')
+        }
+    }
+
+    let mut value = format!("{}; filename=\"{}\"", disposition, fallback);
+    if !filename.is_ascii() {
+        // RFC 5987 ext-value, only attr-chars are left unencoded
+        value.push_str("; filename*=UTF-8''");
+        for &b in filename.as_bytes() {
+            match b {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' |
+                b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' |
+                b'^' | b'_' | b'`' | b'|' | b'~' => value.push(b as char),
+                _ => value.push_str(&format!("%{:02X}", b))
+            }
+        }
+    }
+
+    // Only visible ASCII is left in `value`
+    HeaderValue::from_str(&value).unwrap()
+}
+
+// Wraps an ETag in double quotes unless it is already a (weak) quoted tag.
+#[cfg(feature = "templates")]
+fn quote_etag(etag: &str) -> String {
+    if etag.ends_with('"') && (etag.starts_with('"') || etag.starts_with("W/\"")) {
+        etag.to_string()
+    } else {
+        format!("\"{}\"", etag)
+    }
+}
+
+// Suffixes the quoted `etag` with a hash of the request's values for
+// `key_headers`, if any.
+#[cfg(feature = "templates")]
+fn variant_etag<D>(etag: String, req: &Request<D>, key_headers: &[HeaderName]) -> String {
+    if key_headers.is_empty() {
+        return etag;
+    }
+
+    // One `name: value` line per value, header values can't contain newlines
+    let mut key = Vec::new();
+    for name in key_headers {
+        for value in req.origin.headers().get_all(name) {
+            key.extend_from_slice(name.as_str().as_bytes());
+            key.extend_from_slice(b": ");
+            key.extend_from_slice(value.as_bytes());
+            key.push(b'\n');
+        }
+    }
+    format!("{}-{:x}\"", &etag[..etag.len() - 1], crate::util::fnv1a(&key))
+}
+
+// Weak comparison of `etag` against an `If-None-Match` header value, see
+// https://tools.ietf.org/html/rfc7232#section-3.2
+pub(crate) fn etag_matches(if_none_match: &HeaderValue, etag: &str) -> bool {
+    let strip_weak = |tag: &str| -> String {
+        tag.trim().trim_start_matches("W/").to_string()
+    };
+    let etag = strip_weak(etag);
+
+    match if_none_match.to_str() {
+        Ok(value) => value.split(',').any(|tag| tag.trim() == "*" || strip_weak(tag) == etag),
+        Err(_) => false
+    }
+}
+
 #[test]
 fn matches_content_type () {
     assert_eq!(Some(MediaType::Txt), mime_from_filename("test.txt"));
@@ -358,6 +1122,493 @@ fn matches_content_type () {
     assert_eq!(Some(MediaType::Bin), mime_from_filename("test.bin"));
 }
 
+#[test]
+fn formats_content_disposition() {
+    assert_eq!(content_disposition("inline", "report.pdf"), "inline; filename=\"report.pdf\"");
+    assert_eq!(content_disposition("inline", "say \"hi\".txt"), "inline; filename=\"say \\\"hi\\\".txt\"");
+    assert_eq!(content_disposition("inline", "Übersicht 1.pdf"),
+               "inline; filename=\"_bersicht 1.pdf\"; filename*=UTF-8''%C3%9Cbersicht%201.pdf");
+}
+
+#[cfg(feature = "templates")]
+#[test]
+fn matches_etags () {
+    assert_eq!(quote_etag("v1"), "\"v1\"");
+    assert_eq!(quote_etag("\"v1\""), "\"v1\"");
+    assert_eq!(quote_etag("W/\"v1\""), "W/\"v1\"");
+
+    let etag = quote_etag("v1");
+    assert!(etag_matches(&HeaderValue::from_static("\"v1\""), &etag));
+    assert!(etag_matches(&HeaderValue::from_static("\"v0\", W/\"v1\""), &etag));
+    assert!(etag_matches(&HeaderValue::from_static("*"), &etag));
+    assert!(!etag_matches(&HeaderValue::from_static("\"v2\""), &etag));
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(any(feature = "templates", feature = "json"))]
+    use std::collections::HashMap;
+    #[cfg(feature = "templates")]
+    use std::sync::Arc;
+    use hyper::{Body, Response as HyperResponse, StatusCode};
+    use hyper::Request as HyperRequest;
+    use hyper::header;
+    #[cfg(feature = "templates")]
+    use crate::{Request, Response};
+    #[cfg(feature = "templates")]
+    use crate::template_cache::{ReloadPolicy, TemplateCache};
+    use crate::test_helpers::{body_string, halted, request, response};
+    #[cfg(feature = "templates")]
+    use crate::test_helpers::response_with_templates;
+
+    #[tokio::test]
+    async fn fallback_headers_can_be_disabled() {
+        use std::convert::Infallible;
+        use hyper::server::conn::Http;
+        use hyper::service::service_fn;
+        use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+        let res = halted(response().send("hi"));
+        assert_eq!(res.headers()[header::SERVER], "Nickel");
+
+        let mut res = response();
+        res.no_fallback_headers();
+        let res = halted(res.send("hi"));
+        assert!(res.headers().get(header::SERVER).is_none());
+        assert!(res.headers().get(header::DATE).is_none());
+
+        // still a valid response on the wire
+        let (mut client, server) = duplex(1024);
+        let mut origin = Some(res.origin);
+        let service = service_fn(move |_| {
+            let res = origin.take().expect("a single request");
+            async move { Ok::<_, Infallible>(res) }
+        });
+        tokio::spawn(Http::new().http1_only(true).serve_connection(server, service));
+
+        client.write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n").await.unwrap();
+        let mut raw = String::new();
+        client.read_to_string(&mut raw).await.unwrap();
+        let raw = raw.to_lowercase();
+        assert!(raw.starts_with("http/1.1 200 ok\r\n"), "{}", raw);
+        assert!(raw.contains("\r\ncontent-length: 2\r\n"), "{}", raw);
+        assert!(raw.contains("\r\ndate: "), "{}", raw);
+        assert!(!raw.contains("\r\nserver: "), "{}", raw);
+        assert!(raw.ends_with("\r\n\r\nhi"), "{}", raw);
+    }
+
+    #[tokio::test]
+    async fn abort_finishes_with_status() {
+        let res = halted(response().abort(StatusCode::FORBIDDEN));
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        assert_eq!(body_string(res).await, "");
+    }
+
+    #[tokio::test]
+    async fn abort_with_sends_custom_body() {
+        let res = halted(response().abort_with(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+                                               "Blocked in your region"));
+        assert_eq!(res.status(), StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+        assert_eq!(body_string(res).await, "Blocked in your region");
+    }
+
+    #[test]
+    fn with_headers_merges_into_existing() {
+        let mut res = response();
+        res.set_header(header::CACHE_CONTROL, header::HeaderValue::from_static("no-store"));
+        res.set_header(header::X_FRAME_OPTIONS, header::HeaderValue::from_static("SAMEORIGIN"));
+
+        res.with_headers(vec![
+            (header::X_FRAME_OPTIONS, header::HeaderValue::from_static("DENY")),
+            (header::X_CONTENT_TYPE_OPTIONS, header::HeaderValue::from_static("nosniff")),
+            (header::VARY, header::HeaderValue::from_static("Accept")),
+            (header::VARY, header::HeaderValue::from_static("Origin")),
+        ]);
+
+        let headers = res.headers();
+        assert_eq!(headers[header::CACHE_CONTROL], "no-store");
+        assert_eq!(headers.get_all(header::X_FRAME_OPTIONS).iter().collect::<Vec<_>>(), vec!["DENY"]);
+        assert_eq!(headers[header::X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers.get_all(header::VARY).iter().collect::<Vec<_>>(), vec!["Accept", "Origin"]);
+    }
+
+    #[tokio::test]
+    async fn send_file_reports_size_of_precompressed_file() {
+        let path = std::env::temp_dir().join(format!("nickel-send-file-{}.js.gz", std::process::id()));
+        let compressed = vec![0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03];
+        std::fs::write(&path, &compressed).unwrap();
+
+        let mut res = response();
+        res.set_header(header::CONTENT_TYPE, header::HeaderValue::from_static("application/javascript"));
+        res.set_header(header::CONTENT_ENCODING, header::HeaderValue::from_static("gzip"));
+        let res = halted(res.send_file(&path).await);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], compressed.len().to_string().as_str());
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/javascript");
+        assert_eq!(res.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(res.headers()[header::ACCEPT_RANGES], "bytes");
+    }
+
+    #[tokio::test]
+    async fn send_file_ranged_sends_requested_bytes() {
+        let path = std::env::temp_dir().join(format!("nickel-ranged-{}.txt", std::process::id()));
+        std::fs::write(&path, b"0123456789").unwrap();
+        let ranged = |range: &str| request(HyperRequest::get("/download").header("range", range));
+
+        let res = halted(response().send_file_ranged(&path, &ranged("bytes=2-5")).await);
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 2-5/10");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "4");
+        assert_eq!(res.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+        assert_eq!(body_string(res).await, "2345");
+
+        let res = halted(response().send_file_ranged(&path, &ranged("bytes=-3")).await);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 7-9/10");
+        assert_eq!(body_string(res).await, "789");
+
+        for range in &["bytes=10-", "bytes=abc", "bytes=5-2"] {
+            let res = halted(response().send_file_ranged(&path, &ranged(range)).await);
+            assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE, "{}", range);
+            assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes */10", "{}", range);
+        }
+
+        let res = halted(response().send_file_ranged(&path, &request(HyperRequest::get("/download"))).await);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(body_string(res).await, "0123456789");
+    }
+
+    #[tokio::test]
+    async fn pipe_from_relays_upstream_responses() {
+        use futures::channel::mpsc;
+        use futures::SinkExt;
+
+        let (mut sender, chunks) = mpsc::channel::<Result<&'static str, std::io::Error>>(1);
+        let upstream = HyperResponse::builder()
+            .status(StatusCode::CREATED)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::LOCATION, "/users/42")
+            .header(header::CONNECTION, "keep-alive, x-upstream-hop")
+            .header("keep-alive", "timeout=5")
+            .header("x-upstream-hop", "1")
+            .header(header::SET_COOKIE, "a=1")
+            .header(header::SET_COOKIE, "b=2")
+            .body(Body::wrap_stream(chunks))
+            .unwrap();
+
+        let mut res = response();
+        res.set_header(header::CONTENT_TYPE, header::HeaderValue::from_static("text/plain"));
+        res.set_header(header::X_FRAME_OPTIONS, header::HeaderValue::from_static("DENY"));
+        let res = halted(res.pipe_from(upstream));
+
+        assert_eq!(res.status(), StatusCode::CREATED);
+        let headers = res.headers();
+        assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+        assert_eq!(headers[header::LOCATION], "/users/42");
+        assert_eq!(headers.get_all(header::SET_COOKIE).iter().collect::<Vec<_>>(), vec!["a=1", "b=2"]);
+        assert_eq!(headers[header::X_FRAME_OPTIONS], "DENY");
+        for hop in &["connection", "keep-alive", "x-upstream-hop"] {
+            assert!(headers.get(*hop).is_none(), "{} was relayed", hop);
+        }
+
+        // streamed, not buffered
+        sender.send(Ok("{\"id\":")).await.unwrap();
+        let mut body = res.origin.into_body();
+        assert_eq!(&hyper::body::HttpBody::data(&mut body).await.unwrap().unwrap()[..], b"{\"id\":");
+        sender.send(Ok("42}")).await.unwrap();
+        drop(sender);
+        assert_eq!(&hyper::body::to_bytes(body).await.unwrap()[..], b"42}");
+    }
+
+    #[tokio::test]
+    async fn send_file_inline_sets_disposition() {
+        let path = std::env::temp_dir().join(format!("nickel-inline-{}.pdf", std::process::id()));
+        std::fs::write(&path, b"%PDF-1.4").unwrap();
+
+        let res = halted(response().send_file_inline(&path, "Quarterly report.pdf").await);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(res.headers()[header::CONTENT_DISPOSITION], "inline; filename=\"Quarterly report.pdf\"");
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/pdf");
+        assert_eq!(body_string(res).await, "%PDF-1.4");
+    }
+
+    #[test]
+    fn appended_cookies_stay_separate_and_ordered() {
+        let mut res = response();
+        res.append_header(header::SET_COOKIE, header::HeaderValue::from_static("a=1"));
+        res.append_header(header::SET_COOKIE, header::HeaderValue::from_static("b=2; Path=/"));
+        res.append_header(header::SET_COOKIE, header::HeaderValue::from_static("c=3"));
+
+        let cookies: Vec<_> = res.headers().get_all(header::SET_COOKIE).iter().collect();
+        assert_eq!(cookies, vec!["a=1", "b=2; Path=/", "c=3"]);
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn send_json_serializes_data() {
+        #[derive(Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
+        struct User { id: u32, name: String }
+
+        let user = User { id: 1, name: "Alice".to_string() };
+        let res = halted(response().send_json(&user));
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(serde_json::from_str::<User>(&body_string(res).await).unwrap(), user);
+
+        let mut res = response();
+        res.set_header(header::CONTENT_TYPE, header::HeaderValue::from_static("application/json; charset=utf-8"));
+        let res = halted(res.send_json(&user));
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json; charset=utf-8");
+
+        // maps with non-string keys can't be represented in JSON
+        let mut unrepresentable = std::collections::HashMap::new();
+        unrepresentable.insert((1, 2), "pair");
+        let err = response().send_json(&unrepresentable).err().expect("expected an error");
+        assert_eq!(err.stream.unwrap().status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn send_json_array_streams_elements() {
+        #[derive(serde_derive::Serialize)]
+        struct User { id: u32, name: &'static str }
+
+        let users = vec![User { id: 1, name: "Alice" }, User { id: 2, name: "Bob" }, User { id: 3, name: "Carol" }];
+        let res = halted(response().send_json_array(users));
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+
+        let body: serde_json::Value = serde_json::from_str(&body_string(res).await).unwrap();
+        assert_eq!(body, serde_json::json!([
+            { "id": 1, "name": "Alice" },
+            { "id": 2, "name": "Bob" },
+            { "id": 3, "name": "Carol" }
+        ]));
+
+        let res = halted(response().send_json_array(Vec::<u32>::new()));
+        assert_eq!(body_string(res).await, "[]");
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn send_json_array_aborts_on_serialization_error() {
+        // maps with non-string keys can't be represented in JSON
+        let mut invalid = HashMap::new();
+        invalid.insert((1, 2), "tuple key");
+        let res = halted(response().send_json_array(vec![HashMap::new(), invalid]));
+
+        assert!(hyper::body::to_bytes(res.origin.into_body()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn streams_chunks_as_they_arrive() {
+        use futures::channel::mpsc;
+        use futures::SinkExt;
+        use hyper::body::HttpBody;
+
+        let (mut tx, rx) = mpsc::channel::<Result<String, std::io::Error>>(1);
+        let res = halted(response().send_stream(rx));
+        assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+
+        tokio::spawn(async move {
+            for i in 1..=3 {
+                tx.send(Ok(format!("chunk {};", i))).await.unwrap();
+            }
+        });
+        let mut body = res.origin.into_body();
+        let mut chunks = Vec::new();
+        while let Some(chunk) = body.data().await {
+            chunks.push(String::from_utf8(chunk.unwrap().to_vec()).unwrap());
+        }
+        assert_eq!(chunks, vec!["chunk 1;", "chunk 2;", "chunk 3;"]);
+
+        let (mut tx, rx) = mpsc::channel(2);
+        tx.send(Ok("partial")).await.unwrap();
+        tx.send(Err(std::io::Error::new(std::io::ErrorKind::Other, "cursor closed"))).await.unwrap();
+        let res = halted(response().send_stream(rx));
+        assert!(hyper::body::to_bytes(res.origin.into_body()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn text_responses_declare_charset() {
+        use crate::MediaType;
+
+        let res = halted(response().send("<h1>Hello</h1>"));
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+
+        let res = halted(response().send(vec![1u8, 2, 3]));
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/octet-stream");
+
+        let mut res = response();
+        res.set(MediaType::Txt);
+        res.set_charset("iso-8859-1").unwrap();
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain; charset=iso-8859-1");
+
+        let mut res = response();
+        res.set_charset("windows-1252").unwrap();
+        let res = halted(res.send("legacy"));
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html; charset=windows-1252");
+    }
+
+    #[test]
+    fn retry_after_sends_seconds_or_date() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let mut res = response();
+        res.retry_after(Duration::from_secs(120));
+        assert_eq!(res.headers()[header::RETRY_AFTER], "120");
+
+        res.retry_after(Duration::from_millis(1500));
+        assert_eq!(res.headers()[header::RETRY_AFTER], "2");
+
+        res.retry_after(UNIX_EPOCH + Duration::from_secs(1445412480));
+        assert_eq!(res.headers()[header::RETRY_AFTER], "Wed, 21 Oct 2015 07:28:00 GMT");
+
+        res.retry_after(UNIX_EPOCH + Duration::from_millis(1_000_000_000_500));
+        assert_eq!(res.headers()[header::RETRY_AFTER], "Sun, 09 Sep 2001 01:46:40 GMT");
+    }
+
+    #[test]
+    fn add_server_timing_joins_metrics() {
+        use std::time::Duration;
+
+        let mut res = response();
+        res.add_server_timing("db", Duration::from_micros(53_200), Some("Load \"users\"")).unwrap();
+        res.add_server_timing("cache", Duration::from_millis(2), None).unwrap();
+        res.add_server_timing("a, b;dur=0", Duration::from_millis(1), None).unwrap();
+        res.add_server_timing("", Duration::from_millis(1), None).unwrap();
+
+        assert_eq!(res.headers().get_all("server-timing").iter().count(), 1);
+        assert_eq!(res.headers()["server-timing"], "db;dur=53.2;desc=\"Load \\\"users\\\"\", cache;dur=2");
+    }
+
+    #[test]
+    fn add_link_formats_relations() {
+        let mut res = response();
+        res.add_link("https://example.com/items?page=3", "next").unwrap();
+        res.add_link("https://example.com/items?page=1", "prev").unwrap();
+
+        assert_eq!(res.headers().get_all(header::LINK).iter().count(), 1);
+        assert_eq!(res.headers().get(header::LINK).unwrap(),
+                   "<https://example.com/items?page=3>; rel=\"next\", \
+                    <https://example.com/items?page=1>; rel=\"prev\"");
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn render_cached_renders_with_etag() {
+        let req = request(HyperRequest::get("/"));
+        let mut data = HashMap::new();
+        data.insert("name", "user");
+
+        let res = halted(response().render_cached(&req, "examples/assets/template.tpl", &data, "v1").await);
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers().get(header::ETAG).unwrap(), "\"v1\"");
+        assert!(body_string(res).await.contains("Hello user!"));
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn render_cached_answers_matching_etag_with_304() {
+        let req = request(HyperRequest::get("/").header(header::IF_NONE_MATCH, "\"v1\""));
+        let mut data = HashMap::new();
+        data.insert("name", "user");
+
+        // The template path does not exist, so this would fail if rendered
+        let res = halted(response().render_cached(&req, "does/not/exist.tpl", &data, "v1").await);
+
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers().get(header::ETAG).unwrap(), "\"v1\"");
+        assert_eq!(body_string(res).await, "");
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn render_cached_varies_on_cache_key_headers() {
+        let templates = Arc::new(TemplateCache::with_policy(ReloadPolicy::Never)
+                                     .with_cache_key_headers(vec![header::ACCEPT_LANGUAGE]));
+        let response = || response_with_templates(templates.clone());
+        let mut data = HashMap::new();
+        data.insert("name", "user");
+        let path = "examples/assets/template.tpl";
+
+        let german = request(HyperRequest::get("/").header(header::ACCEPT_LANGUAGE, "de"));
+        let res = halted(response().render_cached(&german, path, &data, "v1").await);
+        let german_etag = res.headers()[header::ETAG].to_str().unwrap().to_string();
+        assert_eq!(res.headers()[header::VARY], "accept-language");
+        // stable across builds and Rust releases
+        assert_eq!(german_etag, "\"v1-bff587b869ebc4d7\"");
+
+        let english = request(HyperRequest::get("/").header(header::ACCEPT_LANGUAGE, "en"));
+        let res = halted(response().render_cached(&english, path, &data, "v1").await);
+        assert_ne!(res.headers()[header::ETAG], german_etag.as_str());
+
+        // revalidating the german variant
+        let cached = request(HyperRequest::get("/").header(header::ACCEPT_LANGUAGE, "de")
+                                                  .header(header::IF_NONE_MATCH, german_etag.as_str()));
+        let res = halted(response().render_cached(&cached, path, &data, "v1").await);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        // the german variant is stale for english clients
+        let switched = request(HyperRequest::get("/").header(header::ACCEPT_LANGUAGE, "en")
+                                                    .header(header::IF_NONE_MATCH, german_etag.as_str()));
+        let res = halted(response().render_cached(&switched, path, &data, "v1").await);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(body_string(res).await.contains("Hello user!"));
+    }
+
+    #[cfg(feature = "templates")]
+    struct Page;
+
+    #[cfg(feature = "templates")]
+    #[async_trait::async_trait]
+    impl crate::Middleware<()> for Page {
+        async fn invoke(&self, _: &mut Request<()>, res: Response<()>) -> crate::MiddlewareResult<()> {
+            let mut data = HashMap::new();
+            data.insert("name", "user");
+            res.render("examples/assets/template.tpl", &data).await
+        }
+    }
+
+    #[cfg(feature = "templates")]
+    #[tokio::test]
+    async fn head_requests_get_headers_of_render_without_body() {
+        use crate::{HeadRender, HttpRouter, Middleware, Router};
+
+        let mut router = Router::new();
+        router.get("/", Page);
+        let invoke = |method: &'static str, head_render: HeadRender| {
+            let mut req = request(HyperRequest::builder().method(method).uri("/"));
+            let templates = TemplateCache::with_policy(ReloadPolicy::Never).with_head_render(head_render);
+            let mut res = response_with_templates(templates);
+            // as done by the server
+            if method == "HEAD" {
+                res.set_head_request();
+            }
+            let router = &router;
+            async move { halted(router.invoke(&mut req, res).await) }
+        };
+
+        let res = invoke("GET", HeadRender::Skip).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let rendered = body_string(res).await;
+        assert!(rendered.contains("Hello user!"));
+
+        let res = invoke("HEAD", HeadRender::Skip).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert!(res.headers().get(header::CONTENT_LENGTH).is_none());
+        assert_eq!(body_string(res).await, "");
+
+        let res = invoke("HEAD", HeadRender::Measure).await;
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], rendered.len().to_string().as_str());
+        assert_eq!(body_string(res).await, "");
+    }
+}
+
 mod modifier_impls {
     use hyper::StatusCode;
     use hyper::header;
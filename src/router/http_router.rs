@@ -115,6 +115,10 @@ pub trait HttpRouter<D: Send + 'static + Sync> {
     ///             ["This matches /user/list/4711",
     ///              "AND /user/extended/list/4711"];
     ///         }
+    ///         // only registered if the condition holds
+    ///         if (cfg!(debug_assertions)) get "/debug" => {
+    ///             "Only in debug builds"
+    ///         }
     ///     };
     ///
     ///     let mut server = Nickel::new();
@@ -180,4 +184,36 @@ pub trait HttpRouter<D: Send + 'static + Sync> {
     fn patch<M: Into<Matcher>, H: Middleware<D>>(&mut self, matcher: M, handler: H) -> &mut Self {
         self.add_route(Method::PATCH, matcher, handler)
     }
+
+    /// Registers a handler for every standard method, e.g. for proxies or
+    /// debugging endpoints. `HEAD` requests are answered by the `GET` route,
+    /// like for routes registered through `get(...)`. On a `Router`, settings
+    /// like `https_only()` chained to it apply to the routes of all methods.
+    ///
+    /// Take a look at `get(...)` for a more detailed description.
+    ///
+    /// # Examples
+    ///
+    /// ```{rust}
+    /// #[macro_use] extern crate nickel;
+    /// use nickel::{Nickel, HttpRouter};
+    ///
+    /// fn main() {
+    ///     let mut server = Nickel::new();
+    ///     server.any("/echo", middleware! { |request|
+    ///         format!("Method is: {}", request.origin.method())
+    ///     });
+    /// }
+    /// ```
+    fn any<M: Into<Matcher>, H: Middleware<D> + Clone>(&mut self, matcher: M, handler: H) -> &mut Self {
+        let matcher = matcher.into();
+        for method in ANY_METHODS {
+            self.add_route(method.clone(), matcher.clone(), handler.clone());
+        }
+        self
+    }
 }
+
+// The methods routed by `any(...)`
+pub(crate) const ANY_METHODS: &[Method] = &[Method::GET, Method::POST, Method::PUT, Method::DELETE,
+                                             Method::PATCH, Method::OPTIONS, Method::CONNECT, Method::TRACE];
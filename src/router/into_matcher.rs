@@ -25,35 +25,98 @@ static VAR_SEQ:               &'static str = "[,a-zA-Z0-9_-]*";
 static VAR_SEQ_WITH_SLASH:    &'static str = "[,/a-zA-Z0-9_-]*";
 // matches request params (e.g. ?foo=true&bar=false)
 static REGEX_PARAM_SEQ:       &'static str = "(\\?[a-zA-Z0-9%_=&-]*)?";
+// prefixes of the capture names for wildcards (* and **)
+static WILDCARD_CAPTURE:        &str = "nickel_wildcard_";
+static DOUBLE_WILDCARD_CAPTURE: &str = "nickel_double_wildcard_";
+
+// The wildcard a capture name of `wildcard_regex` stands for, if any.
+pub(crate) fn wildcard_name(capture: &str) -> Option<&'static str> {
+    if capture.starts_with(DOUBLE_WILDCARD_CAPTURE) {
+        Some("**")
+    } else if capture.starts_with(WILDCARD_CAPTURE) {
+        Some("*")
+    } else {
+        None
+    }
+}
+
+// Replaces the wildcards of `path` by the appropriate regex, capturing them
+// under numbered names if `capture` is set.
+fn replace_wildcards(path: &str, capture: bool) -> String {
+    let mut replaced = String::with_capacity(path.len());
+    let mut rest = path;
+    let mut count = 0;
+    while let Some(i) = rest.find('*') {
+        replaced.push_str(&rest[..i]);
+        let double = rest[i..].starts_with("**");
+        let (seq, prefix) = if double {
+            (VAR_SEQ_WITH_SLASH, DOUBLE_WILDCARD_CAPTURE)
+        } else {
+            (VAR_SEQ, WILDCARD_CAPTURE)
+        };
+        if capture {
+            replaced.push_str(&format!("(?P<{}{}>{})", prefix, count, seq));
+            count += 1;
+        } else {
+            replaced.push_str(seq);
+        }
+        rest = &rest[i + if double { 2 } else { 1 }..];
+    }
+    replaced.push_str(rest);
+    replaced
+}
+
+// Escapes the dots of `path`, which separate variables like in
+// `/images/:name.:ext` and would otherwise match any character. Dots that
+// are escaped already, as in the format suffix, are kept as they are.
+fn escape_dots(path: &str) -> String {
+    let mut escaped = String::with_capacity(path.len());
+    let mut previous = None;
+    for c in path.chars() {
+        if c == '.' && previous != Some('\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+        previous = Some(c);
+    }
+    escaped
+}
+
+fn route_regex(path: &str, capture_wildcards: bool) -> Regex {
+    let wildcarded = replace_wildcards(&escape_dots(path), capture_wildcards);
+
+    // Add a named capture for each :(variable) symbol
+    let named_captures = REGEX_VAR_SEQ.replace_all(&wildcarded, |captures: &Captures<'_>| {
+        // There should only ever be one match (after subgroup 0)
+        let c = captures.iter().skip(1).next().unwrap();
+        format!("(?P<{}>[,a-zA-Z0-9%_-]*)", c.unwrap().as_str())
+    });
+
+    let line_regex = format!("^{}{}$", named_captures, REGEX_PARAM_SEQ);
+    Regex::new(&line_regex).unwrap()
+}
 
 impl From<String> for Matcher {
     fn from(s: String) -> Matcher {
+        let template = s.clone();
         let with_format = if s.contains(FORMAT_VAR) {
             s
         } else {
             format!("{}(\\.{})?", s, FORMAT_VAR)
         };
 
-        // First mark all double wildcards for replacement. We can't directly
-        // replace them since the replacement does contain the * symbol as well,
-        // which would get overwritten with the next replace call
-        let with_placeholder = with_format.replace("**", "___DOUBLE_WILDCARD___");
-
-        // Then replace the regular wildcard symbols (*) with the appropriate regex
-        let star_replaced = with_placeholder.replace("*", VAR_SEQ);
-
-        // Now replace the previously marked double wild cards (**)
-        let wildcarded = star_replaced.replace("___DOUBLE_WILDCARD___", VAR_SEQ_WITH_SLASH);
-
-        // Add a named capture for each :(variable) symbol
-        let named_captures = REGEX_VAR_SEQ.replace_all(&wildcarded, |captures: &Captures<'_>| {
-            // There should only ever be one match (after subgroup 0)
-            let c = captures.iter().skip(1).next().unwrap();
-            format!("(?P<{}>[,a-zA-Z0-9%_-]*)", c.unwrap().as_str())
-        });
-
-        let line_regex = format!("^{}{}$", named_captures, REGEX_PARAM_SEQ);
-        let regex = Regex::new(&line_regex).unwrap();
-        Matcher::new(with_format, regex)
+        let regex = route_regex(&with_format, false);
+        // Capturing the wildcards in the main regex would shift the
+        // positions of the other captures
+        let wildcard_regex = if with_format.contains('*') {
+            Some(route_regex(&with_format, true))
+        } else {
+            None
+        };
+        let matcher = Matcher::new(with_format, regex).with_template(template);
+        match wildcard_regex {
+            Some(regex) => matcher.with_wildcard_regex(regex),
+            None => matcher
+        }
     }
 }
@@ -1,23 +1,64 @@
 use std::borrow::Cow;
 use std::ops::Deref;
 use regex::Regex;
+use super::into_matcher::wildcard_name;
 
+#[derive(Clone)]
 pub struct Matcher {
     path: Cow<'static, str>,
-    regex: Regex
+    template: Option<String>,
+    regex: Regex,
+    // Like `regex`, but also capturing wildcards
+    wildcard_regex: Option<Regex>
 }
 
 impl Matcher {
     pub fn new<P: Into<Cow<'static, str>>>(path: P, regex: Regex) -> Matcher {
         Matcher {
             path: path.into(),
-            regex: regex
+            template: None,
+            regex: regex,
+            wildcard_regex: None
         }
     }
 
     pub fn path(&self) -> &str {
         &self.path
     }
+
+    /// The path template the matcher was created from, e.g. `/users/:id`.
+    /// Falls back to `path` for matchers built from a regex.
+    pub fn template(&self) -> &str {
+        self.template.as_deref().unwrap_or(&self.path)
+    }
+
+    pub(crate) fn with_template(mut self, template: String) -> Matcher {
+        self.template = Some(template);
+        self
+    }
+
+    pub(crate) fn with_wildcard_regex(mut self, regex: Regex) -> Matcher {
+        self.wildcard_regex = Some(regex);
+        self
+    }
+
+    // The named captures and wildcards matched in `path`, in the order
+    // they appear in the path. Wildcards are named `*` and `**`.
+    pub(crate) fn ordered_captures(&self, path: &str) -> Vec<(String, String)> {
+        let regex = self.wildcard_regex.as_ref().unwrap_or(&self.regex);
+        let captures = match regex.captures(path) {
+            Some(captures) => captures,
+            None => return vec![]
+        };
+        regex.capture_names()
+             .flatten()
+             .filter_map(|name| {
+                 let capture = captures.name(name)?;
+                 let name = wildcard_name(name).unwrap_or(name);
+                 Some((name.to_string(), capture.as_str().to_string()))
+             })
+             .collect()
+    }
 }
 
 impl Deref for Matcher {
@@ -5,7 +5,11 @@ use crate::request::Request;
 use crate::response::Response;
 use crate::router::HttpRouter;
 use hyper::{Method, StatusCode};
+use hyper::header::{self, HeaderValue};
+use mime::Mime;
+use crate::mimes::MediaType;
 use crate::router::{Matcher, FORMAT_PARAM};
+use crate::router::http_router::ANY_METHODS;
 
 /// A Route is the basic data structure that stores both the path
 /// and the handler that gets executed for the route.
@@ -13,6 +17,17 @@ use crate::router::{Matcher, FORMAT_PARAM};
 pub struct Route<D=()> {
     pub method: Method,
     pub handler: Box<dyn Middleware<D> + Send + Sync + 'static>,
+    /// Media types accepted as request body. Empty means anything goes.
+    pub consumes: Vec<Mime>,
+    /// Media types the handler can respond with. Empty means anything goes.
+    pub produces: Vec<Mime>,
+    /// Overrides `Options::max_body_size` for this route.
+    pub max_body: Option<u64>,
+    /// Only match requests made over HTTPS, see `Request::is_secure`.
+    pub https_only: bool,
+    /// Routes with a higher priority are matched first, see
+    /// `Router::priority`.
+    pub priority: i32,
     matcher: Matcher
 }
 
@@ -22,10 +37,38 @@ pub struct Route<D=()> {
 /// evaluated string
 pub struct RouteResult {
     // pub route: &'r Route<D>,
-    params: Vec<(String, String)>
+    params: Vec<(String, String)>,
+    ordered_params: Vec<(String, String)>,
+    template: Option<String>,
+    method: Option<Method>
 }
 
 impl RouteResult {
+    pub(crate) fn from_params(params: Vec<(String, String)>) -> RouteResult {
+        RouteResult { ordered_params: params.clone(), params, template: None, method: None }
+    }
+
+    /// The method the matched route was added for. For a `HEAD` request
+    /// answered by a `GET` route this is `GET`.
+    pub fn method(&self) -> Option<&Method> {
+        self.method.as_ref()
+    }
+
+    /// The path template of the matched route, e.g. `/users/:id`. Unlike
+    /// the request path, it is shared by all requests for the route, which
+    /// suits logs and metrics.
+    pub fn template(&self) -> Option<&str> {
+        self.template.as_deref()
+    }
+
+    /// The named parameters and the wildcard captures of the matched route,
+    /// in the order they appear in its path. Wildcards are named `*` and
+    /// `**`, e.g. `/files/:owner/**` matching `/files/alice/docs/cv.pdf`
+    /// yields `[("owner", "alice"), ("**", "docs/cv.pdf")]`.
+    pub fn ordered_params(&self) -> Vec<(&str, &str)> {
+        self.ordered_params.iter().map(|(k, v)| (&k[..], &v[..])).collect()
+    }
+
     pub fn param(&self, key: &str) -> Option<&str> {
         for &(ref k, ref v) in &self.params {
             if k == &key {
@@ -47,20 +90,341 @@ impl RouteResult {
 /// added to the middleware stack with `server.utilize(router)`.
 pub struct Router<D=()> {
     routes: Vec<Route<D>>,
+    // Whether `add_route_if` skipped the last route, so settings for it
+    // are ignored
+    skipped_last: bool,
+    // How many routes the last call added, as `any` adds one per method
+    last_added: usize,
+    automatic_options: bool,
+    strict_methods: bool,
 }
 
 impl<D> Router<D> {
     pub fn new() -> Router<D> {
         Router {
-            routes: Vec::new()
+            routes: Vec::new(),
+            skipped_last: false,
+            last_added: 0,
+            automatic_options: false,
+            strict_methods: false
+        }
+    }
+
+    /// Answers `OPTIONS` requests for paths without an `OPTIONS` route of
+    /// their own with `204 No Content` and an `Allow` header listing the
+    /// methods routed for the path, see `allowed_methods`. Routes added for
+    /// `OPTIONS` still take priority.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Router, HttpRouter};
+    ///
+    /// let mut router = Router::<()>::new();
+    /// router.get("/users", middleware!("all users"));
+    /// router.post("/users", middleware!("created"));
+    /// // OPTIONS /users: 204 with `Allow: GET, HEAD, POST, OPTIONS`
+    /// router.enable_automatic_options();
+    /// ```
+    pub fn enable_automatic_options(&mut self) -> &mut Self {
+        self.automatic_options = true;
+        self
+    }
+
+    /// With `strict` set, requests for a path that is only routed for other
+    /// methods are answered with `405 Method Not Allowed` and an `Allow`
+    /// header listing those methods, instead of passing them on to the next
+    /// middleware, which usually ends in a `404 Not Found`.
+    ///
+    /// As the request doesn't reach later middleware anymore, all handlers
+    /// for the paths of this router should be registered on it.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Router, HttpRouter};
+    ///
+    /// let mut router = Router::<()>::new();
+    /// router.get("/users", middleware!("all users"));
+    /// // DELETE /users: 405 with `Allow: GET, HEAD`
+    /// router.strict_method_matching(true);
+    /// ```
+    pub fn strict_method_matching(&mut self, strict: bool) -> &mut Self {
+        self.strict_methods = strict;
+        self
+    }
+
+    /// The methods routed for `path`, in the order their routes were added,
+    /// treating the request as plain HTTP like `match_route`. `HEAD` is
+    /// included along with `GET`, as it is answered by the `GET` route, and
+    /// `OPTIONS` if automatic `OPTIONS` responses are enabled. Empty if no
+    /// route matches the path.
+    pub fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        self.methods_for(path, false)
+    }
+
+    fn methods_for(&self, path: &str, secure: bool) -> Vec<Method> {
+        fn add(methods: &mut Vec<Method>, method: &Method) {
+            if !methods.contains(method) {
+                methods.push(method.clone());
+            }
+        }
+
+        let mut methods = Vec::new();
+        for route in self.routes.iter().filter(|r| (secure || !r.https_only) && r.matcher.is_match(path)) {
+            add(&mut methods, &route.method);
+            if route.method == Method::GET {
+                add(&mut methods, &Method::HEAD);
+            }
+        }
+        if self.automatic_options && !methods.is_empty() {
+            add(&mut methods, &Method::OPTIONS);
         }
+        methods
     }
 
+    /// Finds the first route for `method` and `path`, treating the request
+    /// as plain HTTP, so `https_only` routes are skipped. `HEAD` requests
+    /// without a `HEAD` route of their own are answered by the `GET` route.
     pub fn match_route(&self, method: &Method, path: &str) -> Option<(RouteResult, &Route<D>)> {
-        self.routes
-            .iter()
-            .find(|item| item.method == *method && item.matcher.is_match(path))
-            .map(|route| (RouteResult{params: extract_params(route, path)}, route))
+        self.find_route(method, path, false)
+    }
+
+    /// Finds the first route matching `req`, ignoring its query string.
+    pub fn match_request(&self, req: &Request<D>) -> Option<(RouteResult, &Route<D>)> {
+        self.find_route(req.origin.method(), req.path_without_query(), req.is_secure())
+    }
+
+    fn find_route(&self, method: &Method, path: &str, secure: bool) -> Option<(RouteResult, &Route<D>)> {
+        // The first of the matching routes with the highest priority
+        let find = |method: &Method| {
+            self.routes
+                .iter()
+                .filter(|item| item.method == *method && (secure || !item.https_only) && item.matcher.is_match(path))
+                .fold(None, |best: Option<&Route<D>>, item| match best {
+                    Some(best) if best.priority >= item.priority => Some(best),
+                    _ => Some(item)
+                })
+        };
+
+        let route = match find(method) {
+            None if *method == Method::HEAD => find(&Method::GET),
+            route => route
+        };
+        route.map(|route| {
+            let result = RouteResult {
+                params: extract_params(route, path),
+                ordered_params: route.matcher.ordered_captures(path),
+                template: Some(route.template().to_string()),
+                method: Some(route.method.clone())
+            };
+            (result, route)
+        })
+    }
+
+    /// The routes registered so far, in the order they were added.
+    pub fn routes(&self) -> &[Route<D>] {
+        &self.routes
+    }
+
+    /// Removes the routes registered for `method` with the path template
+    /// `template`, as passed when adding them. Returns whether any route was
+    /// removed.
+    ///
+    /// Once the router is part of a server, routes can only be changed by
+    /// swapping in a new stack, see `StackHandle::swap`.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Router, HttpRouter};
+    /// use nickel::hyper::Method;
+    ///
+    /// let mut router = Router::<()>::new();
+    /// router.get("/plugins/search", middleware!("search"));
+    /// assert!(router.remove_route(Method::GET, "/plugins/search"));
+    /// assert!(router.routes().is_empty());
+    /// ```
+    pub fn remove_route(&mut self, method: Method, template: &str) -> bool {
+        let before = self.routes.len();
+        self.routes.retain(|route| !(route.method == method && route.template() == template));
+        self.routes.len() != before
+    }
+
+    /// Restricts the most recently added route to requests whose
+    /// `Content-Type` is `media_type`. Can be called multiple times to
+    /// allow several types; other requests are answered with
+    /// `415 Unsupported Media Type`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no route has been added yet.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Router, HttpRouter, MediaType};
+    ///
+    /// let mut router = Router::<()>::new();
+    /// router.post("/users", middleware!("created"))
+    ///       .consumes(MediaType::Json)
+    ///       .produces(MediaType::Json);
+    /// ```
+    pub fn consumes(&mut self, media_type: MediaType) -> &mut Self {
+        let mime: Mime = media_type.into();
+        for route in self.last_routes("consumes") {
+            route.consumes.push(mime.clone());
+        }
+        self
+    }
+
+    /// Restricts the most recently added route to requests whose `Accept`
+    /// header allows `media_type`. Can be called multiple times; requests
+    /// accepting none of the types are answered with `406 Not Acceptable`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no route has been added yet.
+    pub fn produces(&mut self, media_type: MediaType) -> &mut Self {
+        let mime: Mime = media_type.into();
+        for route in self.last_routes("produces") {
+            route.produces.push(mime.clone());
+        }
+        self
+    }
+
+    /// Overrides the server wide `Options::max_body_size` for the most
+    /// recently added route, e.g. to allow large uploads on one route only.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no route has been added yet.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Router, HttpRouter};
+    ///
+    /// let mut router = Router::<()>::new();
+    /// router.post("/upload", middleware!("uploaded"))
+    ///       .max_body(50_000_000);
+    /// ```
+    pub fn max_body(&mut self, bytes: u64) -> &mut Self {
+        for route in self.last_routes("max_body") {
+            route.max_body = Some(bytes);
+        }
+        self
+    }
+
+    /// Makes the most recently added route match only requests made over
+    /// HTTPS. Plain HTTP requests skip it and fall through to later routes
+    /// and middleware, e.g. one redirecting to the HTTPS site.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no route has been added yet.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Router, HttpRouter};
+    ///
+    /// let mut router = Router::<()>::new();
+    /// router.get("/account", middleware!("your account")).https_only();
+    /// router.get("/account", middleware!("please use https"));
+    /// ```
+    pub fn https_only(&mut self) -> &mut Self {
+        for route in self.last_routes("https_only") {
+            route.https_only = true;
+        }
+        self
+    }
+
+    /// Sets the priority of the most recently added route. When several
+    /// routes match a request, the one with the highest priority wins, and
+    /// routes of equal priority are tried in the order they were added.
+    /// Routes have a priority of 0 by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no route has been added yet.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Router, HttpRouter};
+    ///
+    /// let mut router = Router::<()>::new();
+    /// router.get("/users/:id", middleware!("a user"));
+    /// // answers /users/me despite being added later
+    /// router.get("/users/me", middleware!("myself")).priority(10);
+    /// ```
+    pub fn priority(&mut self, priority: i32) -> &mut Self {
+        for route in self.last_routes("priority") {
+            route.priority = priority;
+        }
+        self
+    }
+
+    fn last_routes(&mut self, setting: &str) -> &mut [Route<D>] {
+        if self.skipped_last {
+            return &mut [];
+        }
+        if self.routes.is_empty() {
+            panic!("`{}` must be called after adding a route", setting)
+        }
+        let start = self.routes.len() - self.last_added;
+        &mut self.routes[start..]
+    }
+}
+
+impl<D: Send + 'static + Sync> Router<D> {
+    /// Adds the route like `add_route` if `condition` holds, e.g. for
+    /// endpoints behind a feature flag. Otherwise the route is skipped, along
+    /// with any settings like `priority` chained to it.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Router, HttpRouter};
+    /// use nickel::hyper::Method;
+    ///
+    /// let beta_enabled = std::env::var("BETA").is_ok();
+    /// let mut router = Router::<()>::new();
+    /// router.add_route_if(beta_enabled, Method::GET, "/beta", middleware!("beta"))
+    ///       .priority(1);
+    /// ```
+    pub fn add_route_if<M, H>(&mut self, condition: bool, method: Method, matcher: M, handler: H) -> &mut Self
+    where M: Into<Matcher>, H: Middleware<D> {
+        if condition {
+            self.add_route(method, matcher, handler)
+        } else {
+            self.skipped_last = true;
+            self
+        }
+    }
+}
+
+impl<D> Route<D> {
+    /// The path template the route was registered with, e.g. `/users/:id`.
+    pub fn template(&self) -> &str {
+        self.matcher.template()
+    }
+
+    // The status to reject `req` with if it doesn't satisfy the media types
+    // declared for this route.
+    fn check_media_types(&self, req: &Request<D>) -> Option<(StatusCode, &'static str)> {
+        if !self.consumes.is_empty() {
+            let content_type = req.origin.headers()
+                                         .get(header::CONTENT_TYPE)
+                                         .and_then(|v| v.to_str().ok())
+                                         .and_then(|v| v.parse::<Mime>().ok());
+            let supported = match content_type {
+                Some(ct) => self.consumes.iter().any(|m| m.essence_str() == ct.essence_str()),
+                None => false
+            };
+            if !supported {
+                return Some((StatusCode::UNSUPPORTED_MEDIA_TYPE, "Unsupported request content type"))
+            }
+        }
+
+        if !self.produces.is_empty() && !self.produces.iter().any(|m| req.accepts_mime(m)) {
+            return Some((StatusCode::NOT_ACCEPTABLE, "None of the acceptable content types can be produced"))
+        }
+
+        None
     }
 }
 
@@ -92,9 +456,25 @@ impl<D: Send + 'static + Sync> HttpRouter<D> for Router<D> {
             matcher: matcher.into(),
             method: method,
             handler: Box::new(handler),
+            consumes: Vec::new(),
+            produces: Vec::new(),
+            max_body: None,
+            https_only: false,
+            priority: 0,
         };
 
         self.routes.push(route);
+        self.skipped_last = false;
+        self.last_added = 1;
+        self
+    }
+
+    fn any<M: Into<Matcher>, H: Middleware<D> + Clone>(&mut self, matcher: M, handler: H) -> &mut Self {
+        let matcher = matcher.into();
+        for method in ANY_METHODS {
+            self.add_route(method.clone(), matcher.clone(), handler.clone());
+        }
+        self.last_added = ANY_METHODS.len();
         self
     }
 }
@@ -105,18 +485,46 @@ impl<D: Send + Sync + 'static> Middleware<D> for Router<D> {
                           -> MiddlewareResult<D> {
         debug!("Router::invoke for '{:?}'", req.origin.uri());
 
-        // Strip off the querystring when matching a route
-        let route_result = self.match_route(&req.origin.method(), req.path_without_query());
+        let route_result = self.match_request(req);
 
         debug!("route_result.route.path: {:?}", route_result.as_ref().map(|(_, r)| r.matcher.path()));
 
         match route_result {
             Some((route_result, route)) => {
+                if let Some((status, message)) = route.check_media_types(req) {
+                    return res.error(status, message);
+                }
+
+                if route.max_body.is_some() {
+                    req.set_body_limit(route.max_body);
+                }
+
                 res.set(StatusCode::OK);
                 req.route_result = Some(route_result);
                 route.handler.invoke(req, res).await
             },
-            None => res.next_middleware()
+            None => {
+                let methods = self.methods_for(req.path_without_query(), req.is_secure());
+                if methods.is_empty() {
+                    return res.next_middleware();
+                }
+                let automatic_options = self.automatic_options && req.origin.method() == Method::OPTIONS;
+                let allow = methods.iter().map(|m| m.as_str()).collect::<Vec<_>>().join(", ");
+                if !automatic_options {
+                    // Let error handlers tell a wrong method from a wrong path
+                    req.add_allowed_methods(methods);
+                    if !self.strict_methods {
+                        return res.next_middleware();
+                    }
+                }
+                res.set_header(header::ALLOW, HeaderValue::from_str(&allow).unwrap());
+                if automatic_options {
+                    res.abort(StatusCode::NO_CONTENT)
+                } else {
+                    res.error(StatusCode::METHOD_NOT_ALLOWED,
+                              format!("{} is not allowed, use one of {}", req.origin.method(), allow))
+                }
+            }
         }
     }
 }
@@ -345,3 +753,353 @@ fn ignores_querystring() {
     let route_result = route_result.unwrap();
     assert_eq!(route_result.param("a"), Some("bar"));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Router;
+    use crate::{Continue, HttpRouter, MediaType, Middleware, MiddlewareResult, Request, Response};
+    use crate::test_helpers;
+    use async_trait::async_trait;
+    use hyper::{Method, Request as HyperRequest, StatusCode};
+
+    fn json_router() -> Router<()> {
+        let mut router = Router::new();
+        router.post("/users", middleware!("created"))
+              .consumes(MediaType::Json)
+              .produces(MediaType::Json);
+        router
+    }
+
+    #[test]
+    fn orders_params_and_wildcards() {
+        let mut router = Router::<()>::new();
+        router.get("/repos/:owner/*/tree/**/:file", middleware!("file"));
+        router.get("/users/:id", middleware!("user"));
+
+        let result = router.match_route(&Method::GET, "/repos/alice/nickel/tree/src/router/mod.rs").unwrap().0;
+        assert_eq!(result.ordered_params(), vec![("owner", "alice"), ("*", "nickel"), ("**", "src/router"),
+                                                 ("file", "mod"), ("format", "rs")]);
+        assert_eq!(result.param("owner"), Some("alice"));
+        assert_eq!(result.param("file"), Some("mod"));
+
+        let result = router.match_route(&Method::GET, "/users/42").unwrap().0;
+        assert_eq!(result.ordered_params(), vec![("id", "42")]);
+    }
+
+    #[tokio::test]
+    async fn wrong_content_type_is_unsupported() {
+        let router = json_router();
+        let mut req = test_helpers::request(HyperRequest::post("/users").header("content-type", "text/plain"));
+        let res = test_helpers::errored(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let mut req = test_helpers::request(HyperRequest::post("/users"));
+        let res = test_helpers::errored(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn incompatible_accept_is_not_acceptable() {
+        let router = json_router();
+        let mut req = test_helpers::request(HyperRequest::post("/users")
+                                                .header("content-type", "application/json")
+                                                .header("accept", "text/html"));
+        let res = test_helpers::errored(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn matching_media_types_pass() {
+        let router = json_router();
+        let mut req = test_helpers::request(HyperRequest::post("/users")
+                                                .header("content-type", "application/json; charset=utf-8")
+                                                .header("accept", "text/html, application/*;q=0.5"));
+        let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test_helpers::body_string(res).await, "created");
+    }
+
+    #[test]
+    #[should_panic(expected = "`consumes` must be called after adding a route")]
+    fn consumes_without_route_panics() {
+        Router::<()>::new().consumes(MediaType::Json);
+    }
+
+    struct BodyLength;
+
+    #[async_trait]
+    impl Middleware<()> for BodyLength {
+        async fn invoke(&self, req: &mut Request<()>, res: Response<()>) -> MiddlewareResult<()> {
+            let length = try_with!(res, req.raw_body().await).len();
+            res.send(length.to_string())
+        }
+    }
+
+    fn upload_request(path: &str) -> Request<()> {
+        let mut req = test_helpers::request_with_body(HyperRequest::post(path), vec![b'x'; 32]);
+        // as set by the server from `Options::max_body_size`
+        req.set_body_limit(Some(16));
+        req
+    }
+
+    #[tokio::test]
+    async fn https_only_routes_skip_plain_requests() {
+        let mut router = Router::new();
+        router.get("/account", middleware!("account")).https_only();
+
+        let mut req = test_helpers::from_proxy(HyperRequest::get("/account").header("x-forwarded-proto", "https"));
+        let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "account");
+
+        let mut req = test_helpers::get("/account");
+        match router.invoke(&mut req, test_helpers::response()).await {
+            Ok(Continue(_)) => {},
+            _ => panic!("expected the plain request to fall through")
+        }
+
+        router.get("/account", middleware!("use https"));
+        let mut req = test_helpers::get("/account");
+        let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "use https");
+    }
+
+    #[tokio::test]
+    async fn settings_apply_to_every_method_of_any() {
+        let mut router = Router::new();
+        router.any("/admin", middleware!("admin")).https_only();
+
+        for method in &["GET", "POST", "TRACE"] {
+            let mut req = test_helpers::request(HyperRequest::builder().method(*method).uri("/admin"));
+            match router.invoke(&mut req, test_helpers::response()).await {
+                Ok(Continue(_)) => {},
+                _ => panic!("expected the plain {} request to fall through", method)
+            }
+
+            let mut req = test_helpers::from_proxy(HyperRequest::builder().method(*method)
+                                                                          .uri("/admin")
+                                                                          .header("x-forwarded-proto", "https"));
+            let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+            assert_eq!(test_helpers::body_string(res).await, "admin");
+        }
+    }
+
+    #[tokio::test]
+    async fn head_falls_back_to_get_routes() {
+        let mut router = Router::<()>::new();
+        router.get("/page", middleware!("page"));
+        router.get("/status", middleware!("status"));
+        router.head("/status", middleware!(""));
+
+        let (_, route) = router.match_route(&Method::HEAD, "/page").unwrap();
+        assert_eq!(route.method, Method::GET);
+        let (_, route) = router.match_route(&Method::HEAD, "/status").unwrap();
+        assert_eq!(route.method, Method::HEAD);
+        assert!(router.match_route(&Method::POST, "/page").is_none());
+    }
+
+    #[tokio::test]
+    async fn higher_priority_routes_win() {
+        let mut router = Router::new();
+        router.get("/files/report.pdf", middleware!("exact"));
+        router.get("/files/*", middleware!("wildcard")).priority(5);
+        router.get("/files/*", middleware!("later wildcard")).priority(5);
+        router.get("/files/*", middleware!("low")).priority(-1);
+
+        let mut req = test_helpers::get("/files/report.pdf");
+        let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "wildcard");
+    }
+
+    async fn served_body(router: &Router<()>, path: &str) -> Option<String> {
+        let mut req = test_helpers::get(path);
+        match router.invoke(&mut req, test_helpers::response()).await {
+            Ok(Continue(_)) => None,
+            result => Some(test_helpers::body_string(test_helpers::halted(result)).await)
+        }
+    }
+
+    #[tokio::test]
+    async fn conditional_routes() {
+        let mut router = Router::new();
+        router.add_route_if(true, Method::GET, "/enabled", middleware!("enabled"));
+        router.add_route_if(false, Method::GET, "/disabled", middleware!("disabled"))
+              .https_only();
+        assert_eq!(served_body(&router, "/enabled").await.as_deref(), Some("enabled"));
+        assert_eq!(served_body(&router, "/disabled").await, None);
+
+        let router = router! {
+            if (false) get "/beta" => { "beta" }
+            if (true) get "/stable" => |_, res| { "stable" }
+        };
+        assert_eq!(served_body(&router, "/stable").await.as_deref(), Some("stable"));
+        assert_eq!(served_body(&router, "/beta").await, None);
+    }
+
+    #[tokio::test]
+    async fn answers_options_automatically() {
+        use hyper::header;
+
+        let mut router = Router::new();
+        router.get("/users/:id", middleware!("a user"));
+        router.delete("/users/:id", middleware!("deleted"));
+        router.options("/reports", middleware!("custom options"));
+        router.get("/reports", middleware!("reports"));
+        router.enable_automatic_options();
+
+        let mut req = test_helpers::request(HyperRequest::options("/users/42"));
+        let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::NO_CONTENT);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, DELETE, OPTIONS");
+
+        // explicit routes win
+        let mut req = test_helpers::request(HyperRequest::options("/reports"));
+        let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "custom options");
+
+        let mut req = test_helpers::request(HyperRequest::options("/missing"));
+        match router.invoke(&mut req, test_helpers::response()).await {
+            Ok(Continue(_)) => {},
+            _ => panic!("expected unknown paths to fall through")
+        }
+
+        assert_eq!(router.allowed_methods("/reports"), vec![Method::OPTIONS, Method::GET, Method::HEAD]);
+    }
+
+    #[tokio::test]
+    async fn strict_routers_reject_other_methods() {
+        use hyper::header;
+
+        let mut router = Router::new();
+        router.get("/users", middleware!("all users"));
+        router.post("/users", middleware!("created"));
+
+        // lenient by default
+        let mut req = test_helpers::request(HyperRequest::delete("/users"));
+        match router.invoke(&mut req, test_helpers::response()).await {
+            Ok(Continue(_)) => {},
+            _ => panic!("expected the request to fall through")
+        }
+
+        router.strict_method_matching(true);
+        let mut req = test_helpers::request(HyperRequest::delete("/users"));
+        let res = test_helpers::errored(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(res.headers()[header::ALLOW], "GET, HEAD, POST");
+
+        // unknown paths are still left to others
+        let mut req = test_helpers::request(HyperRequest::delete("/posts"));
+        match router.invoke(&mut req, test_helpers::response()).await {
+            Ok(Continue(_)) => {},
+            _ => panic!("expected unknown paths to fall through")
+        }
+    }
+
+    #[tokio::test]
+    async fn any_routes_answer_every_method() {
+        let mut router = Router::new();
+        router.any("/echo", middleware! { |req| format!("{} echoed", req.origin.method()) });
+
+        for method in &[Method::GET, Method::POST, Method::DELETE] {
+            let mut req = test_helpers::request(HyperRequest::builder().method(method.clone()).uri("/echo"));
+            let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+            assert_eq!(test_helpers::body_string(res).await, format!("{} echoed", method));
+        }
+        assert_eq!(served_body(&router, "/other").await, None);
+    }
+
+    #[tokio::test]
+    async fn method_mismatches_are_reported_to_error_handlers() {
+        use crate::{DefaultErrorHandler, ErrorHandler, NickelError};
+
+        let mut router = Router::new();
+        router.get("/users", middleware!("all users"));
+        router.post("/users/:id", middleware!("updated"));
+
+        let mut req = test_helpers::request(HyperRequest::head("/users"));
+        test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(req.matched_route_method(), Some(&Method::GET));
+        assert!(req.allowed_methods().is_empty());
+
+        let mut req = test_helpers::request(HyperRequest::post("/users"));
+        let res = match router.invoke(&mut req, test_helpers::response()).await {
+            Ok(Continue(res)) => res,
+            _ => panic!("expected the request to continue")
+        };
+        assert_eq!(req.matched_route_method(), None);
+        assert_eq!(req.allowed_methods(), &[Method::GET, Method::HEAD]);
+
+        let mut err = NickelError::new(res, "File Not Found", StatusCode::NOT_FOUND);
+        DefaultErrorHandler.handle_error(&mut err, &mut req);
+        assert_eq!(test_helpers::body_string(err.stream.unwrap()).await,
+                   "Not Found: POST /users isn't routed, use one of GET, HEAD");
+
+        let mut req = test_helpers::request(HyperRequest::post("/accounts"));
+        router.invoke(&mut req, test_helpers::response()).await.ok();
+        assert!(req.allowed_methods().is_empty());
+
+        // strict routers answer with a 405 themselves, recording the methods
+        // all the same
+        router.strict_method_matching(true);
+        let mut req = test_helpers::request(HyperRequest::delete("/users"));
+        let res = test_helpers::errored(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(req.allowed_methods(), &[Method::GET, Method::HEAD]);
+    }
+
+    #[test]
+    fn captures_variables_separated_by_dots() {
+        let mut router = Router::<()>::new();
+        router.get("/images/:name.:ext", middleware!("image"));
+
+        let result = router.match_route(&Method::GET, "/images/cat.png").unwrap().0;
+        assert_eq!(result.param("name"), Some("cat"));
+        assert_eq!(result.param("ext"), Some("png"));
+        assert_eq!(result.ordered_params(), vec![("name", "cat"), ("ext", "png")]);
+
+        assert!(router.match_route(&Method::GET, "/images/cat").is_none());
+        assert!(router.match_route(&Method::GET, "/images/cat-png").is_none());
+    }
+
+    #[tokio::test]
+    async fn router_macro_supports_patch() {
+        let router = router! {
+            patch "/users/:id" => |req| { format!("patched {}", req.param("id").unwrap()) }
+        };
+        let mut req = test_helpers::request(HyperRequest::patch("/users/42"));
+        let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "patched 42");
+
+        assert_eq!(served_body(&router, "/users/42").await, None);
+    }
+
+    #[tokio::test]
+    async fn removed_routes_no_longer_match() {
+        let mut router = Router::new();
+        router.get("/plugins/search", middleware!("search"));
+        router.get("/plugins/stats", middleware!("stats"));
+
+        assert!(router.remove_route(Method::GET, "/plugins/search"));
+        assert!(!router.remove_route(Method::GET, "/plugins/search"));
+        assert!(!router.remove_route(Method::POST, "/plugins/stats"));
+
+        let templates: Vec<_> = router.routes().iter().map(|r| r.template()).collect();
+        assert_eq!(templates, vec!["/plugins/stats"]);
+        assert_eq!(served_body(&router, "/plugins/search").await, None);
+        assert_eq!(served_body(&router, "/plugins/stats").await.as_deref(), Some("stats"));
+    }
+
+    #[tokio::test]
+    async fn route_max_body_overrides_global_limit() {
+        let mut router = Router::new();
+        router.post("/upload", BodyLength).max_body(64);
+        router.post("/api", BodyLength);
+
+        let mut req = upload_request("/upload");
+        let res = test_helpers::halted(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "32");
+
+        let mut req = upload_request("/api");
+        let res = test_helpers::errored(router.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
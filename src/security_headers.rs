@@ -0,0 +1,150 @@
+use std::time::Duration;
+use async_trait::async_trait;
+use hyper::header::{HeaderName, HeaderValue};
+
+use crate::status::StatusCode;
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::{Middleware, MiddlewareResult};
+
+const PERMISSIONS_POLICY: HeaderName = HeaderName::from_static("permissions-policy");
+const EXPECT_CT: HeaderName = HeaderName::from_static("expect-ct");
+
+/// Sets policy headers restricting what browsers allow pages to do. Headers
+/// set by handlers take precedence, so single routes can relax the policy.
+///
+/// By default a `Permissions-Policy` disables the camera, microphone and
+/// geolocation for the page and all frames it embeds.
+///
+/// # Examples
+/// ```{rust}
+/// use std::time::Duration;
+/// use nickel::{Nickel, SecurityHeaders};
+///
+/// let mut server = Nickel::new();
+/// // Permissions-Policy: camera=(), microphone=(), geolocation=(self), payment=()
+/// server.utilize(SecurityHeaders::new()
+///                    .permission("geolocation", "(self)")
+///                    .permission("payment", "()")
+///                    .expect_ct(Duration::from_secs(86400), false));
+/// ```
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    permissions: Vec<(String, String)>,
+    expect_ct: Option<(Duration, bool)>,
+}
+
+impl SecurityHeaders {
+    /// Create a new middleware with the default policy.
+    pub fn new() -> SecurityHeaders {
+        let disabled = |feature: &str| (feature.to_string(), "()".to_string());
+        SecurityHeaders {
+            permissions: vec![disabled("camera"), disabled("microphone"), disabled("geolocation")],
+            expect_ct: None,
+        }
+    }
+
+    /// Sets the allowlist of `feature` in the `Permissions-Policy`, e.g.
+    /// `()` to disable it, `(self)` for the page's own origin or
+    /// `(self "https://maps.example.com")` to include an embedded site.
+    pub fn permission(mut self, feature: &str, allowlist: &str) -> Self {
+        match self.permissions.iter_mut().find(|(f, _)| f == feature) {
+            Some(directive) => directive.1 = allowlist.to_string(),
+            None => self.permissions.push((feature.to_string(), allowlist.to_string()))
+        }
+        self
+    }
+
+    /// Leaves `feature` to the browser's default.
+    pub fn allow_default(mut self, feature: &str) -> Self {
+        self.permissions.retain(|(f, _)| f != feature);
+        self
+    }
+
+    /// Sends `Expect-CT`, asking browsers to check that the site's
+    /// certificates are logged for Certificate Transparency, and to refuse
+    /// the connection otherwise if `enforce` is set.
+    ///
+    /// Defaults to not sending it.
+    pub fn expect_ct(mut self, max_age: Duration, enforce: bool) -> Self {
+        self.expect_ct = Some((max_age, enforce));
+        self
+    }
+
+    fn permissions_policy(&self) -> String {
+        self.permissions.iter()
+                        .map(|(feature, allowlist)| format!("{}={}", feature, allowlist))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+    }
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> SecurityHeaders {
+        SecurityHeaders::new()
+    }
+}
+
+#[async_trait]
+impl<D: Send + 'static + Sync> Middleware<D> for SecurityHeaders {
+    async fn invoke(&self, _req: &mut Request<D>, mut res: Response<D>)
+            -> MiddlewareResult<D> {
+        if !self.permissions.is_empty() {
+            let value = match HeaderValue::from_str(&self.permissions_policy()) {
+                Ok(v) => v,
+                Err(e) => return res.error(StatusCode::INTERNAL_SERVER_ERROR,
+                                           format!("Invalid Permissions-Policy: {}", e))
+            };
+            res.set_header_fallback(&PERMISSIONS_POLICY, &value);
+        }
+
+        if let Some((max_age, enforce)) = self.expect_ct {
+            let value = format!("max-age={}{}", max_age.as_secs(), if enforce { ", enforce" } else { "" });
+            res.set_header_fallback(&EXPECT_CT, &HeaderValue::from_str(&value).unwrap());
+        }
+
+        res.next_middleware()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use hyper::header::{HeaderName, HeaderValue};
+    use super::SecurityHeaders;
+    use crate::{Action, Middleware, Response};
+    use crate::test_helpers;
+
+    async fn headers_of(security: SecurityHeaders, res: Response<()>) -> Response<()> {
+        let mut req = test_helpers::get("/");
+        match security.invoke(&mut req, res).await {
+            Ok(Action::Continue(res)) => res,
+            _ => panic!("expected the request to pass")
+        }
+    }
+
+    #[tokio::test]
+    async fn sets_permissions_policy() {
+        let res = headers_of(SecurityHeaders::new(), test_helpers::response()).await;
+        assert_eq!(res.headers()["permissions-policy"], "camera=(), microphone=(), geolocation=()");
+        assert!(res.headers().get("expect-ct").is_none());
+
+        let security = SecurityHeaders::new()
+                           .permission("geolocation", "(self \"https://maps.example.com\")")
+                           .permission("payment", "()")
+                           .allow_default("camera")
+                           .expect_ct(Duration::from_secs(86400), true);
+        let res = headers_of(security, test_helpers::response()).await;
+        assert_eq!(res.headers()["permissions-policy"],
+                   "microphone=(), geolocation=(self \"https://maps.example.com\"), payment=()");
+        assert_eq!(res.headers()["expect-ct"], "max-age=86400, enforce");
+    }
+
+    #[tokio::test]
+    async fn keeps_headers_set_before() {
+        let mut res = test_helpers::response();
+        res.set_header(HeaderName::from_static("permissions-policy"), HeaderValue::from_static("camera=(self)"));
+        let res = headers_of(SecurityHeaders::new(), res).await;
+        assert_eq!(res.headers()["permissions-policy"], "camera=(self)");
+    }
+}
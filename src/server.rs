@@ -1,30 +1,98 @@
 use std::clone::Clone;
+use std::pin::Pin;
 use std::convert::Infallible;
+use std::io;
 use std::net::{SocketAddr, ToSocketAddrs};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
-use hyper::{Body, Request, Response, StatusCode};
+use futures::{future, stream};
+use hyper::{Body, Method, Request, Response, StatusCode};
 use hyper::server::Server as HyperServer;
-use hyper::server::conn::AddrStream;
+use hyper::server::accept::{self, Accept};
+use hyper::server::conn::{AddrIncoming, AddrStream};
 use hyper::service::{make_service_fn, service_fn};
 //use hyper::net::SslServer;
 
+use crate::idle_timeout::{self, IdleTimeout};
 use crate::middleware::MiddlewareStack;
-use crate::request;
+use crate::nickel::Nickel;
+use crate::request::{self, BodyLimits, ProxySettings};
 use crate::response;
-use crate::template_cache::{ReloadPolicy, TemplateCache};
+use crate::template_cache::TemplateCache;
+
+/// A handle to the middleware of a server, for replacing it while the
+/// server is running, e.g. to apply a configuration change without a
+/// restart. Obtained from `Nickel::stack_handle`.
+///
+/// A swap only affects requests arriving after it, requests already being
+/// handled finish on the previous middleware.
+pub struct StackHandle<D: Send + 'static + Sync> {
+    stack: Arc<RwLock<Arc<MiddlewareStack<D>>>>,
+}
+
+impl<D: Send + 'static + Sync> StackHandle<D> {
+    pub(crate) fn new(stack: MiddlewareStack<D>) -> StackHandle<D> {
+        StackHandle { stack: Arc::new(RwLock::new(Arc::new(stack))) }
+    }
+
+    /// Replaces the middleware, routes and error handlers of the server with
+    /// those of `app`. The data and options of `app` are ignored, the server
+    /// keeps its own.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, HttpRouter};
+    ///
+    /// let mut server = Nickel::new();
+    /// server.get("/", middleware!("version 1"));
+    /// let handle = server.stack_handle();
+    /// // server.listen(..) in one task, and later in another one:
+    ///
+    /// let mut reloaded = Nickel::new();
+    /// reloaded.get("/", middleware!("version 2"));
+    /// handle.swap(reloaded);
+    /// ```
+    pub fn swap(&self, mut app: Nickel<D>) {
+        self.set(app.finish_stack());
+    }
+
+    pub(crate) fn set(&self, stack: MiddlewareStack<D>) {
+        *self.stack.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(stack);
+    }
+
+    pub(crate) fn current(&self) -> Arc<MiddlewareStack<D>> {
+        self.stack.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+impl<D: Send + 'static + Sync> Clone for StackHandle<D> {
+    fn clone(&self) -> StackHandle<D> {
+        StackHandle { stack: self.stack.clone() }
+    }
+}
 
 pub struct Server<D: Send + 'static + Sync> {
-    middleware_stack: Arc<MiddlewareStack<D>>,
+    middleware_stack: StackHandle<D>,
     templates: Arc<TemplateCache>,
+    body_limits: Arc<BodyLimits>,
+    proxies: Arc<ProxySettings>,
+    idle_timeout: Option<Duration>,
     shared_data: Arc<D>,
 }
 
 impl<D: Sync + Send + 'static> Server<D> {
-    pub fn new(middleware_stack: MiddlewareStack<D>, reload_policy: ReloadPolicy, data: D) -> Server<D> {
+    pub fn new(middleware_stack: StackHandle<D>,
+               templates: TemplateCache,
+               body_limits: BodyLimits,
+               proxies: ProxySettings,
+               idle_timeout: Option<Duration>,
+               data: D) -> Server<D> {
         Server {
-            middleware_stack: Arc::new(middleware_stack),
-            templates: Arc::new(TemplateCache::with_policy(reload_policy)),
+            middleware_stack,
+            templates: Arc::new(templates),
+            body_limits: Arc::new(body_limits),
+            proxies: Arc::new(proxies),
+            idle_timeout,
             shared_data: Arc::new(data)
         }
     }
@@ -36,37 +104,84 @@ impl<D: Sync + Send + 'static> Server<D> {
                                          -> Result<(), Box<dyn std::error::Error>> {
         let socket_addr: SocketAddr = addr.to_socket_addrs()?.next().ok_or(ServerError("bad address".to_string()))?;
 
-        let make_svc = make_service_fn(move |socket: &AddrStream| {
-            let remote_addr = socket.remote_addr();
-            let mw = self.middleware_stack.clone();
-            let data = self.shared_data.clone();
-            let res_templates = self.templates.clone();
-            async move {
-                Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
-                    let mw2 = mw.clone();
-                    let req_data2 = data.clone();
-                    let res_data2 = data.clone();
-                    let res_templates2 = res_templates.clone();
-                    async move {
-                        let res = Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap();
-                        let nickel_req = request::Request::from_internal(req,
-                                                                         Some(remote_addr.to_owned()),
-                                                                         req_data2);
-                        let nickel_res = response::Response::from_internal(res,
-                                                                           res_templates2,
-                                                                           res_data2);
-                        let final_res = mw2.invoke(nickel_req, nickel_res).await;
-                        Ok::<_, Infallible>(final_res)
-                    }
-                }))
-            }
+        self.serve_multi(&[socket_addr], keep_alive_timeout, thread_count).await
+    }
+
+    /// Binds all of `addrs` before serving any of them, so a failure to bind
+    /// one address is reported (naming the address) without having started
+    /// the others. Bind failures are `io::Error`s of the original kind,
+    /// e.g. `AddrInUse`.
+    pub async fn serve_multi(self,
+                             addrs: &[SocketAddr],
+                             _keep_alive_timeout: Option<Duration>, // TODO: migration cleanup - use this
+                             _thread_count: Option<usize>) // TODO: migration cleanup - use or remove this
+                             -> Result<(), Box<dyn std::error::Error>> {
+        let mut incomings = Vec::with_capacity(addrs.len());
+        for addr in addrs {
+            let bind_error = |e: io::Error| io::Error::new(e.kind(), format!("failed to bind {}: {}", addr, e));
+            let listener = std::net::TcpListener::bind(addr).map_err(bind_error)?;
+            listener.set_nonblocking(true).map_err(bind_error)?;
+            let listener = tokio::net::TcpListener::from_std(listener).map_err(bind_error)?;
+            incomings.push(AddrIncoming::from_listener(listener)?);
+        }
+
+        let servers = incomings.into_iter().map(|mut incoming| {
+            let local_addr = incoming.local_addr();
+            let idle_timeout = self.idle_timeout;
+            let connections = stream::poll_fn(move |cx| {
+                Pin::new(&mut incoming).poll_accept(cx)
+                                       .map_ok(|socket| IdleTimeout::new(socket, idle_timeout))
+            });
+
+            let mw_stack = self.middleware_stack.clone();
+            let shared_data = self.shared_data.clone();
+            let templates = self.templates.clone();
+            let body_limits = self.body_limits.clone();
+            let proxies = self.proxies.clone();
+            let make_svc = make_service_fn(move |socket: &IdleTimeout<AddrStream>| {
+                let remote_addr = socket.get_ref().remote_addr();
+                let busy = socket.busy();
+                let mw = mw_stack.clone();
+                let data = shared_data.clone();
+                let res_templates = templates.clone();
+                let body_limits = body_limits.clone();
+                let proxies = proxies.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                        let mw2 = mw.current();
+                        let req_data2 = data.clone();
+                        let res_data2 = data.clone();
+                        let res_templates2 = res_templates.clone();
+                        let body_limits2 = body_limits.clone();
+                        let proxies2 = proxies.clone();
+                        let busy = busy();
+                        async move {
+                            let res = Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()).unwrap();
+                            let mut nickel_req = request::Request::from_internal(req,
+                                                                                 Some(remote_addr.to_owned()),
+                                                                                 req_data2);
+                            nickel_req.set_body_limit(body_limits2.for_request(&nickel_req));
+                            nickel_req.set_json_limits(body_limits2.json());
+                            nickel_req.set_proxy_settings(proxies2);
+                            let mut nickel_res = response::Response::from_internal(res,
+                                                                                   res_templates2,
+                                                                                   res_data2);
+                            if nickel_req.origin.method() == Method::HEAD {
+                                nickel_res.set_head_request();
+                            }
+                            let final_res = mw2.invoke(nickel_req, nickel_res).await;
+                            Ok::<_, Infallible>(idle_timeout::busy_until_sent(final_res, busy))
+                        }
+                    }))
+                }
+            });
+
+            println!("Listening on http://{}", local_addr);
+            HyperServer::builder(accept::from_stream(connections)).serve(make_svc)
         });
-        let server = HyperServer::bind(&socket_addr).serve(make_svc);
 
-        println!("Listening on http://{}", socket_addr);
-        
-        server.await?;
-        
+        future::try_join_all(servers).await?;
+
         Ok(())
     }
 }
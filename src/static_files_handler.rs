@@ -1,34 +1,82 @@
 use async_trait::async_trait;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::io::ErrorKind::NotFound;
+use std::io::Read;
 use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Utc};
 
 use hyper::Method;
+use hyper::header::{self, HeaderValue};
+use percent_encoding::percent_decode_str;
+use regex::Regex;
 
 use crate::status::StatusCode;
-use crate::request::Request;
-use crate::response::Response;
-use crate::middleware::{Middleware, MiddlewareResult};
+use crate::mimes::MediaType;
+use crate::request::{self, Request};
+use crate::response::{self, Response};
+use crate::middleware::{Halt, Middleware, MiddlewareResult};
+use crate::mount::Mount;
+use crate::util::http_date;
 
 // this should be much simpler after unboxed closures land in Rust.
 
+pub struct StaticFilesHandler<D = ()> {
+    root_path: PathBuf,
+    hashed_caching: Option<HashedCaching>,
+    authorize: Option<Arc<Authorize<D>>>,
+    sniff: bool,
+    fallback: Option<PathBuf>,
+    // Sent as the type of every file, like the challenge tokens of
+    // `acme_challenges`
+    media_type: Option<MediaType>
+}
+
+type Authorize<D> = dyn Fn(&Request<D>, &Path) -> bool + Send + Sync;
+
+impl<D> Clone for StaticFilesHandler<D> {
+    fn clone(&self) -> StaticFilesHandler<D> {
+        StaticFilesHandler {
+            root_path: self.root_path.clone(),
+            hashed_caching: self.hashed_caching.clone(),
+            authorize: self.authorize.clone(),
+            sniff: self.sniff,
+            fallback: self.fallback.clone(),
+            media_type: self.media_type
+        }
+    }
+}
+
 #[derive(Clone)]
-pub struct StaticFilesHandler {
-    root_path: PathBuf
+struct HashedCaching {
+    pattern: Regex,
+    short_max_age: Duration
 }
 
+// A hex content hash between dots, like in `app.ab12cd.js`
+const DEFAULT_HASH_PATTERN: &str = r"\.[0-9a-f]{6,}\.";
+
 #[async_trait]
-impl<D: Send + 'static + Sync> Middleware<D> for StaticFilesHandler {
+impl<D: Send + 'static + Sync> Middleware<D> for StaticFilesHandler<D> {
     async fn invoke(&self, req: &mut Request<D>, res: Response<D>)
             -> MiddlewareResult<D> {
         match *req.origin.method() {
-            Method::GET | Method::HEAD => self.with_file(self.extract_path(req), res).await,
+            Method::GET | Method::HEAD => {
+                let path = match percent_decode_str(self.extract_path(req)).decode_utf8() {
+                    Ok(path) => path.into_owned(),
+                    Err(_) => return res.error(StatusCode::BAD_REQUEST,
+                                               "The requested path is not valid UTF-8")
+                };
+                self.with_file(req, path, res).await
+            },
             _ => res.next_middleware()
         }
     }
 }
 
-impl StaticFilesHandler {
+impl<D> StaticFilesHandler<D> {
     /// Create a new middleware to serve files from within a given root directory.
     /// The file to serve will be determined by combining the requested Url with
     /// the provided root directory.
@@ -41,13 +89,188 @@ impl StaticFilesHandler {
     ///
     /// server.utilize(StaticFilesHandler::new("/path/to/serve/"));
     /// ```
-    pub fn new<P: AsRef<Path>>(root_path: P) -> StaticFilesHandler {
+    pub fn new<P: AsRef<Path>>(root_path: P) -> StaticFilesHandler<D> {
         StaticFilesHandler {
-            root_path: root_path.as_ref().to_path_buf()
+            root_path: root_path.as_ref().to_path_buf(),
+            hashed_caching: None,
+            authorize: None,
+            sniff: false,
+            fallback: None,
+            media_type: None
         }
     }
 
-    fn extract_path<'a, D>(&self, req: &'a mut Request<D>) -> &'a str {
+    /// Serves the tokens in `challenge_dir` under
+    /// `/.well-known/acme-challenge/`, for ACME clients like certbot that
+    /// prove control of a domain by writing a token file that the
+    /// certificate authority then fetches.
+    ///
+    /// A handler for a whole web root serves `.well-known` as well, this is
+    /// for challenge directories kept elsewhere. Either way, challenge
+    /// tokens are sent as `text/plain`.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, StaticFilesHandler};
+    /// let mut server = Nickel::new();
+    ///
+    /// server.utilize(StaticFilesHandler::acme_challenges("/var/lib/acme/challenges/"));
+    /// ```
+    pub fn acme_challenges<P: AsRef<Path>>(challenge_dir: P) -> Mount<StaticFilesHandler<D>> {
+        let mut handler = StaticFilesHandler::new(challenge_dir);
+        // The tokens are served from the root of the challenge directory,
+        // where `.well-known` isn't part of their path
+        handler.media_type = Some(MediaType::Txt);
+        Mount::new("/.well-known/acme-challenge/", handler)
+    }
+
+    /// Only serves files for which `authorize` returns true, given the
+    /// request and the path of the file relative to the root directory.
+    /// Other requests are answered with `403 Forbidden`, whether the file
+    /// exists or not. Public and protected files can so share a directory.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use std::path::Path;
+    /// use nickel::{Nickel, Request, StaticFilesHandler};
+    /// let mut server = Nickel::new();
+    ///
+    /// server.utilize(StaticFilesHandler::new("assets/")
+    ///                    .authorize(|req: &Request, path: &Path| {
+    ///                        !path.starts_with("reports") || req.bearer_token() == Some("s3cr3t")
+    ///                    }));
+    /// ```
+    pub fn authorize<F>(mut self, authorize: F) -> StaticFilesHandler<D>
+    where F: Fn(&Request<D>, &Path) -> bool + Send + Sync + 'static {
+        self.authorize = Some(Arc::new(authorize));
+        self
+    }
+
+    /// Detects the content type of files without an extension from their
+    /// first bytes, instead of sending them as `application/octet-stream`,
+    /// which browsers download rather than display. HTML, PNG, JPEG, GIF and
+    /// PDF files are recognized.
+    ///
+    /// The detected type is sent as the `Content-Type`, so this works with
+    /// `X-Content-Type-Options: nosniff`, which only stops browsers from
+    /// second-guessing the type the server sent.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, StaticFilesHandler};
+    /// let mut server = Nickel::new();
+    ///
+    /// server.utilize(StaticFilesHandler::new("legacy-site/").sniff_content_type());
+    /// ```
+    pub fn sniff_content_type(mut self) -> StaticFilesHandler<D> {
+        self.sniff = true;
+        self
+    }
+
+    /// Serves `fallback`, relative to the root directory, for page requests
+    /// that don't match a file, so a single-page app can do its routing on
+    /// the client. A page request is one with an `Accept` header listing
+    /// `text/html`, for a path without an extension.
+    ///
+    /// Other requests still fall through to the next middleware, so missing
+    /// assets like `app.js` or API requests keep ending up as `404`.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use nickel::{Nickel, StaticFilesHandler};
+    /// let mut server = Nickel::new();
+    ///
+    /// server.utilize(StaticFilesHandler::new("dist/").with_fallback("index.html"));
+    /// ```
+    pub fn with_fallback<P: AsRef<Path>>(mut self, fallback: P) -> StaticFilesHandler<D> {
+        self.fallback = Some(fallback.as_ref().to_path_buf());
+        self
+    }
+
+    /// Lets clients cache files with a content hash in their name, as
+    /// emitted by cache-busting build pipelines, forever. They are sent with
+    /// `Cache-Control: public, max-age=31536000, immutable`, while all other
+    /// files may only be cached for `short_max_age`.
+    ///
+    /// By default a hash is a dot separated group of at least six lowercase
+    /// hex digits, as in `app.ab12cd.js`, see `hash_pattern` to change that.
+    ///
+    /// # Examples
+    /// ```{rust}
+    /// use std::time::Duration;
+    /// use nickel::{Nickel, StaticFilesHandler};
+    /// let mut server = Nickel::new();
+    ///
+    /// server.utilize(StaticFilesHandler::new("dist/")
+    ///                    .hashed_file_caching(Duration::from_secs(60)));
+    /// ```
+    pub fn hashed_file_caching(mut self, short_max_age: Duration) -> StaticFilesHandler<D> {
+        let pattern = match self.hashed_caching.take() {
+            Some(caching) => caching.pattern,
+            None => Regex::new(DEFAULT_HASH_PATTERN).unwrap()
+        };
+        self.hashed_caching = Some(HashedCaching { pattern, short_max_age });
+        self
+    }
+
+    /// The pattern identifying a content hash in a file name, for
+    /// `hashed_file_caching`. It is matched against the file name only, not
+    /// the directories leading to it.
+    ///
+    /// Enables `hashed_file_caching` with a short max-age of 0 if it
+    /// wasn't enabled yet.
+    pub fn hash_pattern(mut self, pattern: Regex) -> StaticFilesHandler<D> {
+        let short_max_age = self.hashed_caching.as_ref()
+                                               .map_or(Duration::from_secs(0), |c| c.short_max_age);
+        self.hashed_caching = Some(HashedCaching { pattern, short_max_age });
+        self
+    }
+
+    fn cache_control(&self, path: &Path) -> Option<HeaderValue> {
+        let caching = self.hashed_caching.as_ref()?;
+        let hashed = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => caching.pattern.is_match(name),
+            None => false
+        };
+
+        let value = if hashed {
+            "public, max-age=31536000, immutable".to_string()
+        } else {
+            format!("public, max-age={}", caching.short_max_age.as_secs())
+        };
+        HeaderValue::from_str(&value).ok()
+    }
+
+    fn is_authorized(&self, req: &Request<D>, path: &Path) -> bool {
+        match self.authorize {
+            Some(ref authorize) => authorize(req, path),
+            None => true
+        }
+    }
+
+    // Whether `path` is still inside the root directory once symlinks are
+    // resolved. Paths that don't exist are left to the regular lookup.
+    fn within_root(&self, path: &Path) -> bool {
+        match (path.canonicalize(), self.root_path.canonicalize()) {
+            (Ok(path), Ok(root)) => path.starts_with(root),
+            _ => true
+        }
+    }
+
+    // The fallback file to serve instead of the missing `path`, if any
+    fn fallback_for(&self, req: &Request<D>, path: &Path) -> Option<&Path> {
+        let fallback = self.fallback.as_deref()?;
+        let lists_html = req.accept_header().is_some_and(|accept| {
+            request::media_ranges(&accept).any(|range| range.essence_str() == mime::TEXT_HTML.essence_str())
+        });
+        if path.extension().is_none() && lists_html && req.accepts(MediaType::Html) {
+            Some(fallback)
+        } else {
+            None
+        }
+    }
+
+    fn extract_path<'a>(&self, req: &'a Request<D>) -> &'a str {
         let path = req.path_without_query();
         debug!("{:?} {:?}{:?}", req.origin.method(), self.root_path.display(), path);
         
@@ -57,19 +280,68 @@ impl StaticFilesHandler {
         }
     }
 
-    async fn with_file<D: Send + 'static + Sync, P>(&self,
-                                              relative_path: P,
-                                              res: Response<D>)
-                                              -> MiddlewareResult<D> where P: AsRef<Path> {
+    async fn with_file<P>(&self,
+                          req: &Request<D>,
+                          relative_path: P,
+                          mut res: Response<D>)
+                          -> MiddlewareResult<D> where P: AsRef<Path>, D: Send + 'static + Sync {
         let path = relative_path.as_ref();
         if !safe_path(path) {
-            let log_msg = format!("The path '{:?}' was denied access.", path);
-            return res.error(StatusCode::BAD_REQUEST, log_msg);
+            debug!("The path '{:?}' was denied access", path);
+            return res.next_middleware();
+        }
+
+        // `./report.pdf` is the same file as `report.pdf`, so `authorize`
+        // only ever sees the latter
+        let relative_path: PathBuf = path.components()
+                                         .filter(|c| matches!(c, Component::Normal(_)))
+                                         .collect();
+        if !self.is_authorized(req, &relative_path) {
+            return res.error(StatusCode::FORBIDDEN, format!("Access to '{:?}' is not authorized", relative_path));
+        }
+
+        let mut path = self.root_path.join(&relative_path);
+        if !path.is_file() {
+            if let Some(fallback) = self.fallback_for(req, &relative_path) {
+                if !self.is_authorized(req, fallback) {
+                    return res.error(StatusCode::FORBIDDEN, format!("Access to '{:?}' is not authorized", fallback));
+                }
+                path = self.root_path.join(fallback);
+            }
+        }
+        if !self.within_root(&path) {
+            debug!("The path '{:?}' resolves to outside of the root directory", path);
+            return res.next_middleware();
         }
-        
-        let path = self.root_path.join(path);
         match fs::metadata(&path) {
-            Ok(ref attr) if attr.is_file() => return res.send_file(&path).await,
+            Ok(ref attr) if attr.is_file() => {
+                if let Some(cache_control) = self.cache_control(&path) {
+                    res.set_header(header::CACHE_CONTROL, cache_control);
+                }
+                if let Ok(modified) = attr.modified() {
+                    let etag = file_etag(attr.len(), modified);
+                    res.set_header(header::ETAG, HeaderValue::from_str(&etag).unwrap());
+                    res.set_header(header::LAST_MODIFIED, HeaderValue::from_str(&http_date(modified)).unwrap());
+                    if not_modified(req, &etag, modified) {
+                        res.set(StatusCode::NOT_MODIFIED);
+                        res.start();
+                        return Ok(Halt(res));
+                    }
+                }
+                if let Some(media_type) = self.media_type {
+                    res.set(media_type);
+                } else if path.extension().is_none() {
+                    let well_known = path.strip_prefix(&self.root_path).ok().and_then(well_known_type);
+                    let media_type = match well_known {
+                        None if self.sniff => sniff_file(&path),
+                        media_type => media_type
+                    };
+                    if let Some(media_type) = media_type {
+                        res.set(media_type);
+                    }
+                }
+                return res.send_file_ranged(&path, req).await
+            },
             Err(ref e) if e.kind() != NotFound => debug!("Error getting metadata \
                                                           for file '{:?}': {:?}",
                                                          path, e),
@@ -80,13 +352,88 @@ impl StaticFilesHandler {
     }
 }
 
+// A weak ETag from the size and modification time of a file, which is cheap
+// to compute but changes whenever the file is replaced or edited.
+fn file_etag(len: u64, modified: SystemTime) -> String {
+    let nanos = modified.duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0);
+    format!("W/\"{:x}-{:x}\"", len, nanos)
+}
+
+// Whether the client's copy is still current. `If-None-Match` takes
+// precedence over `If-Modified-Since`, which only has second precision.
+fn not_modified<D>(req: &Request<D>, etag: &str, modified: SystemTime) -> bool {
+    let headers = req.origin.headers();
+    if headers.contains_key(header::IF_NONE_MATCH) {
+        return headers.get_all(header::IF_NONE_MATCH)
+                      .iter()
+                      .any(|v| response::etag_matches(v, etag));
+    }
+
+    let since = headers.get(header::IF_MODIFIED_SINCE)
+                       .and_then(|v| v.to_str().ok())
+                       .and_then(|v| DateTime::parse_from_rfc2822(v).ok());
+    match since {
+        Some(since) => DateTime::<Utc>::from(modified).timestamp() <= since.timestamp(),
+        None => false
+    }
+}
+
+// The type of the file at `path` according to its first bytes.
+fn sniff_file(path: &Path) -> Option<MediaType> {
+    let mut head = Vec::with_capacity(512);
+    fs::File::open(path).and_then(|file| file.take(512).read_to_end(&mut head)).ok()?;
+    sniff(&head)
+}
+
+fn sniff(head: &[u8]) -> Option<MediaType> {
+    const SIGNATURES: &[(&[u8], MediaType)] = &[
+        (b"\x89PNG\r\n\x1a\n", MediaType::Png),
+        (b"\xff\xd8\xff", MediaType::Jpeg),
+        (b"GIF87a", MediaType::Gif),
+        (b"GIF89a", MediaType::Gif),
+        (b"%PDF-", MediaType::Pdf),
+    ];
+    const HTML_TAGS: &[&[u8]] = &[b"<!doctype html", b"<html", b"<head", b"<body", b"<!--"];
+
+    if let Some(&(_, media_type)) = SIGNATURES.iter().find(|(signature, _)| head.starts_with(signature)) {
+        return Some(media_type);
+    }
+
+    let text = match head.iter().position(|b| !b.is_ascii_whitespace()) {
+        Some(start) => &head[start..],
+        None => return None
+    };
+    let is_html = HTML_TAGS.iter().any(|tag| {
+        text.len() >= tag.len() && text[..tag.len()].eq_ignore_ascii_case(tag)
+    });
+    if is_html {
+        Some(MediaType::Html)
+    } else {
+        None
+    }
+}
+
+// The content type of extensionless `.well-known` resources with a
+// registered format, given their path relative to the root directory
+fn well_known_type(path: &Path) -> Option<MediaType> {
+    let mut names = path.iter().map(|name| name.to_str());
+    if names.next()? != Some(".well-known") {
+        return None;
+    }
+    match names.next()?? {
+        "acme-challenge" => Some(MediaType::Txt),
+        "apple-app-site-association" | "openid-configuration" | "oauth-authorization-server" => Some(MediaType::Json),
+        _ => None
+    }
+}
+
 /// Block paths from accessing the parent directory
 fn safe_path<P: AsRef<Path>>(path: P) -> bool {
-    use std::path::Component;
-
     path.as_ref().components().all(|c| match c {
-        // whitelist non-suspicious in case new things get added in future
-        Component::CurDir | Component::Normal(_) => true,
+        // whitelist non-suspicious in case new things get added in future.
+        // Backslashes are separators on Windows, so `..\\` would sneak past
+        Component::CurDir => true,
+        Component::Normal(name) => !name.to_string_lossy().contains('\\'),
         _ => false
     })
 }
@@ -98,7 +445,9 @@ fn bad_paths() {
         "foo/bar/../baz",
         "../bar/",
         "..",
-        "/" // Root path should be handled already
+        "/", // Root path should be handled already
+        "foo\\..\\..\\secret",
+        "..\\secret"
     ];
 
     for &path in bad_paths {
@@ -120,3 +469,266 @@ fn valid_paths() {
         assert!(safe_path(path), "expected {:?} to not be suspicious", path);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use hyper::header;
+    use regex::Regex;
+    use super::StaticFilesHandler;
+    use crate::{Middleware, test_helpers};
+
+    fn assets(name: &str, files: &[&str]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nickel-static-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        for file in files {
+            fs::write(dir.join(file), "content").unwrap();
+        }
+        dir
+    }
+
+    async fn cache_control(handler: &StaticFilesHandler, path: &str) -> Option<String> {
+        let mut req = test_helpers::get(path);
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        res.headers().get(header::CACHE_CONTROL).map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn unchanged_files_are_not_modified() {
+        use hyper::{Request as HyperRequest, StatusCode};
+
+        let root = assets("conditional", &["app.js"]);
+        let handler = StaticFilesHandler::new(&root);
+
+        let mut req = test_helpers::get("/app.js");
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::OK);
+        let etag = res.headers()[header::ETAG].to_str().unwrap().to_string();
+        let last_modified = res.headers()[header::LAST_MODIFIED].to_str().unwrap().to_string();
+        assert!(etag.starts_with("W/\""), "{}", etag);
+        assert_eq!(test_helpers::body_string(res).await, "content");
+
+        let mut req = test_helpers::request(HyperRequest::get("/app.js").header("if-none-match", etag.as_str()));
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], etag.as_str());
+        assert_eq!(test_helpers::body_string(res).await, "");
+
+        let mut req = test_helpers::request(HyperRequest::get("/app.js").header("if-modified-since", last_modified.as_str()));
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        // a stale tag wins over a current date
+        let mut req = test_helpers::request(HyperRequest::get("/app.js")
+                                                .header("if-none-match", "W/\"0-0\"")
+                                                .header("if-modified-since", last_modified.as_str()));
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test_helpers::body_string(res).await, "content");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn page_requests_fall_back_to_index() {
+        use hyper::Request as HyperRequest;
+        use crate::Action;
+
+        let root = assets("fallback", &["index.html", "app.js"]);
+        let handler = StaticFilesHandler::new(&root).with_fallback("index.html");
+        let page = |path: &str, accept: &str| {
+            test_helpers::request(HyperRequest::get(path).header("accept", accept))
+        };
+
+        let mut req = page("/users/42", "text/html,application/xhtml+xml,*/*;q=0.8");
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert_eq!(test_helpers::body_string(res).await, "content");
+
+        // missing assets, API requests and excluded HTML fall through
+        for (path, accept) in &[("/missing.js", "text/html"),
+                                ("/api/users", "application/json"),
+                                ("/users/42", "text/html;q=0, */*")] {
+            let mut req = page(path, accept);
+            match handler.invoke(&mut req, test_helpers::response()).await {
+                Ok(Action::Continue(_)) => {},
+                _ => panic!("expected {} to fall through", path)
+            }
+        }
+
+        let mut req = page("/app.js", "*/*");
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "content");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn hashed_files_are_immutable() {
+        let root = assets("hashed", &["app.ab12cd.js", "app.js"]);
+        let handler = StaticFilesHandler::new(&root).hashed_file_caching(Duration::from_secs(60));
+
+        assert_eq!(cache_control(&handler, "/app.ab12cd.js").await.as_deref(),
+                   Some("public, max-age=31536000, immutable"));
+        assert_eq!(cache_control(&handler, "/app.js").await.as_deref(), Some("public, max-age=60"));
+
+        let plain = StaticFilesHandler::new(&root);
+        assert_eq!(cache_control(&plain, "/app.ab12cd.js").await, None);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn paths_cannot_escape_the_root() {
+        use crate::Action;
+
+        let outside = assets("outside", &["secret.txt"]);
+        let root = assets("traversal", &["hello world.txt"]);
+        let handler = StaticFilesHandler::new(root.join("."));
+
+        let mut req = test_helpers::get("/hello%20world.txt");
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "content");
+
+        let escape = format!("/%2e%2e/nickel-static-{}-outside/secret.txt", std::process::id());
+        for path in &[escape.as_str(), "/sub%5c..%5c..%5csecret.txt", "/%2E%2E%2Fsecret.txt"] {
+            let mut req = test_helpers::get(path);
+            match handler.invoke(&mut req, test_helpers::response()).await {
+                Ok(Action::Continue(_)) => {},
+                _ => panic!("expected {} to fall through", path)
+            }
+        }
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("link.txt")).unwrap();
+            let mut req = test_helpers::get("/link.txt");
+            match handler.invoke(&mut req, test_helpers::response()).await {
+                Ok(Action::Continue(_)) => {},
+                _ => panic!("expected the symlink out of the root to fall through")
+            }
+        }
+
+        fs::remove_dir_all(root).unwrap();
+        fs::remove_dir_all(outside).unwrap();
+    }
+
+    #[tokio::test]
+    async fn protected_paths_need_authorization() {
+        use std::path::Path;
+        use hyper::{Request as HyperRequest, StatusCode};
+        use crate::Request;
+
+        let root = assets("authorize", &["index.css", "report.pdf"]);
+        let handler = StaticFilesHandler::new(&root).authorize(|req: &Request, path: &Path| {
+            path != Path::new("report.pdf") || req.bearer_token() == Some("s3cr3t")
+        });
+        let fetch = |path: &str, token: Option<&str>| {
+            let mut builder = HyperRequest::get(path);
+            if let Some(token) = token {
+                builder = builder.header("authorization", format!("Bearer {}", token));
+            }
+            test_helpers::request(builder)
+        };
+
+        let mut req = fetch("/report.pdf", None);
+        let res = test_helpers::errored(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        for path in &["/./report.pdf", "/%2e/report.pdf", "/.%2freport.pdf"] {
+            let mut req = fetch(path, None);
+            let res = test_helpers::errored(handler.invoke(&mut req, test_helpers::response()).await);
+            assert_eq!(res.status(), StatusCode::FORBIDDEN, "{}", path);
+        }
+
+        let mut req = fetch("/report.pdf", Some("wrong"));
+        let res = test_helpers::errored(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let mut req = fetch("/report.pdf", Some("s3cr3t"));
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "content");
+
+        let mut req = fetch("/index.css", None);
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(test_helpers::body_string(res).await, "content");
+
+        // the fallback of page requests is protected as well
+        let handler = handler.with_fallback("report.pdf");
+        let mut req = test_helpers::request(HyperRequest::get("/dashboard").header("accept", "text/html"));
+        let res = test_helpers::errored(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn acme_challenges_are_served_from_well_known() {
+        use crate::Mount;
+
+        let root = assets("well-known", &[]);
+        let challenges = root.join(".well-known").join("acme-challenge");
+        fs::create_dir_all(&challenges).unwrap();
+        fs::write(challenges.join("LoqXcYV8q5ONbJQxbmR7SCTNo3tiAXDfowyjxAjEuX0"), "token").unwrap();
+        fs::write(root.join(".well-known").join("openid-configuration"), "{}").unwrap();
+
+        let handler = StaticFilesHandler::new(&root);
+        let mut req = test_helpers::get("/.well-known/acme-challenge/LoqXcYV8q5ONbJQxbmR7SCTNo3tiAXDfowyjxAjEuX0");
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+        assert_eq!(test_helpers::body_string(res).await, "token");
+        let mut req = test_helpers::get("/.well-known/openid-configuration");
+        let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/json");
+
+        let acme: Mount<StaticFilesHandler> = StaticFilesHandler::acme_challenges(&challenges);
+        let mut req = test_helpers::get("/.well-known/acme-challenge/LoqXcYV8q5ONbJQxbmR7SCTNo3tiAXDfowyjxAjEuX0");
+        let res = test_helpers::halted(acme.invoke(&mut req, test_helpers::response()).await);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+        assert_eq!(test_helpers::body_string(res).await, "token");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn sniffs_extensionless_files() {
+        let root = assets("sniff", &[]);
+        fs::write(root.join("about"), "\n  <!DOCTYPE html><title>About</title>").unwrap();
+        fs::write(root.join("logo"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        fs::write(root.join("notes"), "just some notes").unwrap();
+        fs::write(root.join("page.txt"), "<html>not really</html>").unwrap();
+
+        let content_type = |handler: StaticFilesHandler, path: &'static str| async move {
+            let mut req = test_helpers::get(path);
+            let res = test_helpers::halted(handler.invoke(&mut req, test_helpers::response()).await);
+            res.headers()[header::CONTENT_TYPE].to_str().unwrap().to_string()
+        };
+        let handler = StaticFilesHandler::new(&root).sniff_content_type();
+        assert_eq!(content_type(handler.clone(), "/about").await, "text/html; charset=utf-8");
+        assert_eq!(content_type(handler.clone(), "/logo").await, "image/png");
+        assert_eq!(content_type(handler.clone(), "/notes").await, "application/octet-stream");
+        // extensions win
+        assert_eq!(content_type(handler, "/page.txt").await, "text/plain; charset=utf-8");
+
+        let plain = StaticFilesHandler::new(&root);
+        assert_eq!(content_type(plain, "/about").await, "application/octet-stream");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn hash_pattern_is_configurable() {
+        let root = assets("pattern", &["app-7F3A.js", "app.ab12cd.js"]);
+        let handler = StaticFilesHandler::new(&root)
+                          .hash_pattern(Regex::new(r"-[0-9A-F]{4}\.").unwrap())
+                          .hashed_file_caching(Duration::from_secs(300));
+
+        assert_eq!(cache_control(&handler, "/app-7F3A.js").await.as_deref(),
+                   Some("public, max-age=31536000, immutable"));
+        assert_eq!(cache_control(&handler, "/app.ab12cd.js").await.as_deref(), Some("public, max-age=300"));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -1,33 +1,146 @@
-use mustache::{Error, Template, compile_str};
+#[cfg(feature = "templates")]
+use mustache::compile_str;
+#[cfg(feature = "templates")]
 use serde::Serialize;
+#[cfg(feature = "templates")]
+use std::any::Any;
+#[cfg(feature = "templates")]
 use std::collections::HashMap;
-use std::path::{Path, PathBuf};
-use std::time::{Duration, SystemTime};
+#[cfg(feature = "templates")]
+use std::io::Write;
+#[cfg(feature = "templates")]
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use hyper::header::HeaderName;
+#[cfg(feature = "templates")]
+use std::time::SystemTime;
+#[cfg(feature = "templates")]
 use tokio::fs::{read_to_string, metadata};
+#[cfg(feature = "templates")]
 use tokio::sync::RwLock;
 
+/// Errors compiling or rendering a template.
+#[cfg(feature = "templates")]
+pub type TemplateError = Box<dyn std::error::Error + Send + Sync>;
 
+/// A template language for `Response::render`, like Mustache (the
+/// default, see `MustacheEngine`), Handlebars or Tera.
+///
+/// Templates are compiled once and cached by the `TemplateCache`, which
+/// takes care of loading and reloading the template files. `data` is what
+/// was passed to `render`; it implements `serde::Serialize`, which is what
+/// most template crates take. `erased_serde` is re-exported as
+/// `nickel::erased_serde`.
+///
+/// # Examples
+/// ```{rust}
+/// use std::io::Write;
+/// use nickel::{Nickel, TemplateEngine, TemplateError};
+/// use nickel::erased_serde::Serialize;
+///
+/// // Serves the templates as they are, e.g. for static pages
+/// struct Verbatim;
+///
+/// impl TemplateEngine for Verbatim {
+///     type Template = String;
+///
+///     fn compile(&self, source: &str) -> Result<String, TemplateError> {
+///         Ok(source.to_string())
+///     }
+///
+///     fn render(&self, template: &String, writer: &mut dyn Write,
+///               _: &dyn Serialize) -> Result<(), TemplateError> {
+///         Ok(writer.write_all(template.as_bytes())?)
+///     }
+/// }
+///
+/// let server = Nickel::new().with_template_engine(Verbatim);
+/// ```
+#[cfg(feature = "templates")]
+pub trait TemplateEngine: Send + Sync + 'static {
+    /// A compiled template.
+    type Template: Send + Sync + 'static;
+
+    /// Compiles the contents of a template file.
+    fn compile(&self, source: &str) -> Result<Self::Template, TemplateError>;
+
+    /// Renders `template` with `data` to `writer`.
+    fn render(&self, template: &Self::Template, writer: &mut dyn Write,
+              data: &dyn erased_serde::Serialize) -> Result<(), TemplateError>;
+}
+
+/// The default `TemplateEngine`, rendering Mustache templates.
+#[cfg(feature = "templates")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MustacheEngine;
+
+#[cfg(feature = "templates")]
+impl TemplateEngine for MustacheEngine {
+    type Template = mustache::Template;
+
+    fn compile(&self, source: &str) -> Result<mustache::Template, TemplateError> {
+        Ok(compile_str(source)?)
+    }
+
+    fn render(&self, template: &mustache::Template, mut writer: &mut dyn Write,
+              data: &dyn erased_serde::Serialize) -> Result<(), TemplateError> {
+        Ok(template.render(&mut writer, &data)?)
+    }
+}
+
+#[cfg(feature = "templates")]
+type CompiledTemplate = Box<dyn Any + Send + Sync>;
+
+// A `TemplateEngine` with its template type erased, so the cache, and with
+// it responses, don't depend on the engine's type.
+#[cfg(feature = "templates")]
+pub(crate) trait AnyEngine: Send + Sync {
+    fn compile(&self, source: &str) -> Result<CompiledTemplate, TemplateError>;
+    fn render(&self, template: &CompiledTemplate,
+              data: &dyn erased_serde::Serialize) -> Result<String, TemplateError>;
+}
+
+#[cfg(feature = "templates")]
+impl<E: TemplateEngine> AnyEngine for E {
+    fn compile(&self, source: &str) -> Result<CompiledTemplate, TemplateError> {
+        Ok(Box::new(TemplateEngine::compile(self, source)?))
+    }
+
+    fn render(&self, template: &CompiledTemplate,
+              data: &dyn erased_serde::Serialize) -> Result<String, TemplateError> {
+        // Templates are only ever compiled by the engine rendering them
+        let template = template.downcast_ref::<E::Template>()
+                               .expect("template compiled by another engine");
+        let mut rendered = Vec::new();
+        TemplateEngine::render(self, template, &mut rendered, data)?;
+        Ok(String::from_utf8(rendered)?)
+    }
+}
+
+#[cfg(feature = "templates")]
 struct TemplateEntry {
-    template: Template,       // Compiled template
-    mtime: SystemTime,        // mtime of parsed template file
-    last_checked: SystemTime, // last time the template file mtime was checked
+    template: CompiledTemplate, // Compiled template
+    mtime: SystemTime,          // mtime of parsed template file
+    last_checked: SystemTime,   // last time the template file mtime was checked
 }
 
+#[cfg(feature = "templates")]
 impl TemplateEntry {
     // Loads a template from the given filename
-    async fn from_template_file<P: AsRef<Path>>(filename: P) -> Result<TemplateEntry, Error> {
+    async fn from_template_file<P: AsRef<Path>>(engine: &dyn AnyEngine, filename: P) -> Result<TemplateEntry, TemplateError> {
         let path = filename.as_ref();
         let buf = read_to_string(&path).await?;
-        let template = compile_str(&buf)?;
+        let template = engine.compile(&buf)?;
 
         let attr = metadata(path).await?;
         Ok(TemplateEntry{template: template, mtime: attr.modified()?, last_checked: SystemTime::now()})
     }
 
     // render the tempate with the given data
-    fn render<D>(&self, data: &D) -> Result<String, Error>
+    fn render<D>(&self, engine: &dyn AnyEngine, data: &D) -> Result<String, TemplateError>
         where D: Serialize {
-        self.template.render_to_string(data)
+        engine.render(&self.template, data)
     }
 }
 
@@ -48,16 +161,91 @@ pub enum ReloadPolicy {
     Always,
 }
 
-/// Cache of compiled mustache templates
+/// HeadRender controls how `Response::render` answers `HEAD` requests.
+/// Either way no body is sent.
+pub enum HeadRender {
+    /// Don't render the template at all, the response has no
+    /// `Content-Length`.
+    Skip,
+    /// Render the template and throw the result away, to send the
+    /// `Content-Length` a `GET` would have.
+    Measure,
+}
+
+/// Cache of compiled templates
+///
+/// Without the `templates` feature the cache stays empty and offers no way
+/// to render.
 pub struct TemplateCache {
+    #[cfg(feature = "templates")]
     cache: RwLock<HashMap<PathBuf, TemplateEntry>>,
+    #[cfg(feature = "templates")]
+    engine: Box<dyn AnyEngine>,
+    #[cfg_attr(not(feature = "templates"), allow(dead_code))]
     reload_policy: ReloadPolicy,
+    #[cfg_attr(not(feature = "templates"), allow(dead_code))]
+    roots: Vec<PathBuf>,
+    #[cfg_attr(not(feature = "templates"), allow(dead_code))]
+    head_render: HeadRender,
+    #[cfg_attr(not(feature = "templates"), allow(dead_code))]
+    cache_key_headers: Vec<HeaderName>,
 }
 
 impl TemplateCache {
     /// Create a TemplateCache with the specified reload policy
     pub fn with_policy(policy: ReloadPolicy) -> TemplateCache {
-        TemplateCache{cache: RwLock::new(HashMap::new()), reload_policy: policy}
+        TemplateCache {
+            #[cfg(feature = "templates")]
+            cache: RwLock::new(HashMap::new()),
+            #[cfg(feature = "templates")]
+            engine: Box::new(MustacheEngine),
+            reload_policy: policy,
+            roots: Vec::new(),
+            head_render: HeadRender::Skip,
+            cache_key_headers: Vec::new()
+        }
+    }
+
+    /// Look up relative template paths in `roots`, in order. The first root
+    /// containing the template wins.
+    pub fn with_roots(mut self, roots: Vec<PathBuf>) -> TemplateCache {
+        self.roots = roots;
+        self
+    }
+
+    /// How to render templates for `HEAD` requests. Defaults to
+    /// `HeadRender::Skip`.
+    pub fn with_head_render(mut self, head_render: HeadRender) -> TemplateCache {
+        self.head_render = head_render;
+        self
+    }
+
+    /// Request headers whose values select a variant of a cached render,
+    /// see `Response::render_cached`.
+    pub fn with_cache_key_headers(mut self, headers: Vec<HeaderName>) -> TemplateCache {
+        self.cache_key_headers = headers;
+        self
+    }
+}
+
+#[cfg(feature = "templates")]
+impl TemplateCache {
+    /// Compile and render templates with `engine` instead of Mustache.
+    pub fn with_engine<E: TemplateEngine>(self, engine: E) -> TemplateCache {
+        self.with_any_engine(Box::new(engine))
+    }
+
+    pub(crate) fn with_any_engine(mut self, engine: Box<dyn AnyEngine>) -> TemplateCache {
+        self.engine = engine;
+        self
+    }
+
+    pub(crate) fn head_render(&self) -> &HeadRender {
+        &self.head_render
+    }
+
+    pub(crate) fn cache_key_headers(&self) -> &[HeaderName] {
+        &self.cache_key_headers
     }
 
     /// Remove all cache entries
@@ -67,15 +255,39 @@ impl TemplateCache {
     }
 
     /// Force a reload of a template into the cache
-    pub async fn reload_template<P>(&self, path: P) -> Result<(), Error>
+    pub async fn reload_template<P>(&self, path: P) -> Result<(), TemplateError>
         where P: AsRef<Path> {
 
+        let path = self.resolve(path.as_ref()).await;
         let mut c = self.cache.write().await;
-        let template = TemplateEntry::from_template_file(&path).await?;
-        c.insert(path.as_ref().to_path_buf(), template);
+        let template = TemplateEntry::from_template_file(&*self.engine, &path).await?;
+        c.insert(path, template);
         Ok(())
     }
 
+    // The file `path` refers to. Relative paths are looked up in the roots,
+    // preferring already cached templates to avoid hitting the disk.
+    async fn resolve(&self, path: &Path) -> PathBuf {
+        if self.roots.is_empty() || path.is_absolute() {
+            return path.to_path_buf();
+        }
+
+        let candidates: Vec<PathBuf> = self.roots.iter().map(|root| root.join(path)).collect();
+        {
+            let c = self.cache.read().await;
+            if let Some(cached) = candidates.iter().find(|candidate| c.contains_key(*candidate)) {
+                return cached.clone();
+            }
+        }
+        for candidate in &candidates {
+            if metadata(candidate).await.is_ok() {
+                return candidate.clone();
+            }
+        }
+        // Not found anywhere, loading will report the first candidate
+        candidates.into_iter().next().unwrap_or_else(|| path.to_path_buf())
+    }
+
     // Tries to render the template with the given data. This method
     // only needs a read lock. Returns:
     //
@@ -84,8 +296,8 @@ impl TemplateCache {
     //   * Ok(None) - template needs loading, either it was never
     //                loaded, or it is outdated
     //
-    //   * Err(e) - mustache error
-    async fn try_render_template<P, D>(&self, path: P, data: &D) -> Result<Option<String>, Error>
+    //   * Err(e) - template error
+    async fn try_render_template<P, D>(&self, path: P, data: &D) -> Result<Option<String>, TemplateError>
         where P: AsRef<Path>, D: Serialize {
 
         let c = self.cache.read().await;
@@ -109,7 +321,7 @@ impl TemplateCache {
                     return Ok(None);
                 }
             }
-            let rendered = template.render(data)?;
+            let rendered = template.render(&*self.engine, data)?;
             Ok(Some(rendered))
         } else {
             Ok(None)
@@ -118,21 +330,23 @@ impl TemplateCache {
 
     // Load the template from disk, compile it, store the compiled
     // template in cache, and render. This needs a write lock.
-    async fn load_render_template<P, D>(&self, path: P, data: &D) -> Result<String, Error>
+    async fn load_render_template<P, D>(&self, path: P, data: &D) -> Result<String, TemplateError>
         where P: AsRef<Path>, D: Serialize {
 
         let mut c = self.cache.write().await;
-        let template = TemplateEntry::from_template_file(&path).await?;
-        let rendered = template.render(data)?;
+        let template = TemplateEntry::from_template_file(&*self.engine, &path).await?;
+        let rendered = template.render(&*self.engine, data)?;
         c.insert(path.as_ref().to_path_buf(), template);
         Ok(rendered)
     }
 
     /// Render the template at `path` to `writer` with
     /// `data`. Templates will be reloaded if necessary according to
-    /// the reload policy.
-    pub async fn render<P, D>(&self, path: P, data: &D) -> Result<String, Error>
+    /// the reload policy. Relative paths are resolved against the template
+    /// roots, if any.
+    pub async fn render<P, D>(&self, path: P, data: &D) -> Result<String, TemplateError>
         where P: AsRef<Path>, D: Serialize {
+        let path = self.resolve(path.as_ref()).await;
         let rendered = match self.try_render_template(&path, data).await {
             Ok(r) => r,
             Err(e) => {
@@ -149,3 +363,100 @@ impl TemplateCache {
         }
     }
 }
+
+#[cfg(all(test, feature = "templates"))]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use super::{ReloadPolicy, TemplateCache};
+
+    fn root(name: &str, templates: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("nickel-templates-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        for (file, content) in templates {
+            fs::write(dir.join(file), content).unwrap();
+        }
+        dir
+    }
+
+    #[tokio::test]
+    async fn resolves_templates_in_root_order() {
+        let app = root("app", &[("page.tpl", "app page for {{name}}")]);
+        let shared = root("shared", &[("page.tpl", "shared page"), ("button.tpl", "button for {{name}}")]);
+        let cache = TemplateCache::with_policy(ReloadPolicy::Never)
+                        .with_roots(vec![app.clone(), shared.clone()]);
+        let mut data = HashMap::new();
+        data.insert("name", "Alice");
+
+        // only in the second root
+        assert_eq!(cache.render("button.tpl", &data).await.unwrap(), "button for Alice");
+        // the first root shadows the second
+        assert_eq!(cache.render("page.tpl", &data).await.unwrap(), "app page for Alice");
+        // cached renders resolve the same way
+        assert_eq!(cache.render("page.tpl", &data).await.unwrap(), "app page for Alice");
+        assert!(cache.render("missing.tpl", &data).await.is_err());
+
+        fs::remove_dir_all(app).unwrap();
+        fs::remove_dir_all(shared).unwrap();
+    }
+
+    #[tokio::test]
+    async fn renders_with_the_configured_engine() {
+        use std::io::Write;
+        use mustache::Data;
+        use super::{TemplateEngine, TemplateError};
+
+        // Replaces `$name` with the name in the data
+        struct Dollar;
+
+        impl TemplateEngine for Dollar {
+            type Template = Vec<String>;
+
+            fn compile(&self, source: &str) -> Result<Vec<String>, TemplateError> {
+                Ok(source.split("$name").map(str::to_string).collect())
+            }
+
+            fn render(&self, template: &Vec<String>, writer: &mut dyn Write,
+                      data: &dyn erased_serde::Serialize) -> Result<(), TemplateError> {
+                let name = match mustache::to_data(data)? {
+                    Data::Map(mut map) => match map.remove("name") {
+                        Some(Data::String(name)) => name,
+                        _ => return Err("missing name".into())
+                    },
+                    _ => return Err("data is no map".into())
+                };
+                Ok(writer.write_all(template.join(&name).as_bytes())?)
+            }
+        }
+
+        let dir = root("engine", &[("page.tpl", "$name's page, {{name}}")]);
+        let cache = TemplateCache::with_policy(ReloadPolicy::Never)
+                        .with_roots(vec![dir.clone()])
+                        .with_engine(Dollar);
+        let mut data = HashMap::new();
+        data.insert("name", "Alice");
+        assert_eq!(cache.render("page.tpl", &data).await.unwrap(), "Alice's page, {{name}}");
+        assert!(cache.render("page.tpl", &HashMap::<String, String>::new()).await.is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn escapes_values_unless_raw() {
+        let dir = root("escaping", &[("escaped.tpl", "<div>{{comment}}</div>"),
+                                     ("raw.tpl", "<div>{{{comment}}}</div>")]);
+        let cache = TemplateCache::with_policy(ReloadPolicy::Never).with_roots(vec![dir.clone()]);
+
+        let mut data = HashMap::new();
+        data.insert("comment", "<script>alert('hi')</script>");
+        assert_eq!(cache.render("escaped.tpl", &data).await.unwrap(),
+                   "<div>&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt;</div>");
+
+        let mut data = HashMap::new();
+        data.insert("comment", "<em>trusted</em>");
+        assert_eq!(cache.render("raw.tpl", &data).await.unwrap(), "<div><em>trusted</em></div>");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+use std::marker::PhantomData;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::status::StatusCode;
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::{Middleware, MiddlewareResult};
+
+/// Adds variables to every template rendered in response to a request,
+/// like the application name, the current user or a CSRF token, without
+/// passing them to each `render` call.
+///
+/// `provider` is called for every request and has to return data that
+/// serializes to a map, e.g. a struct or a `HashMap`. Its entries are
+/// merged under the data passed to `render`, so the handler's data wins
+/// when both have the same key. See `Response::add_template_context` for
+/// adding variables from other middleware.
+///
+/// It has to be added before the handlers rendering templates.
+///
+/// # Examples
+/// ```{rust}
+/// #[macro_use] extern crate nickel;
+/// use std::collections::HashMap;
+/// use nickel::{Nickel, HttpRouter, Request, TemplateContext};
+///
+/// fn main() {
+///     let mut server = Nickel::new();
+///     server.utilize(TemplateContext::new(|req: &Request| {
+///         let mut context = HashMap::new();
+///         context.insert("app", "Nickel".to_string());
+///         context.insert("path", req.path_without_query().to_string());
+///         context
+///     }));
+///     server.get("/", middleware! { |_, res|
+///         let mut data = HashMap::new();
+///         data.insert("name", "user");
+///         // the template can use {{app}} and {{path}} as well
+///         return res.render("examples/assets/template.tpl", &data)
+///     });
+/// }
+/// ```
+pub struct TemplateContext<F, T> {
+    provider: F,
+    context: PhantomData<fn() -> T>,
+}
+
+impl<F, T> TemplateContext<F, T> {
+    /// Adds the data returned by `provider` to the template context.
+    pub fn new(provider: F) -> TemplateContext<F, T> {
+        TemplateContext {
+            provider,
+            context: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<D, F, T> Middleware<D> for TemplateContext<F, T>
+where D: Send + 'static + Sync,
+      F: Fn(&Request<D>) -> T + Send + Sync + 'static,
+      T: Serialize + 'static {
+    async fn invoke(&self, req: &mut Request<D>, mut res: Response<D>)
+            -> MiddlewareResult<D> {
+        let context = (self.provider)(req);
+        match res.add_template_context(&context) {
+            Ok(()) => res.next_middleware(),
+            Err(e) => res.error(StatusCode::INTERNAL_SERVER_ERROR, e)
+        }
+    }
+}
+
+// Template variables, kept as JSON values so every `TemplateEngine` gets
+// numbers as numbers. Mustache's own `Data` would turn them into strings.
+pub(crate) type ContextEntries = Map<String, Value>;
+
+// The entries of `data`, which has to serialize to a map.
+pub(crate) fn context_entries<T: Serialize>(data: &T) -> Result<ContextEntries, String> {
+    match serde_json::to_value(data) {
+        Ok(Value::Object(entries)) => Ok(entries),
+        Ok(_) => Err("Template context has to serialize to a map".to_string()),
+        Err(e) => Err(format!("Failed to serialize template context: {}", e))
+    }
+}
+
+// `data` on top of `context`: if `data` is a map, its entries replace
+// those of `context` with the same key. Other data is left as it is.
+pub(crate) fn merge_context<T: Serialize>(mut context: ContextEntries,
+                                          data: &T) -> Result<Value, serde_json::Error> {
+    match serde_json::to_value(data)? {
+        Value::Object(entries) => {
+            context.extend(entries);
+            Ok(Value::Object(context))
+        },
+        data => Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use super::TemplateContext;
+    use crate::{Action, Middleware, Request};
+    use crate::test_helpers::{self, body_string, halted, response};
+
+    #[derive(serde_derive::Serialize)]
+    struct Globals {
+        app: &'static str,
+        title: &'static str,
+    }
+
+    #[tokio::test]
+    async fn provided_variables_are_rendered() {
+        let dir = std::env::temp_dir().join(format!("nickel-template-context-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("page.tpl");
+        fs::write(&template, "{{app}}: {{title}} for {{name}}").unwrap();
+
+        let context = TemplateContext::new(|_: &Request| Globals { app: "Nickel", title: "Default title" });
+        let mut req = test_helpers::get("/");
+        let res = match context.invoke(&mut req, response()).await {
+            Ok(Action::Continue(res)) => res,
+            _ => panic!("expected the request to continue")
+        };
+
+        let mut data = HashMap::new();
+        data.insert("name", "user");
+        data.insert("title", "Profile");
+        let res = halted(res.render(template.to_str().unwrap().to_string(), &data).await);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(body_string(res).await, "Nickel: Profile for user");
+    }
+
+    #[tokio::test]
+    async fn engines_get_numbers_along_with_the_context() {
+        use std::io::Write;
+        use crate::{TemplateEngine, TemplateError};
+        use crate::template_cache::{ReloadPolicy, TemplateCache};
+
+        // Renders `<app>: <count + 1>`, which only works for actual numbers
+        struct Increment;
+
+        impl TemplateEngine for Increment {
+            type Template = ();
+
+            fn compile(&self, _: &str) -> Result<(), TemplateError> {
+                Ok(())
+            }
+
+            fn render(&self, _: &(), writer: &mut dyn Write,
+                      data: &dyn erased_serde::Serialize) -> Result<(), TemplateError> {
+                let data = serde_json::to_value(data)?;
+                let count = data["count"].as_u64().ok_or("count is no number")?;
+                Ok(write!(writer, "{}: {}", data["app"].as_str().unwrap_or(""), count + 1)?)
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("nickel-template-numbers-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("count.tpl");
+        fs::write(&template, "").unwrap();
+
+        let templates = TemplateCache::with_policy(ReloadPolicy::Never).with_engine(Increment);
+        let mut res = test_helpers::response_with_templates(templates);
+        let mut context = HashMap::new();
+        context.insert("app", "Nickel");
+        res.add_template_context(&context).unwrap();
+
+        let mut data = HashMap::new();
+        data.insert("count", 41);
+        let res = halted(res.render(template.to_str().unwrap().to_string(), &data).await);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(body_string(res).await, "Nickel: 42");
+    }
+}
@@ -0,0 +1,99 @@
+//! Builders shared by the unit tests of the different modules.
+use std::sync::{Arc, Mutex, Once};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use hyper::{Body, Request as HyperRequest, Response as HyperResponse};
+use hyper::http::request::Builder;
+use crate::{Action, MiddlewareResult, Request, Response};
+use crate::request::ProxySettings;
+use crate::template_cache::{ReloadPolicy, TemplateCache};
+
+pub fn request(builder: Builder) -> Request<()> {
+    request_with_body(builder, Body::empty())
+}
+
+pub fn request_with_body<B: Into<Body>>(builder: Builder, body: B) -> Request<()> {
+    Request::from_internal(builder.body(body.into()).unwrap(), None, Arc::new(()))
+}
+
+/// A request sent from `remote_addr`, e.g. `"203.0.113.7:5000"`.
+pub fn request_from(builder: Builder, remote_addr: &str) -> Request<()> {
+    let remote_addr = remote_addr.parse().expect("invalid remote address");
+    Request::from_internal(builder.body(Body::empty()).unwrap(), Some(remote_addr), Arc::new(()))
+}
+
+/// A request forwarded by a proxy listed in `Options::trusted_proxies`.
+pub fn from_proxy(builder: Builder) -> Request<()> {
+    let mut req = request_from(builder, "10.0.0.1:40000");
+    req.set_proxy_settings(Arc::new(ProxySettings::new(vec!["10.0.0.1".parse().unwrap()], None)));
+    req
+}
+
+pub fn get(uri: &str) -> Request<()> {
+    request(HyperRequest::get(uri))
+}
+
+pub fn response() -> Response<()> {
+    response_with_templates(TemplateCache::with_policy(ReloadPolicy::Never))
+}
+
+pub fn response_with_templates<T: Into<Arc<TemplateCache>>>(templates: T) -> Response<()> {
+    Response::from_internal(HyperResponse::new(Body::empty()), templates.into(), Arc::new(()))
+}
+
+pub fn halted(result: MiddlewareResult<()>) -> Response<()> {
+    match result {
+        Ok(Action::Halt(res)) => res,
+        Ok(Action::Continue(_)) => panic!("expected the response to halt, but it continued"),
+        Err(err) => panic!("expected the response to halt, but it errored: {}", err.message)
+    }
+}
+
+pub fn errored(result: MiddlewareResult<()>) -> Response<()> {
+    match result {
+        Err(err) => err.stream.expect("error without a response"),
+        Ok(_) => panic!("expected an error")
+    }
+}
+
+pub async fn body_string(res: Response<()>) -> String {
+    let bytes = hyper::body::to_bytes(res.origin.into_body()).await.unwrap();
+    String::from_utf8(bytes.to_vec()).unwrap()
+}
+
+// Records everything logged, for tests asserting on log output.
+struct CapturingLogger(Mutex<Vec<(String, Level, String)>>);
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _: &Metadata) -> bool { true }
+
+    fn log(&self, record: &Record) {
+        let entry = (record.target().to_string(), record.level(), record.args().to_string());
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).push(entry);
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger(Mutex::new(Vec::new()));
+static INSTALL_LOGGER: Once = Once::new();
+
+/// Starts capturing log output, must be called before the code under test
+/// logs anything.
+pub fn capture_logs() {
+    INSTALL_LOGGER.call_once(|| {
+        log::set_logger(&LOGGER).expect("another logger was installed");
+        log::set_max_level(LevelFilter::Trace);
+    });
+}
+
+/// The level and message of everything logged by `target` (a module path
+/// like `nickel::middleware`) since `capture_logs` was called. Tests run
+/// concurrently, so this may include output of other tests.
+pub fn logs_of(target: &str) -> Vec<(Level, String)> {
+    LOGGER.0.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|(t, _, _)| t == target)
+            .map(|(_, level, message)| (*level, message.clone()))
+            .collect()
+}
@@ -0,0 +1,143 @@
+use async_trait::async_trait;
+use hyper::Method;
+
+use crate::extensions::Redirect;
+use crate::request::Request;
+use crate::response::Response;
+use crate::middleware::{Middleware, MiddlewareResult};
+
+/// How `TrailingSlash` canonicalizes request paths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailingSlashPolicy {
+    /// Redirect `/foo` to `/foo/`.
+    Add,
+    /// Redirect `/foo/` to `/foo`.
+    Remove,
+    /// Leave all paths untouched.
+    Leave,
+}
+
+/// Redirects requests for non-canonical paths with a `301 Moved Permanently`,
+/// either adding or removing the trailing slash depending on the policy.
+///
+/// It should be added before the router. The root path is never redirected
+/// and the query string is carried over to the new location. Only `GET` and
+/// `HEAD` requests are redirected, as clients may not repeat the body of
+/// other requests when following the redirect.
+///
+/// # Examples
+/// ```{rust}
+/// use nickel::{Nickel, TrailingSlash, TrailingSlashPolicy};
+///
+/// let mut server = Nickel::new();
+/// server.utilize(TrailingSlash::new(TrailingSlashPolicy::Remove));
+/// ```
+pub struct TrailingSlash {
+    policy: TrailingSlashPolicy,
+}
+
+impl TrailingSlash {
+    pub fn new(policy: TrailingSlashPolicy) -> TrailingSlash {
+        TrailingSlash { policy }
+    }
+
+    // The canonical form of `path`, or `None` if it already is canonical.
+    fn canonical(&self, path: &str) -> Option<String> {
+        if path == "/" {
+            return None;
+        }
+
+        // Browsers read a location starting with `//` or `/\` as another
+        // host, so those are collapsed to a single leading slash.
+        let path = &format!("/{}", path.trim_start_matches(&['/', '\\'][..]));
+        match self.policy {
+            TrailingSlashPolicy::Add if !path.ends_with('/') => Some(format!("{}/", path)),
+            TrailingSlashPolicy::Remove if path.ends_with('/') => {
+                let trimmed = path.trim_end_matches('/');
+                Some(if trimmed.is_empty() { "/".to_string() } else { trimmed.to_string() })
+            },
+            _ => None
+        }
+    }
+}
+
+#[async_trait]
+impl<D: Send + 'static + Sync> Middleware<D> for TrailingSlash {
+    async fn invoke(&self, req: &mut Request<D>, res: Response<D>)
+            -> MiddlewareResult<D> {
+        let method = req.origin.method();
+        if method != Method::GET && method != Method::HEAD {
+            return res.next_middleware();
+        }
+
+        let uri = req.origin.uri();
+        match self.canonical(uri.path()) {
+            Some(path) => {
+                let location = match uri.query() {
+                    Some(query) => format!("{}?{}", path, query),
+                    None => path
+                };
+                res.redirect_permanently(location)
+            },
+            None => res.next_middleware()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyper::{header, Request as HyperRequest, StatusCode};
+    use super::{TrailingSlash, TrailingSlashPolicy};
+    use crate::{Action, Middleware};
+    use crate::test_helpers;
+
+    async fn location(policy: TrailingSlashPolicy, uri: &str) -> Option<String> {
+        let mut req = test_helpers::get(uri);
+        match TrailingSlash::new(policy).invoke(&mut req, test_helpers::response()).await {
+            Ok(Action::Halt(res)) => {
+                assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+                Some(res.headers()[header::LOCATION].to_str().unwrap().to_string())
+            },
+            Ok(Action::Continue(_)) => None,
+            Err(err) => panic!("unexpected error: {}", err.message)
+        }
+    }
+
+    #[tokio::test]
+    async fn add_policy_appends_slash() {
+        let add = TrailingSlashPolicy::Add;
+        assert_eq!(location(add, "/foo").await.as_deref(), Some("/foo/"));
+        assert_eq!(location(add, "/foo?page=2&q=a%20b").await.as_deref(), Some("/foo/?page=2&q=a%20b"));
+        assert_eq!(location(add, "/foo/").await, None);
+        assert_eq!(location(add, "/").await, None);
+    }
+
+    #[tokio::test]
+    async fn remove_policy_strips_slash() {
+        let remove = TrailingSlashPolicy::Remove;
+        assert_eq!(location(remove, "/foo/").await.as_deref(), Some("/foo"));
+        assert_eq!(location(remove, "/foo/bar//?page=2").await.as_deref(), Some("/foo/bar?page=2"));
+        assert_eq!(location(remove, "/foo").await, None);
+        assert_eq!(location(remove, "/?page=2").await, None);
+    }
+
+    #[tokio::test]
+    async fn never_redirects_to_other_hosts() {
+        let add = TrailingSlashPolicy::Add;
+        assert_eq!(location(add, "//evil.example").await.as_deref(), Some("/evil.example/"));
+        assert_eq!(location(add, "///evil.example?a=b").await.as_deref(), Some("/evil.example/?a=b"));
+
+        let remove = TrailingSlashPolicy::Remove;
+        assert_eq!(location(remove, "//evil.example/").await.as_deref(), Some("/evil.example"));
+        assert_eq!(location(remove, "//evil.example//").await.as_deref(), Some("/evil.example"));
+    }
+
+    #[tokio::test]
+    async fn leave_policy_and_other_methods_pass_through() {
+        assert_eq!(location(TrailingSlashPolicy::Leave, "/foo/").await, None);
+
+        let mut req = test_helpers::request(HyperRequest::post("/foo/"));
+        let result = TrailingSlash::new(TrailingSlashPolicy::Remove).invoke(&mut req, test_helpers::response()).await;
+        assert!(matches!(result, Ok(Action::Continue(_))));
+    }
+}
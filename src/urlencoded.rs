@@ -5,7 +5,7 @@ use url::form_urlencoded;
 
 type QueryStore = HashMap<String, Vec<String>>;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Default, PartialEq, Eq)]
 pub struct Params(QueryStore);
 
 // TODO: remove it in favor of Params
@@ -37,6 +37,7 @@ pub fn parse(encoded_string : &str) -> Params {
     Params(form_urlencoded::parse(encoded_string.as_bytes()).into_owned().group())
 }
 
+#[cfg_attr(not(feature = "query"), allow(dead_code))]
 pub fn parse_uri(origin: &Uri) -> Params {
     origin.query().map(|q| parse(&*q)).unwrap_or_else(|| Params(HashMap::new()))
 }
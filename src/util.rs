@@ -0,0 +1,22 @@
+// Small helpers shared by several modules.
+use std::time::SystemTime;
+use chrono::{DateTime, Utc};
+
+// The 64 bit FNV-1a hash of `bytes`. Unlike std's `DefaultHasher` its output
+// is specified, so all instances of a service tag a body the same way, across
+// builds and Rust releases.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3))
+}
+
+// Whether `s` is a `token` as defined by RFC 7230, as required of header
+// field names, media subtypes and cookie names.
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?={}".contains(&b))
+}
+
+// `time` in the IMF-fixdate format of HTTP headers like `Last-Modified`, e.g.
+// `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub(crate) fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
@@ -25,6 +25,7 @@ mod examples {
     mod enable_cors;
     mod form_data;
     mod integration_testing;
+    mod resumable_upload;
 
     #[cfg(feature = "ssl")]
     mod https;
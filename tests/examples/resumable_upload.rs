@@ -0,0 +1,5 @@
+// HACK: Need cargo support to run `#[test]`s witin examples, this imitates it.
+
+#![allow(dead_code)]
+#![allow(unused_attributes)]
+include!("../../examples/resumable_upload.rs");